@@ -0,0 +1,145 @@
+use crate::common::{render, resolve_auth, AuthMethod, OutputFormat, TableView};
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use gcprs::auth::{CLOUD_PLATFORM_SCOPE, USERINFO_EMAIL_SCOPE};
+use gcprs::metadata::MetadataApi;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+
+#[derive(Debug, Args)]
+pub struct AuthArgs {
+    /// Output raw JSON
+    #[clap(short = 'r', long = "raw_json", default_value = "false")]
+    pub raw: bool,
+
+    /// Authenticate with user application. otherwise authenticate with service account
+    #[clap(short = 'a', long = "auth_user", default_value = "true")]
+    pub auth_user: bool,
+
+    #[clap(subcommand)]
+    pub auth_sub_command: AuthSubCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AuthSubCommand {
+    /// Show the active identity, project and scopes
+    Whoami,
+}
+
+/// Active identity
+#[derive(Debug, Serialize)]
+struct Identity {
+    method: String,
+    email: String,
+    project: String,
+    scopes: Vec<String>,
+}
+
+impl TableView for Identity {
+    fn columns(&self) -> Vec<String> {
+        vec![
+            "method".to_string(),
+            "email".to_string(),
+            "project".to_string(),
+            "scopes".to_string(),
+        ]
+    }
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.method.clone(),
+            self.email.clone(),
+            self.project.clone(),
+            self.scopes.join(","),
+        ]
+    }
+}
+
+/// Fields of the service account key file
+#[derive(Debug, Default, Deserialize)]
+struct ServiceAccountKey {
+    client_email: Option<String>,
+    project_id: Option<String>,
+}
+
+/// Read the key file set to `GOOGLE_APPLICATION_CREDENTIALS`.
+fn read_key_file() -> Option<ServiceAccountKey> {
+    let path = env::var("GOOGLE_APPLICATION_CREDENTIALS").ok()?;
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+async fn whoami(auth_user: bool) -> Result<Identity> {
+    let method = AuthMethod::resolve(auth_user)?;
+    let gcpauth = resolve_auth(auth_user).await?;
+    let info = match method {
+        // the user token has no email without the email scope
+        AuthMethod::User => {
+            gcpauth
+                .token_info_with_scopes(&[CLOUD_PLATFORM_SCOPE, USERINFO_EMAIL_SCOPE])
+                .await?
+        }
+        AuthMethod::ServiceAccount => gcpauth.token_info().await?,
+    };
+    let mut scopes: Vec<String> = info
+        .scope
+        .as_ref()
+        .map(|s| s.split_whitespace().map(|s| s.to_string()).collect())
+        .unwrap_or_default();
+
+    let (method_name, email, project) = match method {
+        AuthMethod::User => ("user", info.email, env::var("PROJECT_ID").ok()),
+        AuthMethod::ServiceAccount => {
+            let key = read_key_file();
+            let metadata = MetadataApi::new();
+            // the key file is used first. unless the credential comes from the metadata server.
+            let (key_email, key_project) = key
+                .map(|k| (k.client_email, k.project_id))
+                .unwrap_or_default();
+            let email = match info.email.or(key_email) {
+                Some(email) => Some(email),
+                None => match metadata.service_account_info().await {
+                    Ok(sa) => {
+                        if scopes.is_empty() {
+                            scopes = sa.scopes;
+                        }
+                        Some(sa.email)
+                    }
+                    Err(_) => None,
+                },
+            };
+            let project = match env::var("PROJECT_ID").ok().or(key_project) {
+                Some(project) => Some(project),
+                None => metadata.project_id().await.ok(),
+            };
+            ("sa", email, project)
+        }
+    };
+    let email = email.ok_or_else(|| {
+        anyhow::anyhow!(
+            "could not determine the email of the {} identity",
+            method_name
+        )
+    })?;
+    Ok(Identity {
+        method: method_name.to_string(),
+        email,
+        project: project.unwrap_or_default(),
+        scopes,
+    })
+}
+
+pub async fn handle(aargs: AuthArgs) -> Result<()> {
+    let format = if aargs.raw {
+        OutputFormat::Json
+    } else {
+        OutputFormat::Stdout
+    };
+    match aargs.auth_sub_command {
+        AuthSubCommand::Whoami => {
+            let identity = whoami(aargs.auth_user).await?;
+            render(&vec![identity], format, false)
+        }
+    }
+}
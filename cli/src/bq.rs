@@ -1,11 +1,18 @@
-use crate::common::{render as render2, OutputFormat, TableView};
+use crate::common::{
+    render as render2, render_stream, resolve_auth_legacy, select_columns, OutputFormat, TableView,
+};
 use anyhow::Result;
-use bigquery::{Bq, BqDataset, BqListParam, BqProject, BqQueryParam, BqRow, BqTable, QueryResult};
-use clap::{Args, Subcommand};
-use gcprs::auth_legacy as auth;
+use bigquery::{
+    Bq, BqDataset, BqFlatten, BqListParam, BqProject, BqQueryParam, BqRow, BqTable, QueryResult,
+};
+use clap::{Args, Subcommand, ValueEnum};
+use datafusion::parquet::arrow::ArrowWriter;
+use futures::stream::{self, StreamExt, TryStreamExt};
 use gcprs::bigquery;
+use gcprs::metadata::MetadataApi;
 use json_to_table::{json_to_table, Orientation};
 use std::env;
+use std::io;
 use tabled::settings::Style;
 
 #[derive(Debug, Args)]
@@ -22,11 +29,27 @@ pub struct BqArgs {
     #[clap(short = 'j', long = "json", default_value = "false")]
     pub json: bool,
 
+    /// Output Parquet file. All columns are written as string except `query`, which keeps
+    /// INTEGER, FLOAT and BOOLEAN column types.
+    #[clap(long = "parquet")]
+    pub parquet: Option<String>,
+
+    /// Flatten nested columns in CSV output.
+    /// RECORD fields become dotted column names and REPEATED values are exploded into rows or joined.
+    /// This must be used with csv output otherwise ignored.
+    #[clap(long = "flatten", value_enum)]
+    pub flatten: Option<FlattenMode>,
+
     /// Output New line delimited JSON
     /// This must be used with json output otherwise ignored.
     #[clap(short = 'n', long = "new_line", default_value = "false")]
     pub new_line: bool,
 
+    /// Comma separated column names to output. The columns are output in this order.
+    /// e.g. `c1,c2,c3`
+    #[clap(long = "columns")]
+    pub columns: Option<String>,
+
     /// Authenticate with user application. otherwise authenticate with service account
     #[clap(short = 'a', long = "auth_user", default_value = "true")]
     pub auth_user: bool,
@@ -35,6 +58,23 @@ pub struct BqArgs {
     pub bq_sub_command: BqSubCommand,
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum FlattenMode {
+    /// Each element of REPEATED column becomes a separate row
+    Explode,
+    /// Elements of REPEATED column are joined with `;`
+    Join,
+}
+
+impl FlattenMode {
+    fn to_bq_flatten(&self) -> BqFlatten {
+        match self {
+            FlattenMode::Explode => BqFlatten::Explode,
+            FlattenMode::Join => BqFlatten::Join(String::from(";")),
+        }
+    }
+}
+
 #[derive(Debug, Subcommand)]
 pub enum BqSubCommand {
     /// Show available Project list
@@ -49,6 +89,8 @@ pub enum BqSubCommand {
     TableDelete(TableDeleteArgs),
     /// Show Table Data
     ListTableData(ListTableDataArgs),
+    /// Show the first rows of Table without query cost
+    Head(HeadArgs),
     /// Show Query result
     Query(QueryArgs),
 }
@@ -58,6 +100,30 @@ pub struct ListTableDataArgs {
     #[clap(short = 'm', long = "max_results", default_value = "1000")]
     max_results: u32,
 
+    /// Maximum number of rows fetched across pages
+    #[clap(short = 'l', long = "limit")]
+    limit: Option<usize>,
+
+    /// Dataset ID
+    #[clap(short = 'd', long = "dataset")]
+    dataset: String,
+
+    /// Table ID
+    #[clap(short = 't', long = "table")]
+    table: String,
+
+    /// Filter rows with the predicate. e.g. `age > 20`.
+    /// Listing table data is free but the filter is executed as a query and billed.
+    #[clap(short = 'w', long = "where")]
+    predicate: Option<String>,
+}
+
+#[derive(Default, Debug, Args)]
+pub struct HeadArgs {
+    /// Number of rows to show
+    #[clap(short = 'n', long = "rows", default_value = "10")]
+    rows: u32,
+
     /// Dataset ID
     #[clap(short = 'd', long = "dataset")]
     dataset: String,
@@ -94,6 +160,10 @@ pub struct ListTablesArgs {
     /// Dataset ID
     #[clap(short = 'd', long = "dataset")]
     dataset: String,
+
+    /// Fetch row count and size of each table. This calls the API for every table.
+    #[clap(short = 's', long = "size")]
+    size: bool,
 }
 
 #[derive(Default, Debug, Args)]
@@ -102,10 +172,18 @@ pub struct QueryArgs {
     #[clap(short = 'm', long = "max_results", default_value = "1000")]
     max_results: u32,
 
+    /// Maximum number of rows fetched across pages
+    #[clap(short = 'l', long = "limit")]
+    limit: Option<usize>,
+
     /// Dry run execution.
     #[clap(short = 'd', long = "dry_run")]
     dry_run: bool,
 
+    /// Fail the query if it would bill more bytes than this limit.
+    #[clap(short = 'b', long = "maximum_bytes_billed")]
+    maximum_bytes_billed: Option<i64>,
+
     /// Query String
     #[clap(short = 'q', long = "query")]
     query: String,
@@ -147,6 +225,10 @@ impl TableView for BqTable {
             "table".to_owned(),
             "created_at".to_owned(),
             "expired_at".to_owned(),
+            "labels".to_owned(),
+            "description".to_owned(),
+            "num_rows".to_owned(),
+            "size_bytes".to_owned(),
         ]
     }
 
@@ -161,6 +243,22 @@ impl TableView for BqTable {
             self.expired_at
                 .map(|t| format!("{}", t))
                 .unwrap_or("".to_string()),
+            self.labels
+                .as_ref()
+                .map(|ls| {
+                    let mut labels: Vec<String> =
+                        ls.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                    labels.sort();
+                    labels.join(",")
+                })
+                .unwrap_or("".to_string()),
+            self.description.clone().unwrap_or("".to_string()),
+            self.num_rows
+                .map(|n| format!("{}", n))
+                .unwrap_or("".to_string()),
+            self.size_bytes
+                .map(|n| format!("{}", n))
+                .unwrap_or("".to_string()),
         ]
     }
 }
@@ -181,6 +279,93 @@ impl TableView for BqRow {
     }
 }
 
+fn output_format(bqargs: &BqArgs) -> OutputFormat {
+    if bqargs.json {
+        OutputFormat::Json
+    } else if bqargs.csv {
+        OutputFormat::Csv
+    } else if let Some(parquet) = bqargs.parquet.as_ref() {
+        OutputFormat::Parquet(parquet.clone())
+    } else {
+        OutputFormat::Stdout
+    }
+}
+
+fn render_rows(data: &Vec<BqRow>, bqargs: &BqArgs) -> Result<()> {
+    if let Some(columns) = bqargs.columns.as_ref() {
+        anyhow::ensure!(
+            bqargs.flatten.is_none(),
+            "--columns can not be used with --flatten"
+        );
+        let selected = select_columns(data, columns)?;
+        return render2(&selected, output_format(bqargs), bqargs.new_line);
+    }
+    if bqargs.csv {
+        if let Some(mode) = bqargs.flatten {
+            return BqRow::write_flattened(data, io::stdout(), &mode.to_bq_flatten(), b',');
+        }
+    }
+    render2(data, output_format(bqargs), bqargs.new_line)
+}
+
+/// Write the query result to Parquet file with the column types.
+fn write_parquet(data: &QueryResult, filename: &str) -> Result<()> {
+    let batches = data.to_record_batches()?;
+    let first = batches
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("there is no data to write"))?;
+    let file = std::fs::File::create(filename)?;
+    let mut writer = ArrowWriter::try_new(file, first.schema(), None)?;
+    for batch in batches.iter() {
+        writer.write(batch)?;
+    }
+    writer.close()?;
+    Ok(())
+}
+
+/// On-demand query pricing(USD per TiB)
+const ON_DEMAND_PRICE_PER_TIB: f64 = 6.25;
+
+/// Number of concurrent tables.get requests for `list_tables --size`
+const TABLE_FETCH_CONCURRENCY: usize = 8;
+
+fn human_bytes(bytes: i64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while 1024.0 <= size && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, units[unit])
+}
+
+fn render_dry_run(
+    schemas: &Vec<bigquery::BqTableSchema>,
+    total_bytes_processed: Option<i64>,
+    raw_json: bool,
+) -> Result<()> {
+    let bytes = total_bytes_processed.unwrap_or(0);
+    let cost = bytes as f64 / 1024_f64.powi(4) * ON_DEMAND_PRICE_PER_TIB;
+    if raw_json {
+        let json = serde_json::json!({
+            "schemas": schemas,
+            "total_bytes_processed": bytes,
+            "estimated_cost_usd": cost,
+        });
+        println!("{}", json);
+        Ok(())
+    } else {
+        render_json(serde_json::to_string(schemas)?, false)?;
+        println!(
+            "This query will process {} (~${:.3})",
+            human_bytes(bytes),
+            cost
+        );
+        Ok(())
+    }
+}
+
 fn render_json(json_str: String, raw_json: bool) -> Result<()> {
     if raw_json {
         println!("{}", json_str)
@@ -203,18 +388,18 @@ pub async fn handle(bqargs: BqArgs) -> Result<()> {
     } else {
         match env::var("PROJECT_ID") {
             Ok(project) => project,
-            Err(err) => {
-                anyhow::bail!("{}: PROJECT_ID is necessary", err)
-            }
+            Err(err) => match MetadataApi::new().project_id().await {
+                // running on GCP
+                Ok(project) => project,
+                Err(_) => {
+                    anyhow::bail!("{}: PROJECT_ID is necessary", err)
+                }
+            },
         }
     };
 
-    let spauth = if bqargs.auth_user {
-        auth::GcpAuth::from_user_auth().await.unwrap()
-    } else {
-        auth::GcpAuth::from_service_account().await.unwrap()
-    };
-    match bqargs.bq_sub_command {
+    let spauth = resolve_auth_legacy(bqargs.auth_user).await?;
+    match &bqargs.bq_sub_command {
         BqSubCommand::ListProject => {
             let data = Bq::list_project(spauth).await?;
             render2(
@@ -244,7 +429,14 @@ pub async fn handle(bqargs: BqArgs) -> Result<()> {
         BqSubCommand::ListTables(args) => {
             let bigquery = Bq::new(&spauth, &project).unwrap();
             let list_params = BqListParam::new();
-            let data = bigquery.list_tables(&args.dataset, &list_params).await?;
+            let mut data = bigquery.list_tables(&args.dataset, &list_params).await?;
+            if args.size {
+                data = stream::iter(data.iter())
+                    .map(|t| bigquery.get_table(&args.dataset, &t.table_id))
+                    .buffered(TABLE_FETCH_CONCURRENCY)
+                    .try_collect()
+                    .await?;
+            }
             render2(
                 &data,
                 if bqargs.json {
@@ -259,50 +451,86 @@ pub async fn handle(bqargs: BqArgs) -> Result<()> {
             let bigquery = Bq::new(&spauth, &project).unwrap();
             let mut list_params = BqListParam::new();
             list_params.max_results(args.max_results);
+            if let Some(limit) = args.limit {
+                list_params.num_result_limit(limit);
+            }
+            if let Some(predicate) = args.predicate.as_ref() {
+                // tabledata.list can not filter rows, so run a query instead.
+                let sql = format!(
+                    "SELECT * FROM `{}.{}.{}` WHERE {}",
+                    project, args.dataset, args.table, predicate
+                );
+                let mut query_params = BqQueryParam::new(&sql);
+                query_params.max_results(args.max_results);
+                if let Some(limit) = args.limit {
+                    query_params.num_result_limit(limit);
+                }
+                return match bigquery.query(&query_params).await? {
+                    QueryResult::Data { rows, .. } => render_rows(&rows, &bqargs),
+                    QueryResult::Schema { .. } => anyhow::bail!("query returned no data"),
+                };
+            }
+            let table = BqTable::new(&project, &args.dataset, &args.table);
+            if (bqargs.csv && bqargs.flatten.is_some()) || bqargs.columns.is_some() {
+                let data = bigquery.list_tabledata(&table, &list_params).await?;
+                return render_rows(&data, &bqargs);
+            }
+            let rows = bigquery.list_tabledata_stream(&table, &list_params);
+            render_stream(rows, output_format(&bqargs), bqargs.new_line).await
+        }
+        BqSubCommand::Head(args) => {
+            let bigquery = Bq::new(&spauth, &project).unwrap();
+            // tabledata.list reads the table storage directly so it is free of charge.
+            let mut list_params = BqListParam::new();
+            list_params.max_results(args.rows);
+            list_params.num_result_limit(args.rows as usize);
             let table = BqTable::new(&project, &args.dataset, &args.table);
             let data = bigquery.list_tabledata(&table, &list_params).await?;
-
-            render2(
-                &data,
-                if bqargs.json {
-                    OutputFormat::Json
-                } else if bqargs.csv {
-                    OutputFormat::Csv
-                } else {
-                    OutputFormat::Stdout
-                },
-                bqargs.new_line,
-            )
+            render_rows(&data, &bqargs)
         }
         BqSubCommand::Query(args) => {
             let bigquery = Bq::new(&spauth, &project).unwrap();
             let mut query_params = BqQueryParam::new(&args.query);
             query_params.max_results(args.max_results);
+            if let Some(limit) = args.limit {
+                query_params.num_result_limit(limit);
+            }
             query_params.dry_run(args.dry_run);
+            if let Some(bytes) = args.maximum_bytes_billed {
+                query_params.maximum_bytes_billed(bytes);
+            }
             let data = bigquery.query(&query_params).await?;
+            if let Some(parquet) = bqargs.parquet.as_ref() {
+                if bqargs.columns.is_none() && matches!(data, QueryResult::Data { .. }) {
+                    return write_parquet(&data, parquet);
+                }
+            }
 
             match data {
-                QueryResult::Data(ds) => render2(
-                    &ds,
-                    if bqargs.json {
-                        OutputFormat::Json
-                    } else if bqargs.csv {
-                        OutputFormat::Csv
-                    } else {
-                        OutputFormat::Stdout
-                    },
-                    bqargs.new_line,
-                ),
-                QueryResult::Schema(schemas) => {
-                    let json_str = serde_json::to_string(&schemas)?;
-                    render_json(json_str, bqargs.json)
+                QueryResult::Data {
+                    rows, total_rows, ..
+                } => {
+                    render_rows(&rows, &bqargs)?;
+                    match total_rows {
+                        Some(total) if (rows.len() as u64) < total => {
+                            eprintln!("showing {} of {} rows", rows.len(), total)
+                        }
+                        _ => {}
+                    }
+                    Ok(())
                 }
+                QueryResult::Schema {
+                    schemas,
+                    total_bytes_processed,
+                } => render_dry_run(&schemas, total_bytes_processed, bqargs.json),
             }
         }
         BqSubCommand::TableSchema(args) => {
             let bigquery = Bq::new(&spauth, &project).unwrap();
-            let data = bigquery.get_table(&args.dataset, &args.table).await?;
-            let json_str = serde_json::to_string(&data.schemas)?;
+            let schemas = bigquery
+                .get_table_schema(&args.dataset, &args.table)
+                .await?;
+            let json_str = serde_json::to_string(&schemas)?;
             render_json(json_str, bqargs.json)
         }
         BqSubCommand::TableDelete(args) => {
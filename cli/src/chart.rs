@@ -1,13 +1,14 @@
-use crate::df::{register_source, session_context};
+use crate::df::{register_source, run_query, session_context};
 use crate::ml::common::array_value;
 use anyhow::Result;
 use clap::{Args, Subcommand};
 use datafusion::arrow::util::display::array_value_to_string;
 use ndarray::*;
+use datafusion::prelude::SessionContext;
 use plotly::{
-    common::Visible,
-    layout::{Center, DragMode, Layout, Mapbox, MapboxStyle, Margin},
-    Plot, ScatterMapbox,
+    common::{Mode, Visible},
+    layout::{Axis, Center, DragMode, Layout, Mapbox, MapboxStyle, Margin},
+    Bar, Histogram, Plot, Scatter, ScatterMapbox,
 };
 use std::collections::HashMap;
 use std::ffi::OsStr;
@@ -25,6 +26,17 @@ pub struct ChartArgs {
     #[clap(short = 'i', long = "inputs")]
     pub inputs: Vec<String>,
 
+    /// Authenticate with user application to read `bq://` inputs. otherwise authenticate with
+    /// service account
+    #[clap(short = 'a', long = "auth_user", default_value = "true")]
+    pub auth_user: bool,
+
+    /// Maximum number of rows read from each `bq://` input.
+    ///
+    /// The whole table is loaded into memory unless this is set.
+    #[clap(long = "bq_limit")]
+    pub bq_limit: Option<usize>,
+
     /// Output file
     ///
     /// The result is always shown in stdout. This option write the result to the file.
@@ -40,6 +52,47 @@ pub enum ChartSubCommand {
     /// If you set label on each data, use `--data_label` option.
     /// If you create multiple series of data on a same map, use `--regend_label` option.
     ScatterMapbox(ScatterMapboxArgs),
+
+    /// Create Line chart
+    ///
+    /// Data is sorted by `--x` column. If you create multiple lines, use `--series` option.
+    Line(XYArgs),
+
+    /// Create Bar chart
+    ///
+    /// If you create multiple series of bars, use `--series` option.
+    Bar(XYArgs),
+
+    /// Create Histogram of a numeric column
+    ///
+    /// Null values are skipped.
+    Histogram(HistogramArgs),
+}
+
+#[derive(Default, Debug, Args)]
+pub struct HistogramArgs {
+    /// target column name. Must be numeric type.
+    #[clap(short = 'c', long = "column")]
+    column: String,
+
+    /// number of bins. If not set, it is decided automatically.
+    #[clap(short = 'b', long = "bins")]
+    bins: Option<usize>,
+}
+
+#[derive(Default, Debug, Args)]
+pub struct XYArgs {
+    /// x axis column name
+    #[clap(short = 'x', long = "x")]
+    x: String,
+
+    /// y axis column name. Must be numeric type.
+    #[clap(short = 'y', long = "y")]
+    y: String,
+
+    /// series column name
+    #[clap(short = 's', long = "series")]
+    series: Option<String>,
 }
 
 #[derive(Default, Debug, Args)]
@@ -115,10 +168,72 @@ impl ScatterMapData {
     }
 }
 
+struct XYData {
+    x: Vec<String>,
+    y: Vec<f64>,
+    series: String,
+}
+
+impl XYData {
+    fn new(series: String) -> Self {
+        XYData {
+            x: Vec::new(),
+            y: Vec::new(),
+            series,
+        }
+    }
+}
+
+/// Query x and y values from t0 and split them into series.
+async fn xy_series(ctx: &SessionContext, args: &XYArgs) -> Result<Vec<XYData>> {
+    let mut query_target = vec![args.x.clone(), args.y.clone()];
+    if let Some(series) = args.series.as_ref() {
+        query_target.push(series.clone());
+    }
+    let sql = format!("select {} from t0 order by {}", query_target.join(","), args.x);
+    println!("sql: {}", sql);
+
+    let batches = run_query(ctx, &sql).await?;
+    let mut series_list: Vec<XYData> = Vec::new();
+    let mut series_index: HashMap<String, usize> = HashMap::new();
+    for batch in batches.iter() {
+        let series_column = args.series.as_ref().and_then(|s| batch.column_by_name(s));
+        if let (Some(x_column), Some(y_column)) =
+            (batch.column_by_name(&args.x), batch.column_by_name(&args.y))
+        {
+            for row in 0..batch.num_rows() {
+                // null is skipped
+                if x_column.is_null(row) || y_column.is_null(row) {
+                    continue;
+                }
+                let s = series_column
+                    .map(|c| array_value_to_string(c, row))
+                    .transpose()?
+                    .unwrap_or(String::from(""));
+                let idx = *series_index.entry(s.clone()).or_insert_with(|| {
+                    series_list.push(XYData::new(s));
+                    series_list.len() - 1
+                });
+                let d = &mut series_list[idx];
+                d.x.push(array_value_to_string(x_column, row)?);
+                d.y.push(array_value(y_column, row)?);
+            }
+        }
+    }
+    Ok(series_list)
+}
+
+fn xy_layout(args: &XYArgs) -> Layout {
+    Layout::new()
+        .auto_size(true)
+        .x_axis(Axis::new().title(args.x.as_str().into()))
+        .y_axis(Axis::new().title(args.y.as_str().into()))
+}
+
 pub async fn handle(cargs: ChartArgs) -> Result<()> {
     let ctx = session_context();
 
-    register_source(&ctx, cargs.inputs).await?;
+    register_source(&ctx, cargs.inputs, cargs.auth_user, cargs.bq_limit).await?;
 
     match cargs.chart_sub_command {
         ChartSubCommand::ScatterMapbox(args) => {
@@ -227,6 +342,65 @@ pub async fn handle(cargs: ChartArgs) -> Result<()> {
 
             write_file(plot, cargs.output)?;
 
+            Ok(())
+        }
+        ChartSubCommand::Line(args) => {
+            let mut plot = Plot::new();
+            for v in xy_series(&ctx, &args).await? {
+                let trace = Scatter::new(v.x, v.y)
+                    .mode(Mode::LinesMarkers)
+                    .name(v.series);
+                plot.add_trace(trace);
+            }
+            plot.set_layout(xy_layout(&args));
+
+            write_file(plot, cargs.output)?;
+
+            Ok(())
+        }
+        ChartSubCommand::Bar(args) => {
+            let mut plot = Plot::new();
+            for v in xy_series(&ctx, &args).await? {
+                let trace = Bar::new(v.x, v.y).name(v.series);
+                plot.add_trace(trace);
+            }
+            plot.set_layout(xy_layout(&args));
+
+            write_file(plot, cargs.output)?;
+
+            Ok(())
+        }
+        ChartSubCommand::Histogram(args) => {
+            let sql = format!("select {} from t0", args.column);
+            println!("sql: {}", sql);
+
+            let batches = run_query(&ctx, &sql).await?;
+            let mut values: Vec<f64> = Vec::new();
+            for batch in batches.iter() {
+                if let Some(column) = batch.column_by_name(&args.column) {
+                    for row in 0..batch.num_rows() {
+                        if column.is_null(row) {
+                            continue;
+                        }
+                        values.push(array_value(column, row)?);
+                    }
+                }
+            }
+
+            let mut trace = Histogram::new(values).name(args.column.clone());
+            if let Some(bins) = args.bins {
+                trace = trace.n_bins_x(bins);
+            }
+            let mut plot = Plot::new();
+            plot.add_trace(trace);
+            plot.set_layout(
+                Layout::new()
+                    .auto_size(true)
+                    .x_axis(Axis::new().title(args.column.as_str().into())),
+            );
+
+            write_file(plot, cargs.output)?;
+
             Ok(())
         }
     }
@@ -0,0 +1,296 @@
+use anyhow::Result;
+use datafusion::arrow::array::{ArrayRef, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::parquet::arrow::ArrowWriter;
+use futures::{pin_mut, Stream, TryStreamExt};
+use gcprs::{auth, auth_legacy};
+use serde::ser::{SerializeMap, Serializer};
+use serde::Serialize;
+use std::env;
+use std::io::{self, Write};
+use std::sync::Arc;
+use tabled::{builder::Builder, settings::Style};
+
+pub enum OutputFormat {
+    /// Markdown table
+    Stdout,
+    /// JSON
+    Json,
+    /// CSV with header
+    Csv,
+    /// Parquet file. All columns are written as string.
+    Parquet(String),
+}
+
+/// Environment variable to choose the authentication method. It overrides `--auth_user`.
+const AUTH_ENV: &str = "GCPRS_AUTH";
+
+/// Authentication method
+pub enum AuthMethod {
+    /// OAuth2 user authentication
+    User,
+    /// Service account or application default credentials
+    ServiceAccount,
+}
+
+impl AuthMethod {
+    pub fn resolve(auth_user: bool) -> Result<Self> {
+        match env::var(AUTH_ENV) {
+            Ok(v) => match v.as_str() {
+                "user" => Ok(AuthMethod::User),
+                "sa" | "adc" => Ok(AuthMethod::ServiceAccount),
+                _ => anyhow::bail!("{}={} is invalid. use one of user, sa or adc", AUTH_ENV, v),
+            },
+            Err(_) if auth_user => Ok(AuthMethod::User),
+            Err(_) => Ok(AuthMethod::ServiceAccount),
+        }
+    }
+
+    fn error(&self, e: anyhow::Error) -> anyhow::Error {
+        match self {
+            AuthMethod::User => anyhow::anyhow!(
+                "user authentication failed: {}. set the client secret JSON path to GOOGLE_APPLICATION_SECRET",
+                e
+            ),
+            AuthMethod::ServiceAccount => anyhow::anyhow!(
+                "service account authentication failed: {}. set the key JSON path to GOOGLE_APPLICATION_CREDENTIALS or run on GCP",
+                e
+            ),
+        }
+    }
+}
+
+/// Authenticate with the method chosen by `GCPRS_AUTH`(`user`, `sa` or `adc`) or `--auth_user`.
+pub async fn resolve_auth(auth_user: bool) -> Result<auth::GcpAuth> {
+    let method = AuthMethod::resolve(auth_user)?;
+    let res = match method {
+        AuthMethod::User => auth::GcpAuth::from_user_auth().await,
+        AuthMethod::ServiceAccount => auth::GcpAuth::from_service_account().await,
+    };
+    res.map_err(|e| method.error(e))
+}
+
+/// Same as `resolve_auth` for the APIs using the legacy client.
+pub async fn resolve_auth_legacy(auth_user: bool) -> Result<auth_legacy::GcpAuth> {
+    let method = AuthMethod::resolve(auth_user)?;
+    let res = match method {
+        AuthMethod::User => auth_legacy::GcpAuth::from_user_auth().await,
+        AuthMethod::ServiceAccount => auth_legacy::GcpAuth::from_service_account().await,
+    };
+    res.map_err(|e| method.error(e))
+}
+
+/// Tabular representation of the API result
+pub trait TableView {
+    fn columns(&self) -> Vec<String>;
+    fn values(&self) -> Vec<String>;
+}
+
+/// Parse comma separated column names and return the indices in `available`.
+///
+/// The order of the names is kept. Unknown names are errors with the available columns.
+pub fn parse_columns(selected: &str, available: &[String]) -> Result<Vec<usize>> {
+    selected
+        .split(',')
+        .map(|c| c.trim())
+        .filter(|c| !c.is_empty())
+        .map(|c| {
+            available.iter().position(|a| a == c).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "unknown column: {}. available columns are {}",
+                    c,
+                    available.join(",")
+                )
+            })
+        })
+        .collect()
+}
+
+/// Selected columns of `TableView`
+pub struct SelectedView {
+    columns: Vec<String>,
+    values: Vec<String>,
+}
+
+impl TableView for SelectedView {
+    fn columns(&self) -> Vec<String> {
+        self.columns.clone()
+    }
+
+    fn values(&self) -> Vec<String> {
+        self.values.clone()
+    }
+}
+
+/// Serialized as a JSON object with the selected columns in order. Values are strings.
+impl Serialize for SelectedView {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.columns.len()))?;
+        for (c, v) in self.columns.iter().zip(self.values.iter()) {
+            map.serialize_entry(c, v)?;
+        }
+        map.end()
+    }
+}
+
+/// Filter and reorder the columns of the data.
+///
+/// # Arguments
+///
+/// * `data` - data to be selected
+/// * `selected` - comma separated column names. e.g. `c1,c2,c3`
+pub fn select_columns<T: TableView>(data: &[T], selected: &str) -> Result<Vec<SelectedView>> {
+    let first = match data.first() {
+        Some(first) => first,
+        None => return Ok(vec![]),
+    };
+    let available = first.columns();
+    let indices = parse_columns(selected, &available)?;
+    let columns: Vec<String> = indices.iter().map(|i| available[*i].clone()).collect();
+    Ok(data
+        .iter()
+        .map(|d| {
+            let values = d.values();
+            SelectedView {
+                columns: columns.clone(),
+                values: indices.iter().map(|i| values[*i].clone()).collect(),
+            }
+        })
+        .collect())
+}
+
+/// Render the data to stdout(or file for Parquet).
+///
+/// # Arguments
+///
+/// * `data` - data to be rendered
+/// * `format` - output format
+/// * `new_line` - output new line delimited JSON. This is used with `OutputFormat::Json`.
+pub fn render<T: TableView + Serialize>(
+    data: &Vec<T>,
+    format: OutputFormat,
+    new_line: bool,
+) -> Result<()> {
+    match format {
+        OutputFormat::Stdout => {
+            let mut builder = Builder::default();
+            if let Some(first) = data.first() {
+                builder.set_header(first.columns());
+            }
+            for d in data {
+                builder.push_record(d.values());
+            }
+            let mut table = builder.build();
+            table.with(Style::markdown());
+            println!("{}", table);
+        }
+        OutputFormat::Json => {
+            if new_line {
+                for d in data {
+                    println!("{}", serde_json::to_string(d)?);
+                }
+            } else {
+                println!("{}", serde_json::to_string(data)?);
+            }
+        }
+        OutputFormat::Csv => {
+            let mut wtr = csv::Writer::from_writer(io::stdout());
+            if let Some(first) = data.first() {
+                wtr.write_record(first.columns())?;
+            }
+            for d in data {
+                wtr.write_record(d.values())?;
+            }
+            wtr.flush()?;
+        }
+        OutputFormat::Parquet(filename) => write_parquet(data, &filename)?,
+    }
+    Ok(())
+}
+
+/// Render the data stream incrementally.
+///
+/// JSON and CSV records are written and flushed as they arrive. Markdown table and Parquet need
+/// all data so they are collected before rendering.
+///
+/// # Arguments
+///
+/// * `data` - stream of data to be rendered
+/// * `format` - output format
+/// * `new_line` - output new line delimited JSON. This is used with `OutputFormat::Json`.
+pub async fn render_stream<T, S>(data: S, format: OutputFormat, new_line: bool) -> Result<()>
+where
+    T: TableView + Serialize,
+    S: Stream<Item = Result<T>>,
+{
+    pin_mut!(data);
+    match format {
+        OutputFormat::Json => {
+            let mut out = io::stdout();
+            let mut first = true;
+            if !new_line {
+                write!(out, "[")?;
+            }
+            while let Some(d) = data.try_next().await? {
+                if new_line {
+                    writeln!(out, "{}", serde_json::to_string(&d)?)?;
+                } else {
+                    if !first {
+                        write!(out, ",")?;
+                    }
+                    write!(out, "{}", serde_json::to_string(&d)?)?;
+                }
+                out.flush()?;
+                first = false;
+            }
+            if !new_line {
+                writeln!(out, "]")?;
+            }
+        }
+        OutputFormat::Csv => {
+            let mut wtr = csv::Writer::from_writer(io::stdout());
+            let mut first = true;
+            while let Some(d) = data.try_next().await? {
+                if first {
+                    wtr.write_record(d.columns())?;
+                    first = false;
+                }
+                wtr.write_record(d.values())?;
+                wtr.flush()?;
+            }
+        }
+        format => {
+            let collected: Vec<T> = data.try_collect().await?;
+            render(&collected, format, new_line)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_parquet<T: TableView>(data: &Vec<T>, filename: &str) -> Result<()> {
+    anyhow::ensure!(0 < data.len(), "there is no data to write");
+
+    let columns = data[0].columns();
+    let schema = Arc::new(Schema::new(
+        columns
+            .iter()
+            .map(|c| Field::new(c, DataType::Utf8, true))
+            .collect::<Vec<_>>(),
+    ));
+    let rows: Vec<Vec<String>> = data.iter().map(|d| d.values()).collect();
+    let arrays: Vec<ArrayRef> = (0..columns.len())
+        .map(|i| {
+            Arc::new(StringArray::from(
+                rows.iter().map(|r| r[i].clone()).collect::<Vec<String>>(),
+            )) as ArrayRef
+        })
+        .collect();
+    let batch = RecordBatch::try_new(schema.clone(), arrays)?;
+
+    let file = std::fs::File::create(filename)?;
+    let mut writer = ArrowWriter::try_new(file, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
@@ -1,14 +1,22 @@
+mod bigquery;
+mod excel;
 mod func;
 
+use crate::common::parse_columns;
 use anyhow::Result;
 use clap::{Args, Subcommand};
+use datafusion::arrow::record_batch::RecordBatch;
 use datafusion::dataframe::DataFrameWriteOptions;
 use datafusion_common::config::{TableParquetOptions, JsonOptions, CsvOptions};
 use datafusion::prelude::{
     CsvReadOptions, DataFrame, NdJsonReadOptions, ParquetReadOptions, SessionConfig, SessionContext,
 };
-use func::{udaf_string_agg, udf_pow};
+use func::{
+    udaf_string_agg, udf_bq_date_trunc, udf_parse_date, udf_parse_timestamp, udf_pow,
+    udfs_safe_cast,
+};
 use object_store::gcp::GoogleCloudStorageBuilder;
+use serde_json::{Map, Value};
 use std::ffi::OsStr;
 use std::fs::remove_dir_all;
 use std::io;
@@ -27,13 +35,38 @@ pub struct DataFusionArgs {
     ///
     /// You can use glob format for a single table.
     /// Multiple tables are also supported. To use it, add `-i <filename>` arguments as you need.
+    /// BigQuery table is read with `bq://<project>.<dataset>.<table>`.
     #[clap(short = 'i', long = "inputs")]
     pub inputs: Vec<String>,
 
+    /// Authenticate with user application to read `bq://` inputs. otherwise authenticate with
+    /// service account
+    #[clap(short = 'a', long = "auth_user", default_value = "true")]
+    pub auth_user: bool,
+
+    /// Maximum number of rows read from each `bq://` input.
+    ///
+    /// The whole table is loaded into memory unless this is set.
+    #[clap(long = "bq_limit")]
+    pub bq_limit: Option<usize>,
+
     /// Output raw JSON
     #[clap(short = 'j', long = "json", default_value = "false")]
     pub json: bool,
 
+    /// Output new line delimited JSON
+    #[clap(long = "ndjson", default_value = "false")]
+    pub ndjson: bool,
+
+    /// Output CSV with header
+    #[clap(long = "csv", default_value = "false")]
+    pub csv: bool,
+
+    /// Comma separated column names to output. The columns are output in this order.
+    /// e.g. `c1,c2,c3`
+    #[clap(long = "columns")]
+    pub columns: Option<String>,
+
     /// Output file. Optional.
     ///
     /// The result is always shown in stdout. This option write the result to the file.
@@ -45,6 +78,20 @@ pub struct DataFusionArgs {
     pub remove: bool,
 }
 
+impl DataFusionArgs {
+    fn output_format(&self) -> OutputFormat {
+        if self.json {
+            OutputFormat::Json
+        } else if self.ndjson {
+            OutputFormat::Ndjson
+        } else if self.csv {
+            OutputFormat::Csv
+        } else {
+            OutputFormat::Table
+        }
+    }
+}
+
 #[derive(Debug, Subcommand)]
 pub enum DataFusionSubCommand {
     /// Execute query
@@ -63,9 +110,22 @@ pub struct QueryArgs {
 #[derive(Default, Debug, Args)]
 pub struct SchemaArgs {}
 
+/// Output format of `print_dataframe`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// Table
+    Table,
+    /// JSON array
+    Json,
+    /// New line delimited JSON
+    Ndjson,
+    /// CSV with header
+    Csv,
+}
+
 #[derive(Error, Debug)]
 pub enum DFError {
-    #[error("file extension must be either `json` or `njson`(new line delimited json), `parquet`, `csv`")]
+    #[error("file extension must be either `json` or `njson`(new line delimited json), `parquet`, `csv`, `xlsx`, `xls`")]
     UnsupportFileFormat,
 }
 
@@ -105,20 +165,74 @@ pub fn session_context() -> SessionContext {
     SessionContext::new_with_config(cfg)
 }
 
-pub async fn register_source(ctx: &SessionContext, inputs: Vec<String>) -> Result<()> {
+/// Split `<name>=<path>` into the table name and the path.
+///
+/// The name must consist of alphanumeric characters or `_`. Otherwise, the input is treated as a path.
+/// A relative path in a sub directory like `dt=2024-01-01/part.parquet` is a Hive style partition,
+/// so it is also treated as a path. Use `<name>=./<dir>/<file>` to name such a file.
+fn split_alias(input: &str) -> (Option<&str>, &str) {
+    if let Some((name, path)) = input.split_once('=') {
+        let is_name =
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        let is_partition = path.contains('/')
+            && !path.contains("://")
+            && !path.starts_with('/')
+            && !path.starts_with("./")
+            && !path.starts_with("../");
+        if is_name && !is_partition {
+            return (Some(name), path);
+        }
+    }
+    (None, input)
+}
+
+/// Register input files as tables and return the registered table names.
+///
+/// The table name is `t0`, `t1`, ... in the order of inputs unless it is set by `<name>=<path>`.
+/// The first input is also registered as `t0`, which `chart` and `ml` query, unless another
+/// input is named `t0`.
+///
+/// # Arguments
+///
+/// * `auth_user` - authenticate with user application to read `bq://` inputs
+/// * `bq_limit` - maximum number of rows read from each `bq://` input
+pub async fn register_source(
+    ctx: &SessionContext,
+    inputs: Vec<String>,
+    auth_user: bool,
+    bq_limit: Option<usize>,
+) -> Result<Vec<String>> {
+    let mut tables = Vec::new();
     for (i, input) in inputs.iter().enumerate() {
-        let table_id = format!("t{}", i);
+        let (alias, input) = split_alias(input);
+        let table_id = alias
+            .map(|a| a.to_string())
+            .unwrap_or_else(|| format!("t{}", i));
+
+        // BigQuery
+        if input.starts_with(bigquery::SCHEME) {
+            let batch = bigquery::read_table(input, auth_user, bq_limit).await?;
+            ctx.register_batch(&table_id, batch)?;
+            tables.push(table_id);
+            continue;
+        }
 
         // GCS
         if let Ok(url) = Url::parse(input) {
             match url.scheme() {
                 "gs" => {
                     if let Some(bucket_name) = url.host_str() {
-                        let sa = std::env::var("GOOGLE_APPLICATION_CREDENTIALS")?;
-                        let gcs = GoogleCloudStorageBuilder::new()
-                            .with_service_account_path(sa)
-                            .with_bucket_name(bucket_name)
-                            .build()?;
+                        let mut builder =
+                            GoogleCloudStorageBuilder::new().with_bucket_name(bucket_name);
+                        if let Ok(credentials) = std::env::var("GOOGLE_APPLICATION_CREDENTIALS") {
+                            // service account or user credential file
+                            builder = builder.with_application_credentials(credentials);
+                        } else if let Ok(key) = std::env::var("GOOGLE_SERVICE_ACCOUNT_KEY") {
+                            // service account JSON itself
+                            builder = builder.with_service_account_key(key);
+                        }
+                        // Unless, application default credentials(gcloud or metadata server) are used.
+                        let gcs = builder.build()?;
                         ctx.runtime_env().register_object_store(&url, Arc::new(gcs));
                     }
                 }
@@ -126,7 +240,8 @@ pub async fn register_source(ctx: &SessionContext, inputs: Vec<String>) -> Resul
             }
         }
 
-        let path = Path::new(input);
+        let (source, sheet) = excel::split_sheet(input);
+        let path = Path::new(source);
         if let Some(input_ex) = path.extension().and_then(OsStr::to_str) {
             match input_ex {
                 "json" | "njson" => {
@@ -142,51 +257,122 @@ pub async fn register_source(ctx: &SessionContext, inputs: Vec<String>) -> Resul
                     ctx.register_csv(&table_id, input, CsvReadOptions::new())
                         .await?
                 }
+                "xlsx" | "xls" => {
+                    let batch = excel::read_sheet(source, sheet)?;
+                    ctx.register_batch(&table_id, batch)?;
+                }
                 _ => anyhow::bail!(DFError::UnsupportFileFormat),
             }
         } else {
             anyhow::bail!(DFError::UnsupportFileFormat)
         }
+        tables.push(table_id);
     }
-    Ok(())
+    if let Some(first) = tables.first() {
+        if !tables.iter().any(|t| t == "t0") {
+            let provider = ctx.table_provider(first.as_str()).await?;
+            ctx.register_table("t0", provider)?;
+        }
+    }
+    Ok(tables)
 }
 
-pub async fn print_dataframe(df: DataFrame, as_json: bool) -> Result<()> {
-    if as_json {
-        let batches = df.collect().await?;
-        let mut json_writer = datafusion::arrow::json::ArrayWriter::new(Vec::new());
-        let mut writer = io::BufWriter::new(io::stdout());
-        for batch in batches.iter() {
-            json_writer.write(batch)?;
+/// Execute the SQL and collect the result.
+pub async fn run_query(ctx: &SessionContext, sql: &str) -> Result<Vec<RecordBatch>> {
+    let df = ctx.sql(sql).await?;
+    Ok(df.collect().await?)
+}
+
+/// Convert the record batches into JSON objects. Each object is a row.
+///
+/// The keys are in the column order. Null values are omitted.
+pub fn record_batches_to_json(batches: &[RecordBatch]) -> Result<Vec<Map<String, Value>>> {
+    let mut json_writer = datafusion::arrow::json::ArrayWriter::new(Vec::new());
+    for batch in batches.iter() {
+        json_writer.write(batch)?;
+    }
+    json_writer.finish()?;
+    let buf = json_writer.into_inner();
+    if buf.is_empty() {
+        // nothing is written if there is no row
+        return Ok(vec![]);
+    }
+    Ok(serde_json::from_slice(&buf)?)
+}
+
+pub async fn print_dataframe(df: DataFrame, format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Table => df.show().await?,
+        OutputFormat::Json => {
+            let rows = record_batches_to_json(&df.collect().await?)?;
+            let mut writer = io::BufWriter::new(io::stdout());
+            serde_json::to_writer(&mut writer, &rows)?;
+            writer.flush()?;
+        }
+        OutputFormat::Ndjson => {
+            let rows = record_batches_to_json(&df.collect().await?)?;
+            let mut writer = io::BufWriter::new(io::stdout());
+            for row in rows.iter() {
+                serde_json::to_writer(&mut writer, row)?;
+                writeln!(writer)?;
+            }
+            writer.flush()?;
+        }
+        OutputFormat::Csv => {
+            let batches = df.collect().await?;
+            let mut csv_writer = datafusion::arrow::csv::Writer::new(io::stdout());
+            for batch in batches.iter() {
+                csv_writer.write(batch)?;
+            }
         }
-        json_writer.finish()?;
-        writer.write_all(&json_writer.into_inner())?;
-    } else {
-        df.show().await?;
     }
     Ok(())
 }
 
 pub async fn handle(dfargs: DataFusionArgs) -> Result<()> {
     let ctx = session_context();
+    let format = dfargs.output_format();
 
-    register_source(&ctx, dfargs.inputs).await?;
+    let tables = register_source(&ctx, dfargs.inputs, dfargs.auth_user, dfargs.bq_limit).await?;
 
     ctx.register_udf(udf_pow());
     ctx.register_udaf(udaf_string_agg());
+    // helpers to run BigQuery SQL locally
+    for udf in udfs_safe_cast() {
+        ctx.register_udf(udf);
+    }
+    ctx.register_udf(udf_parse_date());
+    ctx.register_udf(udf_parse_timestamp());
+    ctx.register_udf(udf_bq_date_trunc());
 
     match dfargs.datafusion_sub_command {
         DataFusionSubCommand::Schema(_args) => {
-            let df = ctx.sql("describe t0").await?;
+            let table = tables.first().ok_or_else(|| {
+                anyhow::anyhow!("no input. please set input with '--inputs' option.")
+            })?;
+            let df = ctx.sql(&format!("describe {}", table)).await?;
 
-            print_dataframe(df, dfargs.json).await?;
+            print_dataframe(df, format).await?;
 
             Ok(())
         }
         DataFusionSubCommand::Query(args) => {
-            let df = ctx.sql(&args.query).await?;
+            let mut df = ctx.sql(&args.query).await?;
+            if let Some(columns) = dfargs.columns.as_ref() {
+                let available: Vec<String> = df
+                    .schema()
+                    .fields()
+                    .iter()
+                    .map(|f| f.name().clone())
+                    .collect();
+                let selected: Vec<&str> = parse_columns(columns, &available)?
+                    .iter()
+                    .map(|i| available[*i].as_str())
+                    .collect();
+                df = df.select_columns(&selected)?;
+            }
 
-            print_dataframe(df.clone(), dfargs.json).await?;
+            print_dataframe(df.clone(), format).await?;
 
             if let Some(output) = dfargs.output {
                 write_file(df, output, dfargs.remove).await?;
@@ -195,3 +381,74 @@ pub async fn handle(dfargs: DataFusionArgs) -> Result<()> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::{Int64Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+
+    #[test]
+    fn split_alias_with_name() {
+        assert_eq!(split_alias("sales=a.csv"), (Some("sales"), "a.csv"));
+        assert_eq!(
+            split_alias("users=./data/b.parquet"),
+            (Some("users"), "./data/b.parquet")
+        );
+        assert_eq!(split_alias("raw=/tmp/c.json"), (Some("raw"), "/tmp/c.json"));
+        assert_eq!(
+            split_alias("logs=gs://bucket/dir/*.json"),
+            (Some("logs"), "gs://bucket/dir/*.json")
+        );
+        assert_eq!(split_alias("tb=bq://p.d.t"), (Some("tb"), "bq://p.d.t"));
+    }
+
+    #[test]
+    fn split_alias_without_name() {
+        assert_eq!(split_alias("a.csv"), (None, "a.csv"));
+        assert_eq!(
+            split_alias("gs://bucket/dt=2024-01-01/x.parquet"),
+            (None, "gs://bucket/dt=2024-01-01/x.parquet")
+        );
+        assert_eq!(split_alias("my-table=a.csv"), (None, "my-table=a.csv"));
+        assert_eq!(split_alias("=a.csv"), (None, "=a.csv"));
+    }
+
+    #[test]
+    fn split_alias_keeps_hive_partition_path() {
+        assert_eq!(
+            split_alias("dt=2024-01-01/part.parquet"),
+            (None, "dt=2024-01-01/part.parquet")
+        );
+        assert_eq!(
+            split_alias("year=2024/month=01/*.csv"),
+            (None, "year=2024/month=01/*.csv")
+        );
+    }
+
+    #[test]
+    fn record_batches_to_json_keeps_column_order() -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("name", DataType::Utf8, true),
+            Field::new("age", DataType::Int64, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from(vec![Some("a"), None])),
+                Arc::new(Int64Array::from(vec![1, 2])),
+            ],
+        )?;
+        let rows = record_batches_to_json(&[batch])?;
+        assert_eq!(rows.len(), 2);
+        let keys: Vec<&String> = rows[0].keys().collect();
+        assert_eq!(keys, vec!["name", "age"]);
+        assert_eq!(rows[0]["name"], Value::from("a"));
+        assert_eq!(rows[0]["age"], Value::from(1));
+        // null is omitted
+        assert!(!rows[1].contains_key("name"));
+
+        assert!(record_batches_to_json(&[RecordBatch::new_empty(schema)])?.is_empty());
+        Ok(())
+    }
+}
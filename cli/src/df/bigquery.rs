@@ -0,0 +1,67 @@
+use crate::common::resolve_auth_legacy;
+use anyhow::Result;
+use datafusion::arrow::record_batch::RecordBatch;
+use gcprs::bigquery::{to_record_batch, Bq, BqListParam, BqTable};
+
+/// Scheme of BigQuery table input
+pub const SCHEME: &str = "bq://";
+
+/// Number of rows per tabledata.list request
+const MAX_RESULTS: u32 = 10000;
+
+/// Split `bq://<project>.<dataset>.<table>` into the project, dataset and table.
+fn split_table(input: &str) -> Result<(&str, &str, &str)> {
+    let invalid = || anyhow::anyhow!("{} is invalid. use bq://<project>.<dataset>.<table>", input);
+    let path = input.strip_prefix(SCHEME).ok_or_else(invalid)?;
+    let mut parts = path.splitn(3, '.');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(p), Some(d), Some(t)) if !p.is_empty() && !d.is_empty() && !t.is_empty() => {
+            Ok((p, d, t))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Read the BigQuery table into a `RecordBatch`.
+///
+/// The table is read by tabledata.list API, so there is no query cost. All rows are held in
+/// memory, so set `limit` for a large table.
+/// See `to_record_batch` for the column types.
+///
+/// # Arguments
+///
+/// * `input` - `bq://<project>.<dataset>.<table>`
+/// * `auth_user` - authenticate with user application. otherwise authenticate with service account
+/// * `limit` - maximum number of rows to read
+pub async fn read_table(input: &str, auth_user: bool, limit: Option<usize>) -> Result<RecordBatch> {
+    let (project, dataset, table) = split_table(input)?;
+    let auth = resolve_auth_legacy(auth_user).await?;
+    let bigquery = Bq::new(&auth, project)?;
+    let schemas = bigquery
+        .get_table_schema(&dataset.to_string(), &table.to_string())
+        .await?;
+    let mut params = BqListParam::new();
+    params.max_results(MAX_RESULTS);
+    if let Some(limit) = limit {
+        params.num_result_limit(limit);
+    }
+    let rows = bigquery
+        .list_tabledata(&BqTable::new(project, dataset, table), &params)
+        .await?;
+    to_record_batch(&schemas, &rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_table_of_input() {
+        assert_eq!(split_table("bq://p.d.t").unwrap(), ("p", "d", "t"));
+        // the rest after the dataset is the table name
+        assert_eq!(split_table("bq://p.d.t.x").unwrap(), ("p", "d", "t.x"));
+        assert!(split_table("bq://p.d").is_err());
+        assert!(split_table("bq://p..t").is_err());
+        assert!(split_table("p.d.t").is_err());
+    }
+}
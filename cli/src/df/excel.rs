@@ -0,0 +1,241 @@
+use anyhow::Result;
+use calamine::{open_workbook_auto, Data, Reader};
+use chrono::NaiveDateTime;
+use datafusion::arrow::array::{
+    ArrayRef, BooleanArray, Float64Array, Int64Array, StringArray, TimestampMicrosecondArray,
+};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use datafusion::arrow::record_batch::RecordBatch;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Split `<path>#<sheet name>` into the path and the sheet name.
+///
+/// Only Excel file path is split. Other inputs are returned as is.
+pub fn split_sheet(input: &str) -> (&str, Option<&str>) {
+    if let Some((path, sheet)) = input.rsplit_once('#') {
+        let ex = Path::new(path).extension().and_then(OsStr::to_str);
+        if let Some("xlsx") | Some("xls") = ex {
+            return (path, Some(sheet));
+        }
+    }
+    (input, None)
+}
+
+/// Date and time of the cell. Durations are not.
+fn cell_datetime(cell: &Data) -> Option<NaiveDateTime> {
+    match cell {
+        Data::DateTime(d) if d.is_datetime() => d.as_datetime(),
+        _ => None,
+    }
+}
+
+/// String of the cell. Date and time is ISO 8601 format.
+fn cell_string(cell: &Data) -> String {
+    match cell_datetime(cell) {
+        Some(d) => d.format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+        None => cell.to_string(),
+    }
+}
+
+/// Infer column type from all rows.
+///
+/// If there are mixed types in the column, the column is treated as string.
+fn infer_type(rows: &[&[Data]], col: usize) -> DataType {
+    let mut inferred: Option<DataType> = None;
+    for row in rows.iter() {
+        let cell_type = match row.get(col) {
+            Some(Data::Int(_)) => DataType::Int64,
+            Some(Data::Float(_)) => DataType::Float64,
+            Some(Data::Bool(_)) => DataType::Boolean,
+            Some(cell) if cell_datetime(cell).is_some() => {
+                DataType::Timestamp(TimeUnit::Microsecond, None)
+            }
+            Some(Data::Empty) | None => continue,
+            _ => return DataType::Utf8,
+        };
+        inferred = match (inferred, cell_type) {
+            (None, t) => Some(t),
+            (Some(a), b) if a == b => Some(a),
+            (Some(DataType::Int64), DataType::Float64)
+            | (Some(DataType::Float64), DataType::Int64) => Some(DataType::Float64),
+            _ => return DataType::Utf8,
+        };
+    }
+    inferred.unwrap_or(DataType::Utf8)
+}
+
+fn to_array(rows: &[&[Data]], col: usize, data_type: &DataType) -> Result<ArrayRef> {
+    let mismatch = |row: usize| {
+        anyhow::anyhow!(
+            "unexpected value type at row {}, column {}. expected {}",
+            row + 1,
+            col + 1,
+            data_type
+        )
+    };
+    let array: ArrayRef = match data_type {
+        DataType::Int64 => {
+            let mut values = Vec::new();
+            for (i, row) in rows.iter().enumerate() {
+                values.push(match row.get(col) {
+                    Some(Data::Int(v)) => Some(*v),
+                    Some(Data::Empty) | None => None,
+                    _ => return Err(mismatch(i)),
+                });
+            }
+            Arc::new(Int64Array::from(values))
+        }
+        DataType::Float64 => {
+            let mut values = Vec::new();
+            for (i, row) in rows.iter().enumerate() {
+                values.push(match row.get(col) {
+                    Some(Data::Int(v)) => Some(*v as f64),
+                    Some(Data::Float(v)) => Some(*v),
+                    Some(Data::Empty) | None => None,
+                    _ => return Err(mismatch(i)),
+                });
+            }
+            Arc::new(Float64Array::from(values))
+        }
+        DataType::Boolean => {
+            let mut values = Vec::new();
+            for (i, row) in rows.iter().enumerate() {
+                values.push(match row.get(col) {
+                    Some(Data::Bool(v)) => Some(*v),
+                    Some(Data::Empty) | None => None,
+                    _ => return Err(mismatch(i)),
+                });
+            }
+            Arc::new(BooleanArray::from(values))
+        }
+        DataType::Timestamp(TimeUnit::Microsecond, None) => {
+            let mut values = Vec::new();
+            for (i, row) in rows.iter().enumerate() {
+                values.push(match row.get(col) {
+                    Some(Data::Empty) | None => None,
+                    Some(cell) => match cell_datetime(cell) {
+                        Some(d) => Some(d.and_utc().timestamp_micros()),
+                        None => return Err(mismatch(i)),
+                    },
+                });
+            }
+            Arc::new(TimestampMicrosecondArray::from(values))
+        }
+        _ => Arc::new(StringArray::from(
+            rows.iter()
+                .map(|row| match row.get(col) {
+                    Some(Data::Empty) | None => None,
+                    Some(v) => Some(cell_string(v)),
+                })
+                .collect::<Vec<Option<String>>>(),
+        )),
+    };
+    Ok(array)
+}
+
+/// Read a sheet of Excel file into a `RecordBatch`.
+///
+/// The first row is used as the header. If `sheet` is not set, the first sheet is read.
+pub fn read_sheet(path: &str, sheet: Option<&str>) -> Result<RecordBatch> {
+    let mut workbook = open_workbook_auto(path)?;
+    let sheet_name = match sheet {
+        Some(s) => s.to_string(),
+        None => workbook
+            .sheet_names()
+            .first()
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("there is no sheet in {}", path))?,
+    };
+    let range = workbook.worksheet_range(&sheet_name)?;
+    let mut rows = range.rows();
+    let header: Vec<String> = rows
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("sheet {} is empty", sheet_name))?
+        .iter()
+        .enumerate()
+        .map(|(i, h)| match h {
+            Data::Empty => format!("column_{}", i),
+            _ => h.to_string(),
+        })
+        .collect();
+    let rows: Vec<&[Data]> = rows.collect();
+
+    let mut fields = Vec::new();
+    let mut columns = Vec::new();
+    for (i, name) in header.iter().enumerate() {
+        let data_type = infer_type(&rows, i);
+        columns.push(to_array(&rows, i, &data_type)?);
+        fields.push(Field::new(name, data_type, true));
+    }
+    let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?;
+    Ok(batch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calamine::{ExcelDateTime, ExcelDateTimeType};
+
+    fn infer(cells: &[Data]) -> DataType {
+        let rows: Vec<Vec<Data>> = cells.iter().map(|c| vec![c.clone()]).collect();
+        let rows: Vec<&[Data]> = rows.iter().map(|r| r.as_slice()).collect();
+        infer_type(&rows, 0)
+    }
+
+    #[test]
+    fn split_sheet_of_excel() {
+        assert_eq!(split_sheet("a.xlsx#Sheet2"), ("a.xlsx", Some("Sheet2")));
+        assert_eq!(split_sheet("dir/a.xls#売上"), ("dir/a.xls", Some("売上")));
+        assert_eq!(split_sheet("a#b.xlsx#s"), ("a#b.xlsx", Some("s")));
+        assert_eq!(split_sheet("a.xlsx"), ("a.xlsx", None));
+        assert_eq!(split_sheet("a.csv#x"), ("a.csv#x", None));
+    }
+
+    #[test]
+    fn infer_type_of_cells() {
+        assert_eq!(infer(&[Data::Int(1), Data::Empty]), DataType::Int64);
+        assert_eq!(infer(&[Data::Int(1), Data::Float(0.5)]), DataType::Float64);
+        assert_eq!(infer(&[Data::Bool(true)]), DataType::Boolean);
+        assert_eq!(
+            infer(&[Data::Int(1), Data::String("a".to_string())]),
+            DataType::Utf8
+        );
+        assert_eq!(infer(&[Data::Empty]), DataType::Utf8);
+        let date = Data::DateTime(ExcelDateTime::new(
+            45292.5,
+            ExcelDateTimeType::DateTime,
+            false,
+        ));
+        assert_eq!(
+            infer(&[date.clone()]),
+            DataType::Timestamp(TimeUnit::Microsecond, None)
+        );
+        assert_eq!(infer(&[date, Data::Int(1)]), DataType::Utf8);
+    }
+
+    #[test]
+    fn mismatch_after_many_rows_falls_back_to_string() {
+        let mut cells: Vec<Data> = (0..200).map(Data::Int).collect();
+        cells.insert(150, Data::String("n/a".to_string()));
+        let rows: Vec<Vec<Data>> = cells.iter().map(|c| vec![c.clone()]).collect();
+        let rows: Vec<&[Data]> = rows.iter().map(|r| r.as_slice()).collect();
+        let data_type = infer_type(&rows, 0);
+        assert_eq!(data_type, DataType::Utf8);
+        let array = to_array(&rows, 0, &data_type).unwrap();
+        let array = array.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(array.value(150), "n/a");
+        assert_eq!(array.value(151), "150");
+    }
+
+    #[test]
+    fn datetime_cell_is_iso_string() {
+        let date = Data::DateTime(ExcelDateTime::new(
+            45292.5,
+            ExcelDateTimeType::DateTime,
+            false,
+        ));
+        assert_eq!(cell_string(&date), "2024-01-01T12:00:00");
+    }
+}
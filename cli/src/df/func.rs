@@ -1,16 +1,23 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, Utc};
 use datafusion::arrow::{
-    array::{ArrayRef, Float64Array},
-    datatypes::DataType,
+    array::{
+        ArrayRef, BooleanArray, Date32Array, Float64Array, Int64Array, StringArray,
+        TimestampMicrosecondArray,
+    },
+    datatypes::{DataType, TimeUnit},
 };
-use datafusion::error::Result;
+use datafusion::error::{DataFusionError, Result};
 use datafusion::logical_expr::Volatility;
 use datafusion::physical_plan::Accumulator;
 use datafusion::prelude::create_udf;
 use datafusion::scalar::ScalarValue;
-use datafusion_common::cast::as_float64_array;
+use datafusion_common::cast::{as_date32_array, as_float64_array, as_string_array};
 use datafusion_expr::{create_udaf, AggregateUDF, ColumnarValue, ScalarUDF};
 use std::sync::Arc;
 
+/// Days from 0001-01-01(CE) to 1970-01-01. Date32 is the days since the UNIX epoch.
+const UNIX_EPOCH_DAYS_FROM_CE: i32 = 719_163;
+
 pub fn udf_pow() -> ScalarUDF {
     let pow = Arc::new(|args: &[ColumnarValue]| {
         let args = ColumnarValue::values_to_arrays(args)?;
@@ -38,6 +45,192 @@ pub fn udf_pow() -> ScalarUDF {
     )
 }
 
+fn udf_safe_cast(
+    name: &str,
+    return_type: DataType,
+    cast: fn(&StringArray) -> ArrayRef,
+) -> ScalarUDF {
+    let fun = Arc::new(move |args: &[ColumnarValue]| {
+        let args = ColumnarValue::values_to_arrays(args)?;
+        let values = as_string_array(&args[0])?;
+        Ok(ColumnarValue::from(cast(values)))
+    });
+    create_udf(
+        name,
+        vec![DataType::Utf8],
+        return_type,
+        Volatility::Immutable,
+        fun,
+    )
+}
+
+/// `safe_cast_int64`, `safe_cast_float64` and `safe_cast_bool` like `SAFE_CAST` of BigQuery.
+///
+/// They parse the string and return NULL instead of an error if it can not be parsed.
+pub fn udfs_safe_cast() -> Vec<ScalarUDF> {
+    vec![
+        udf_safe_cast("safe_cast_int64", DataType::Int64, |values| {
+            Arc::new(
+                values
+                    .iter()
+                    .map(|v| v.and_then(|v| v.trim().parse::<i64>().ok()))
+                    .collect::<Int64Array>(),
+            )
+        }),
+        udf_safe_cast("safe_cast_float64", DataType::Float64, |values| {
+            Arc::new(
+                values
+                    .iter()
+                    .map(|v| v.and_then(|v| v.trim().parse::<f64>().ok()))
+                    .collect::<Float64Array>(),
+            )
+        }),
+        udf_safe_cast("safe_cast_bool", DataType::Boolean, |values| {
+            Arc::new(
+                values
+                    .iter()
+                    .map(|v| {
+                        v.and_then(|v| match v.trim().to_ascii_lowercase().as_str() {
+                            "true" => Some(true),
+                            "false" => Some(false),
+                            _ => None,
+                        })
+                    })
+                    .collect::<BooleanArray>(),
+            )
+        }),
+    ]
+}
+
+/// `parse_date(format, value)` like `PARSE_DATE` of BigQuery. e.g. `parse_date('%Y%m%d', '20240131')`
+pub fn udf_parse_date() -> ScalarUDF {
+    let fun = Arc::new(|args: &[ColumnarValue]| {
+        let args = ColumnarValue::values_to_arrays(args)?;
+        let formats = as_string_array(&args[0])?;
+        let values = as_string_array(&args[1])?;
+        let array = formats
+            .iter()
+            .zip(values.iter())
+            .map(|(format, value)| match (format, value) {
+                (Some(format), Some(value)) => NaiveDate::parse_from_str(value, format)
+                    .map(|d| Some(d.num_days_from_ce() - UNIX_EPOCH_DAYS_FROM_CE))
+                    .map_err(|e| {
+                        DataFusionError::Execution(format!("parse_date failed: {}: {}", value, e))
+                    }),
+                _ => Ok(None),
+            })
+            .collect::<Result<Date32Array>>()?;
+        Ok(ColumnarValue::from(Arc::new(array) as ArrayRef))
+    });
+
+    create_udf(
+        "parse_date",
+        vec![DataType::Utf8, DataType::Utf8],
+        DataType::Date32,
+        Volatility::Immutable,
+        fun,
+    )
+}
+
+/// Parse the timestamp with the format. The timestamp without the time zone is treated as UTC.
+fn parse_timestamp(value: &str, format: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_str(value, format)
+        .map(|t| t.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(value, format).map(|t| t.and_utc()))
+        .ok()
+}
+
+/// `parse_timestamp(format, value)` like `PARSE_TIMESTAMP` of BigQuery.
+/// e.g. `parse_timestamp('%Y-%m-%d %H:%M:%S', '2024-01-31 12:00:00')`
+///
+/// The result is a UTC timestamp in microseconds.
+pub fn udf_parse_timestamp() -> ScalarUDF {
+    let fun = Arc::new(|args: &[ColumnarValue]| {
+        let args = ColumnarValue::values_to_arrays(args)?;
+        let formats = as_string_array(&args[0])?;
+        let values = as_string_array(&args[1])?;
+        let array = formats
+            .iter()
+            .zip(values.iter())
+            .map(|(format, value)| match (format, value) {
+                (Some(format), Some(value)) => parse_timestamp(value, format)
+                    .map(|t| Some(t.timestamp_micros()))
+                    .ok_or_else(|| {
+                        DataFusionError::Execution(format!(
+                            "parse_timestamp failed: {} with {}",
+                            value, format
+                        ))
+                    }),
+                _ => Ok(None),
+            })
+            .collect::<Result<TimestampMicrosecondArray>>()?;
+        Ok(ColumnarValue::from(Arc::new(array) as ArrayRef))
+    });
+
+    create_udf(
+        "parse_timestamp",
+        vec![DataType::Utf8, DataType::Utf8],
+        DataType::Timestamp(TimeUnit::Microsecond, None),
+        Volatility::Immutable,
+        fun,
+    )
+}
+
+/// Truncate the date to the part. `WEEK` starts on Sunday and `ISOWEEK` starts on Monday.
+fn trunc_date(date: NaiveDate, part: &str) -> Result<NaiveDate> {
+    let truncated = match part.to_ascii_uppercase().as_str() {
+        "DAY" => Some(date),
+        "WEEK" => Some(date - Duration::days(date.weekday().num_days_from_sunday() as i64)),
+        "ISOWEEK" => Some(date - Duration::days(date.weekday().num_days_from_monday() as i64)),
+        "MONTH" => date.with_day(1),
+        "QUARTER" => NaiveDate::from_ymd_opt(date.year(), date.month0() / 3 * 3 + 1, 1),
+        "YEAR" => NaiveDate::from_ymd_opt(date.year(), 1, 1),
+        _ => {
+            return Err(DataFusionError::Execution(format!(
+                "bq_date_trunc does not support {}. use DAY, WEEK, ISOWEEK, MONTH, QUARTER or YEAR",
+                part
+            )))
+        }
+    };
+    truncated.ok_or_else(|| DataFusionError::Execution(format!("invalid date: {}", date)))
+}
+
+/// `bq_date_trunc(date, part)` like `DATE_TRUNC` of BigQuery. e.g. `bq_date_trunc(d, 'MONTH')`
+///
+/// The argument order and the week start(Sunday) follow BigQuery. The builtin `date_trunc` of
+/// DataFusion is `date_trunc('month', d)`.
+pub fn udf_bq_date_trunc() -> ScalarUDF {
+    let fun = Arc::new(|args: &[ColumnarValue]| {
+        let args = ColumnarValue::values_to_arrays(args)?;
+        let dates = as_date32_array(&args[0])?;
+        let parts = as_string_array(&args[1])?;
+        let array = dates
+            .iter()
+            .zip(parts.iter())
+            .map(|(days, part)| match (days, part) {
+                (Some(days), Some(part)) => {
+                    let date = NaiveDate::from_num_days_from_ce_opt(days + UNIX_EPOCH_DAYS_FROM_CE)
+                        .ok_or_else(|| {
+                            DataFusionError::Execution(format!("invalid date: {}", days))
+                        })?;
+                    trunc_date(date, part)
+                        .map(|d| Some(d.num_days_from_ce() - UNIX_EPOCH_DAYS_FROM_CE))
+                }
+                _ => Ok(None),
+            })
+            .collect::<Result<Date32Array>>()?;
+        Ok(ColumnarValue::from(Arc::new(array) as ArrayRef))
+    });
+
+    create_udf(
+        "bq_date_trunc",
+        vec![DataType::Date32, DataType::Utf8],
+        DataType::Date32,
+        Volatility::Immutable,
+        fun,
+    )
+}
+
 pub fn udaf_string_agg() -> AggregateUDF {
     create_udaf(
         // the name; used to represent it in plan descriptions and in the registry, to use in SQL.
@@ -1,7 +1,6 @@
-use crate::common::{render, OutputFormat, TableView};
+use crate::common::{render, resolve_auth, OutputFormat, TableView};
 use anyhow::Result;
 use clap::{Args, Subcommand};
-use gcprs::auth;
 use gcprs::drive as libdrive;
 use libdrive::{Drive, DriveFile, DriveListParam};
 
@@ -115,11 +114,7 @@ impl TableView for DriveFile {
 }
 
 pub async fn handle(dargs: DriveArgs) -> Result<()> {
-    let spauth = if dargs.auth_user {
-        auth::GcpAuth::from_user_auth().await.unwrap()
-    } else {
-        auth::GcpAuth::from_service_account().await.unwrap()
-    };
+    let spauth = resolve_auth(dargs.auth_user).await?;
     let drive = Drive::new(&spauth);
     match dargs.drive_sub_command {
         DriveSubCommand::List(args) => {
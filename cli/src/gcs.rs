@@ -1,16 +1,22 @@
-use crate::common::{render, OutputFormat, TableView};
+use crate::common::{render, resolve_auth, OutputFormat, TableView};
 use anyhow::Result;
 use clap::{Args, Subcommand};
+use futures::stream::{self, StreamExt, TryStreamExt};
 use gcprs::auth;
 use gcprs::gcs as libgcs;
-use libgcs::{Gcs, GcsListParam, GcsObject};
+use http_body_util::BodyExt;
+use libgcs::{Gcs, GcsBucket, GcsListParam, GcsObject};
+use serde::Serialize;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use url::Url;
 
 #[derive(Debug, Args)]
 pub struct GcsArgs {
-    /// Bucket name
+    /// Bucket name. Not used by `cp`, `cat` and `stat`, which take `gs://bucket/path` URL instead.
     #[clap(short = 'b', long = "bucket")]
-    pub bucket: String,
+    pub bucket: Option<String>,
 
     /// Output raw JSON
     #[clap(short = 'r', long = "raw_json", default_value = "false")]
@@ -40,6 +46,15 @@ pub enum GcsSubCommand {
 
     /// Delete object
     Delete(ObjectArgs),
+
+    /// Copy files between local and GCS. One of `src` and `dst` must be `gs://bucket/path`.
+    Cp(CpArgs),
+
+    /// Write object content to stdout
+    Cat(CatArgs),
+
+    /// Show object or bucket metadata
+    Stat(StatArgs),
 }
 
 #[derive(Default, Debug, Args)]
@@ -57,6 +72,99 @@ pub struct UploadArgs {
     name: String,
 }
 
+#[derive(Default, Debug, Args)]
+pub struct CpArgs {
+    /// Source. Local path or gs://bucket/path
+    src: String,
+
+    /// Destination. Local path or gs://bucket/path
+    dst: String,
+
+    /// Copy directory(prefix) recursively
+    #[clap(short = 'r', long = "recursive", default_value = "false")]
+    recursive: bool,
+
+    /// The number of concurrent transfers
+    #[clap(short = 'c', long = "concurrency", default_value = "8")]
+    concurrency: usize,
+}
+
+#[derive(Default, Debug, Args)]
+pub struct CatArgs {
+    /// Object. gs://bucket/path
+    object: String,
+}
+
+#[derive(Default, Debug, Args)]
+pub struct StatArgs {
+    /// Object(gs://bucket/path) or bucket(gs://bucket) with `--bucket`
+    target: String,
+
+    /// Show bucket metadata
+    #[clap(long = "bucket", default_value = "false")]
+    bucket: bool,
+}
+
+/// Object metadata for `stat`
+#[derive(Serialize)]
+#[serde(transparent)]
+struct ObjectStat(GcsObject);
+
+impl TableView for ObjectStat {
+    fn columns(&self) -> Vec<String> {
+        vec![
+            "name".to_string(),
+            "size".to_string(),
+            "content_type".to_string(),
+            "created_at".to_string(),
+            "updated_at".to_string(),
+            "md5_hash".to_string(),
+        ]
+    }
+
+    fn values(&self) -> Vec<String> {
+        let o = &self.0;
+        vec![
+            o.name.clone().unwrap_or_default(),
+            o.size.map(|c| format!("{}", c)).unwrap_or_default(),
+            o.content_type.clone().unwrap_or_default(),
+            o.created_at.map(|c| format!("{}", c)).unwrap_or_default(),
+            o.updated_at.map(|c| format!("{}", c)).unwrap_or_default(),
+            o.md5_hash.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+impl TableView for GcsBucket {
+    fn columns(&self) -> Vec<String> {
+        vec![
+            "name".to_string(),
+            "location".to_string(),
+            "location_type".to_string(),
+            "storage_class".to_string(),
+            "created_at".to_string(),
+            "updated_at".to_string(),
+            "versioning".to_string(),
+        ]
+    }
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.location.clone().unwrap_or_default(),
+            self.location_type.clone().unwrap_or_default(),
+            self.storage_class.clone().unwrap_or_default(),
+            self.created_at
+                .map(|c| format!("{}", c))
+                .unwrap_or_default(),
+            self.updated_at
+                .map(|c| format!("{}", c))
+                .unwrap_or_default(),
+            format!("{}", self.versioning),
+        ]
+    }
+}
+
 impl TableView for GcsObject {
     fn columns(&self) -> Vec<String> {
         vec![
@@ -99,18 +207,23 @@ impl TableView for GcsObject {
 }
 
 pub async fn handle(gcsargs: GcsArgs) -> Result<()> {
-    let spauth = if gcsargs.auth_user {
-        auth::GcpAuth::from_user_auth().await.unwrap()
-    } else {
-        auth::GcpAuth::from_service_account().await.unwrap()
-    };
-    let (bucket, path) = if let Ok(url) = Url::parse(&gcsargs.bucket) {
+    let spauth = resolve_auth(gcsargs.auth_user).await?;
+    match &gcsargs.gcs_sub_command {
+        GcsSubCommand::Cp(args) => return copy(&spauth, args).await,
+        GcsSubCommand::Cat(args) => return cat(&spauth, args).await,
+        GcsSubCommand::Stat(args) => return stat(&spauth, args, gcsargs.raw).await,
+        _ => {}
+    }
+    let bucket = gcsargs
+        .bucket
+        .ok_or_else(|| anyhow::anyhow!("--bucket is required"))?;
+    let (bucket, path) = if let Ok(url) = Url::parse(&bucket) {
         (
             url.host_str().unwrap_or(&"".to_string()).to_string(),
             url.path().to_string(),
         )
     } else {
-        (gcsargs.bucket, "".to_string())
+        (bucket, "".to_string())
     };
     let cloud_storage = Gcs::new(&spauth, bucket.clone());
     match gcsargs.gcs_sub_command {
@@ -143,8 +256,8 @@ pub async fn handle(gcsargs: GcsArgs) -> Result<()> {
         GcsSubCommand::Get(args) => {
             let mut object = GcsObject::new(bucket, args.name);
             cloud_storage.get_object(&mut object).await?;
-            if let Some(content) = object.content {
-                println!("{}", content);
+            if let Some(bytes) = object.bytes() {
+                io::stdout().write_all(bytes)?;
             }
             Ok(())
         }
@@ -165,5 +278,243 @@ pub async fn handle(gcsargs: GcsArgs) -> Result<()> {
                 false,
             )
         }
+        GcsSubCommand::Cp(_) | GcsSubCommand::Cat(_) | GcsSubCommand::Stat(_) => unreachable!(),
+    }
+}
+
+/// Split `gs://bucket/path` into the bucket and the path. Returns `None` for local path.
+fn split_gcs_url(s: &str) -> Option<(String, String)> {
+    let rest = s.strip_prefix("gs://")?;
+    match rest.split_once('/') {
+        Some((bucket, path)) => Some((bucket.to_string(), path.to_string())),
+        None => Some((rest.to_string(), "".to_string())),
+    }
+}
+
+/// Join object prefix and relative path with `/`
+fn join_object_name(prefix: &str, relative: &str) -> String {
+    let prefix = prefix.trim_end_matches('/');
+    if prefix.is_empty() {
+        relative.to_string()
+    } else {
+        format!("{}/{}", prefix, relative)
+    }
+}
+
+/// Local path of the object downloaded recursively from the prefix.
+///
+/// The prefix is matched by `/` separated segments, so `data` covers `data/x` but not
+/// `database/x`, and `None` is returned for the latter. The object which is the prefix itself is
+/// saved by the file name. The object name must not escape the destination directory.
+fn object_relative_path(prefix: &str, name: &str) -> Result<Option<PathBuf>> {
+    let relative = if !prefix.is_empty() && name == prefix {
+        name.rsplit('/').next().unwrap_or(name)
+    } else {
+        let dir = if prefix.is_empty() || prefix.ends_with('/') {
+            prefix.to_string()
+        } else {
+            format!("{}/", prefix)
+        };
+        match name.strip_prefix(&dir) {
+            Some(relative) => relative,
+            None => return Ok(None),
+        }
+    };
+    let path = PathBuf::from(relative);
+    anyhow::ensure!(
+        !relative.is_empty()
+            && path
+                .components()
+                .all(|c| matches!(c, std::path::Component::Normal(_))),
+        "object name {} can not be saved under the destination",
+        name
+    );
+    Ok(Some(path))
+}
+
+/// Collect all files under the directory
+fn walk_dir(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk_dir(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Write the object body to stdout while receiving
+async fn cat(spauth: &auth::GcpAuth, args: &CatArgs) -> Result<()> {
+    let (bucket, name) = split_gcs_url(&args.object)
+        .ok_or_else(|| anyhow::anyhow!("object must be gs://bucket/path"))?;
+    let cloud_storage = Gcs::new(spauth, bucket);
+    let mut body = cloud_storage.get_object_stream(name).await?.into_body();
+    let mut out = io::stdout();
+    while let Some(frame) = body.frame().await {
+        if let Some(data) = frame?.data_ref() {
+            match out.write_all(data) {
+                // the reader is closed. e.g. `| head`
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+                res => res?,
+            }
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+async fn stat(spauth: &auth::GcpAuth, args: &StatArgs, raw: bool) -> Result<()> {
+    let (bucket, name) = split_gcs_url(&args.target)
+        .ok_or_else(|| anyhow::anyhow!("target must be gs://bucket/path"))?;
+    let cloud_storage = Gcs::new(spauth, bucket);
+    let format = if raw {
+        OutputFormat::Json
+    } else {
+        OutputFormat::Stdout
+    };
+    if args.bucket {
+        let data = cloud_storage.get_bucket().await?;
+        render(&vec![data], format, false)
+    } else {
+        anyhow::ensure!(
+            !name.is_empty(),
+            "object name is required. use --bucket for bucket"
+        );
+        let data = cloud_storage.get_object_metadata(name).await?;
+        render(&vec![ObjectStat(data)], format, false)
+    }
+}
+
+async fn copy(spauth: &auth::GcpAuth, args: &CpArgs) -> Result<()> {
+    anyhow::ensure!(0 < args.concurrency, "concurrency must be greater than 0");
+    match (split_gcs_url(&args.src), split_gcs_url(&args.dst)) {
+        (None, Some((bucket, prefix))) => {
+            let cloud_storage = Gcs::new(spauth, bucket.clone());
+            let src = Path::new(&args.src);
+            let mut uploads = Vec::new();
+            if args.recursive {
+                let mut files = Vec::new();
+                walk_dir(src, &mut files)?;
+                for file in files {
+                    let relative = file
+                        .strip_prefix(src)?
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/");
+                    uploads.push((join_object_name(&prefix, &relative), file));
+                }
+            } else {
+                anyhow::ensure!(src.is_file(), "{} is not a file", args.src);
+                let name = if prefix.is_empty() || prefix.ends_with('/') {
+                    let file_name = src
+                        .file_name()
+                        .map(|f| f.to_string_lossy().to_string())
+                        .unwrap_or_default();
+                    join_object_name(&prefix, &file_name)
+                } else {
+                    prefix
+                };
+                uploads.push((name, src.to_path_buf()));
+            }
+            let items = uploads
+                .into_iter()
+                .map(|(name, file)| (GcsObject::new(bucket.clone(), name), file))
+                .collect();
+            let results = cloud_storage.insert_many(items, args.concurrency).await?;
+            let mut failed = 0;
+            for (object, result) in results {
+                match result {
+                    Ok(uploaded) => eprintln!("{}", uploaded.url()),
+                    Err(e) => {
+                        failed += 1;
+                        eprintln!("failed to upload {}: {}", object.url(), e);
+                    }
+                }
+            }
+            anyhow::ensure!(failed == 0, "{} uploads failed", failed);
+            Ok(())
+        }
+        (Some((bucket, prefix)), None) => {
+            let cloud_storage = Gcs::new(spauth, bucket);
+            let dst = Path::new(&args.dst);
+            let mut downloads = Vec::new();
+            if args.recursive {
+                let mut params = GcsListParam::new();
+                params.prefix(&prefix);
+                let objects = cloud_storage.list_objects(&params).await?;
+                for name in objects.into_iter().filter_map(|o| o.name) {
+                    if name.ends_with('/') {
+                        continue;
+                    }
+                    if let Some(relative) = object_relative_path(&prefix, &name)? {
+                        downloads.push((name.clone(), dst.join(relative)));
+                    }
+                }
+            } else {
+                let local = if dst.is_dir() {
+                    dst.join(prefix.rsplit('/').next().unwrap_or(&prefix))
+                } else {
+                    dst.to_path_buf()
+                };
+                downloads.push((prefix, local));
+            }
+            stream::iter(downloads)
+                .map(|(name, local)| {
+                    let cloud_storage = &cloud_storage;
+                    async move {
+                        if let Some(parent) = local.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
+                        cloud_storage
+                            .download_to_file(name.clone(), &local, true)
+                            .await?;
+                        eprintln!("{} -> {}", name, local.display());
+                        Ok::<(), anyhow::Error>(())
+                    }
+                })
+                .buffer_unordered(args.concurrency)
+                .try_collect::<Vec<_>>()
+                .await?;
+            Ok(())
+        }
+        _ => anyhow::bail!("one of src and dst must be gs://bucket/path and the other local path"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_object_name_with_prefix() {
+        assert_eq!(join_object_name("", "a.txt"), "a.txt");
+        assert_eq!(join_object_name("data", "a.txt"), "data/a.txt");
+        assert_eq!(join_object_name("data/", "sub/a.txt"), "data/sub/a.txt");
+        assert_eq!(join_object_name("data//", "a.txt"), "data/a.txt");
+    }
+
+    #[test]
+    fn object_relative_path_follows_segments() {
+        let path = |prefix: &str, name: &str| object_relative_path(prefix, name).unwrap();
+        assert_eq!(path("data", "data/x/y.csv"), Some(PathBuf::from("x/y.csv")));
+        assert_eq!(path("data/", "data/x.csv"), Some(PathBuf::from("x.csv")));
+        assert_eq!(path("", "x/y.csv"), Some(PathBuf::from("x/y.csv")));
+        assert_eq!(path("data", "database/x"), None);
+        // the prefix is the object itself
+        assert_eq!(
+            path("data/x.csv", "data/x.csv"),
+            Some(PathBuf::from("x.csv"))
+        );
+    }
+
+    #[test]
+    fn object_relative_path_rejects_escape() {
+        assert!(object_relative_path("prefix", "prefix/../../.bashrc").is_err());
+        assert!(object_relative_path("prefix", "prefix//etc/passwd").is_err());
+        assert!(object_relative_path("", "/etc/passwd").is_err());
+        assert!(object_relative_path("prefix", "prefix/a/./b").is_ok());
     }
 }
@@ -1,9 +1,11 @@
+mod auth;
 mod bq;
 mod chart;
 mod common;
 mod df;
 mod drive;
 mod gcs;
+mod metadata;
 mod ml;
 #[cfg(feature = "text")]
 mod text;
@@ -11,12 +13,14 @@ mod text;
 use text::{handle as handle_text, TextArgs};
 
 use anyhow::Result;
+use auth::{handle as handle_auth, AuthArgs};
 use bq::{handle as handle_bq, BqArgs};
 use chart::{handle as handle_chart, ChartArgs};
 use clap::{Parser, Subcommand};
 use df::{handle as handle_datafusion, DataFusionArgs};
 use drive::{handle as handle_drive, DriveArgs};
 use gcs::{handle as handle_gcs, GcsArgs};
+use metadata::{handle as handle_metadata, MetadataArgs};
 use ml::{handle as handle_ml, MlArgs};
 
 #[derive(Debug, Subcommand)]
@@ -33,6 +37,10 @@ enum SubCommand {
     Chart(ChartArgs),
     /// Execute Drive APIs
     Drive(DriveArgs),
+    /// Inspect the runtime environment via the metadata server
+    Metadata(MetadataArgs),
+    /// Inspect the credentials
+    Auth(AuthArgs),
     /// Execute Text
     #[cfg(feature = "text")]
     Text(TextArgs),
@@ -55,6 +63,8 @@ async fn main() -> Result<()> {
         SubCommand::Ml(mlargs) => handle_ml(mlargs).await,
         SubCommand::Chart(cargs) => handle_chart(cargs).await,
         SubCommand::Drive(dargs) => handle_drive(dargs).await,
+        SubCommand::Metadata(margs) => handle_metadata(margs).await,
+        SubCommand::Auth(aargs) => handle_auth(aargs).await,
         #[cfg(feature = "text")]
         SubCommand::Text(targs) => handle_text(targs).await,
     }
@@ -0,0 +1,107 @@
+use crate::common::{render, OutputFormat, TableView};
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use gcprs::metadata::{MetadataApi, ServiceAccountInfo};
+use serde::Serialize;
+
+#[derive(Debug, Args)]
+pub struct MetadataArgs {
+    /// Output raw JSON
+    #[clap(short = 'r', long = "raw_json", default_value = "false")]
+    pub raw: bool,
+
+    #[clap(subcommand)]
+    pub metadata_sub_command: MetadataSubCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MetadataSubCommand {
+    /// Show project ID
+    ProjectId,
+
+    /// Show default service account
+    ServiceAccount,
+
+    /// Show access token of the service account
+    Token(TokenArgs),
+}
+
+#[derive(Default, Debug, Args)]
+pub struct TokenArgs {
+    /// Service account email or `default`
+    #[clap(short = 's', long = "service_account", default_value = "default")]
+    service_account: String,
+}
+
+impl TableView for ServiceAccountInfo {
+    fn columns(&self) -> Vec<String> {
+        vec![
+            "email".to_string(),
+            "aliases".to_string(),
+            "scopes".to_string(),
+        ]
+    }
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.email.clone(),
+            self.aliases.join(","),
+            self.scopes.join(","),
+        ]
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AccessToken {
+    token: String,
+    expires_at: String,
+}
+
+impl TableView for AccessToken {
+    fn columns(&self) -> Vec<String> {
+        vec!["token".to_string(), "expires_at".to_string()]
+    }
+
+    fn values(&self) -> Vec<String> {
+        vec![self.token.clone(), self.expires_at.clone()]
+    }
+}
+
+/// Explain that the metadata server is only available on GCP
+fn not_on_gcp(e: anyhow::Error) -> anyhow::Error {
+    anyhow::anyhow!(
+        "{}. the metadata server is available only on GCP(Compute Engine, Cloud Run, etc.)",
+        e
+    )
+}
+
+pub async fn handle(margs: MetadataArgs) -> Result<()> {
+    let api = MetadataApi::new();
+    let format = if margs.raw {
+        OutputFormat::Json
+    } else {
+        OutputFormat::Stdout
+    };
+    match margs.metadata_sub_command {
+        MetadataSubCommand::ProjectId => {
+            let project_id = api.project_id().await.map_err(not_on_gcp)?;
+            println!("{}", project_id);
+            Ok(())
+        }
+        MetadataSubCommand::ServiceAccount => {
+            let info = api.service_account_info().await.map_err(not_on_gcp)?;
+            render(&vec![info], format, false)
+        }
+        MetadataSubCommand::Token(args) => {
+            let (token, expires_at) = api
+                .access_token(&args.service_account)
+                .await
+                .map_err(not_on_gcp)?;
+            let data = AccessToken {
+                token,
+                expires_at: format!("{}", expires_at),
+            };
+            render(&vec![data], format, false)
+        }
+    }
+}
@@ -1,6 +1,6 @@
 pub mod common;
 
-use crate::df::{print_dataframe, register_source, session_context};
+use crate::df::{print_dataframe, register_source, session_context, OutputFormat};
 use anyhow::Result;
 use clap::{Args, Subcommand};
 use datafusion::arrow::array;
@@ -10,7 +10,11 @@ use datafusion::prelude::SessionContext;
 use linfa::prelude::*;
 use linfa::DatasetBase;
 use linfa_clustering::{Dbscan, KMeans};
+use linfa_linear::{FittedLinearRegression, LinearRegression};
+use linfa_reduction::Pca;
 use ndarray::*;
+use rand_xoshiro::rand_core::SeedableRng;
+use rand_xoshiro::Xoshiro256Plus;
 use std::sync::Arc;
 
 #[derive(Debug, Args)]
@@ -25,6 +29,17 @@ pub struct MlArgs {
     #[clap(short = 'i', long = "inputs")]
     pub inputs: Vec<String>,
 
+    /// Authenticate with user application to read `bq://` inputs. otherwise authenticate with
+    /// service account
+    #[clap(short = 'a', long = "auth_user", default_value = "true")]
+    pub auth_user: bool,
+
+    /// Maximum number of rows read from each `bq://` input.
+    ///
+    /// The whole table is loaded into memory unless this is set.
+    #[clap(long = "bq_limit")]
+    pub bq_limit: Option<usize>,
+
     /// Output raw JSON
     #[clap(short = 'j', long = "json", default_value = "false")]
     pub json: bool,
@@ -42,6 +57,12 @@ pub struct MlArgs {
     /// If Output argument file exists, force to remove.
     #[clap(short = 'r', long = "remove", default_value = "false")]
     pub remove: bool,
+
+    /// SQL to make the dataset. Optional.
+    ///
+    /// The query result is used as `t0` table instead of the first input.
+    #[clap(short = 'q', long = "query", default_value = None)]
+    pub query: Option<String>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -50,6 +71,10 @@ pub enum MlSubCommand {
     Dbscan(DbscanArgs),
     /// KMeans
     Kmeans(KmeansArgs),
+    /// Linear Regression
+    LinearRegression(LinearRegressionArgs),
+    /// PCA
+    Pca(PcaArgs),
 }
 
 #[derive(Default, Debug, Args)]
@@ -92,6 +117,52 @@ pub struct KmeansArgs {
     /// output model file name
     #[clap(short = 's', long = "save_model_file", default_value = None)]
     save_model_file: Option<String>,
+
+    /// random seed for the initial centroids. Without it, the result varies run to run.
+    #[clap(long = "seed", default_value = None)]
+    seed: Option<u64>,
+}
+
+#[derive(Default, Debug, Args)]
+pub struct LinearRegressionArgs {
+    /// feature columns
+    #[clap(short = 'f', long = "features")]
+    features: Vec<String>,
+
+    /// target column
+    #[clap(short = 't', long = "target")]
+    target: String,
+
+    /// output the table with `prediction` column
+    #[clap(short = 'p', long = "predict", default_value = "false")]
+    predict: bool,
+
+    /// model file name
+    #[clap(short = 'm', long = "load_model_file", default_value = None)]
+    load_model_file: Option<String>,
+
+    /// output model file name
+    #[clap(short = 's', long = "save_model_file", default_value = None)]
+    save_model_file: Option<String>,
+}
+
+#[derive(Default, Debug, Args)]
+pub struct PcaArgs {
+    /// number of components
+    #[clap(short = 'n', long = "components", default_value = "2")]
+    components: usize,
+
+    /// target columns to reduce
+    #[clap(short = 'c', long = "columns")]
+    columns: Vec<String>,
+}
+
+fn output_format(as_json: bool) -> OutputFormat {
+    if as_json {
+        OutputFormat::Json
+    } else {
+        OutputFormat::Table
+    }
 }
 
 async fn run_kmeans(
@@ -100,16 +171,40 @@ async fn run_kmeans(
     args: KmeansArgs,
     ctx: SessionContext,
 ) -> Result<()> {
-    let mut base_dataset = common::BaseData::new(args.columns);
-    base_dataset.make_dataset(&ctx).await?;
+    let (columns, batch) = kmeans_labels(show_stats, as_json, &args, &ctx).await?;
+
+    let df = common::labeled_dataframe(&ctx, &columns, &["label"], batch).await;
+
+    print_dataframe(df, output_format(as_json)).await?;
+
+    Ok(())
+}
+
+/// Cluster the target columns of `t0` and return the columns and the batch with `label` column.
+async fn kmeans_labels(
+    show_stats: bool,
+    as_json: bool,
+    args: &KmeansArgs,
+    ctx: &SessionContext,
+) -> Result<(Vec<String>, RecordBatch)> {
+    let mut base_dataset = common::BaseData::new(args.columns.clone());
+    let batches = base_dataset.source(ctx).await?;
+    base_dataset.make_dataset(&batches)?;
 
     let dataset_arr = Array::from_vec(base_dataset.base_dataset())
         .into_shape((base_dataset.total_rows(), base_dataset.fields().len()))?;
     let dataset = DatasetBase::from(dataset_arr.clone());
 
-    let model = if let Some(model_file) = args.load_model_file {
+    let model = if let Some(model_file) = args.load_model_file.as_ref() {
         let reader = std::fs::File::open(model_file).expect("Failed to open file");
         serde_json::from_reader(reader).expect("Failed to deserialize model")
+    } else if let Some(seed) = args.seed {
+        let rng = Xoshiro256Plus::seed_from_u64(seed);
+        KMeans::params_with_rng(args.num_clusters, rng)
+            .max_n_iterations(args.max_iterations)
+            .tolerance(args.tolerance)
+            .fit(&dataset)
+            .expect("Kmeans fitted")
     } else {
         KMeans::params(args.num_clusters)
             .max_n_iterations(args.max_iterations)
@@ -121,7 +216,10 @@ async fn run_kmeans(
     let dataset = model.predict(dataset);
     //println!("{:?}", dataset);
     if show_stats {
-        println!("shilhouette score: {}", dataset.silhouette_score()?);
+        common::ClusterStats::new(dataset.targets.iter().map(|v| Some(*v)), false)
+            .silhouette_score(|| Ok(dataset.silhouette_score()?))?
+            .centroids(model.centroids().view())
+            .print(as_json)?;
     }
 
     base_dataset.add_field(Field::new("label", DataType::UInt16, true));
@@ -139,16 +237,22 @@ async fn run_kmeans(
     let batch = RecordBatch::try_new(schema, columns)?;
     //println!("{:?}", batch);
 
-    let df = common::labeled_dataframe(&ctx, base_dataset.columns(), batch).await;
-
-    print_dataframe(df, as_json).await?;
-
-    if let Some(model_file) = args.save_model_file {
+    if let Some(model_file) = args.save_model_file.as_ref() {
         let writer = std::fs::File::create(model_file).expect("Failed to open file");
         serde_json::to_writer(writer, &model).expect("Failed to serialize model");
     }
 
-    Ok(())
+    Ok((base_dataset.columns().clone(), batch))
+}
+
+/// `label` and `is_noise` columns of DBSCAN result. Noise points have null label.
+fn noise_label_columns(targets: &[Option<usize>]) -> Vec<array::ArrayRef> {
+    let label_data: Vec<Option<u32>> = targets.iter().map(|t| t.map(|v| v as u32)).collect();
+    let noise_data: Vec<bool> = targets.iter().map(|t| t.is_none()).collect();
+    vec![
+        Arc::new(array::UInt32Array::from(label_data)),
+        Arc::new(array::BooleanArray::from(noise_data)),
+    ]
 }
 
 async fn run_dbscan(
@@ -158,7 +262,8 @@ async fn run_dbscan(
     ctx: SessionContext,
 ) -> Result<()> {
     let mut base_dataset = common::BaseData::new(args.columns);
-    base_dataset.make_dataset(&ctx).await?;
+    let batches = base_dataset.source(&ctx).await?;
+    base_dataset.make_dataset(&batches)?;
 
     let dataset_arr = Array::from_iter(base_dataset.base_dataset())
         .into_shape((base_dataset.total_rows(), base_dataset.fields().len()))?;
@@ -170,11 +275,14 @@ async fn run_dbscan(
         .unwrap();
     //println!("{:?}", clusters);
     if show_stats {
-        println!("shilhouette score: {}", clusters.silhouette_score()?);
+        common::ClusterStats::new(clusters.targets.iter().copied(), true)
+            .silhouette_score(|| Ok(clusters.silhouette_score()?))?
+            .print(as_json)?;
     }
 
-    // add label column to new table
-    base_dataset.add_field(Field::new("label", DataType::UInt16, true));
+    // add label column to new table. noise points are labeled as null and flagged with `is_noise`
+    base_dataset.add_field(Field::new("label", DataType::UInt32, true));
+    base_dataset.add_field(Field::new("is_noise", DataType::Boolean, false));
 
     let schema = base_dataset.fields_to_schema();
     let dataset_arr_trans = dataset_arr.reversed_axes();
@@ -185,32 +293,162 @@ async fn run_dbscan(
         )))
     }
 
-    // if there is no class assigned, assign an independent class
-    let mut label_data: Vec<u16> = Vec::new();
-    let mut noise = base_dataset.total_rows() + 1;
-    for t in clusters.targets.iter() {
-        if let Some(v) = t {
-            label_data.push(*v as u16)
-        } else {
-            label_data.push(noise as u16);
-            noise += 1;
+    columns.extend(noise_label_columns(&clusters.targets.to_vec()));
+
+    let batch = RecordBatch::try_new(schema, columns)?;
+    //println!("{:?}", batch);
+
+    let df = common::labeled_dataframe(&ctx, base_dataset.columns(), &["label", "is_noise"], batch)
+        .await;
+
+    print_dataframe(df, output_format(as_json)).await?;
+
+    Ok(())
+}
+
+/// Split the dataset into the features and the target, which is the last column.
+fn regression_dataset(
+    base_dataset: &common::BaseData,
+    features: &[String],
+) -> Result<(Array2<f64>, Dataset<f64, f64, Ix1>)> {
+    let num_features = features.len();
+    let dataset_arr = Array::from_vec(base_dataset.base_dataset())
+        .into_shape((base_dataset.total_rows(), base_dataset.fields().len()))?;
+    let records = dataset_arr.slice(s![.., 0..num_features]).to_owned();
+    let targets = dataset_arr.column(num_features).to_owned();
+    let dataset = Dataset::new(records, targets).with_feature_names(features.to_vec());
+    Ok((dataset_arr, dataset))
+}
+
+async fn run_linear_regression(
+    as_json: bool,
+    args: LinearRegressionArgs,
+    ctx: SessionContext,
+) -> Result<()> {
+    let mut columns = args.features.clone();
+    columns.push(args.target.clone());
+    let mut base_dataset = common::BaseData::new(columns);
+    // every row is an observation, so duplicated rows are not grouped for fitting
+    let batches = base_dataset.source_rows(&ctx).await?;
+    base_dataset.make_dataset(&batches)?;
+    let (_, dataset) = regression_dataset(&base_dataset, &args.features)?;
+
+    let model: FittedLinearRegression<f64> = if let Some(model_file) = args.load_model_file {
+        let reader = std::fs::File::open(model_file).expect("Failed to open file");
+        serde_json::from_reader(reader).expect("Failed to deserialize model")
+    } else {
+        LinearRegression::new().fit(&dataset)?
+    };
+
+    let prediction = model.predict(&dataset);
+    println!("intercept: {}", model.intercept());
+    for (name, coef) in args.features.iter().zip(model.params().iter()) {
+        println!("coefficient {}: {}", name, coef);
+    }
+    println!("r2: {}", prediction.r2(&dataset)?);
+
+    if args.predict {
+        // the distinct rows are joined to the original table
+        let batches = base_dataset.source(&ctx).await?;
+        base_dataset.make_dataset(&batches)?;
+        let (dataset_arr, dataset) = regression_dataset(&base_dataset, &args.features)?;
+        let prediction = model.predict(&dataset);
+
+        base_dataset.add_field(Field::new("prediction", DataType::Float64, true));
+
+        let schema = base_dataset.fields_to_schema();
+        let dataset_arr_trans = dataset_arr.reversed_axes();
+        let mut columns: Vec<array::ArrayRef> = Vec::new();
+        for n in 0..base_dataset.columns().len() {
+            columns.push(Arc::new(array::Float64Array::from(
+                dataset_arr_trans.slice(s!(n, ..)).to_vec(),
+            )))
         }
+        columns.push(Arc::new(array::Float64Array::from(prediction.to_vec())));
+        let batch = RecordBatch::try_new(schema, columns)?;
+
+        let df =
+            common::labeled_dataframe(&ctx, base_dataset.columns(), &["prediction"], batch).await;
+
+        print_dataframe(df, output_format(as_json)).await?;
     }
-    columns.push(Arc::new(array::UInt16Array::from(label_data)));
 
+    if let Some(model_file) = args.save_model_file {
+        let writer = std::fs::File::create(model_file).expect("Failed to open file");
+        serde_json::to_writer(writer, &model).expect("Failed to serialize model");
+    }
+
+    Ok(())
+}
+
+async fn run_pca(
+    show_stats: bool,
+    as_json: bool,
+    args: PcaArgs,
+    ctx: SessionContext,
+) -> Result<()> {
+    let mut base_dataset = common::BaseData::new(args.columns);
+    // every row is an observation, so duplicated rows are not grouped for fitting
+    let batches = base_dataset.source_rows(&ctx).await?;
+    base_dataset.make_dataset(&batches)?;
+
+    let dataset_arr = Array::from_vec(base_dataset.base_dataset())
+        .into_shape((base_dataset.total_rows(), base_dataset.fields().len()))?;
+    let dataset = DatasetBase::from(dataset_arr);
+
+    let pca = Pca::params(args.components).fit(&dataset)?;
+    if show_stats {
+        println!(
+            "explained variance ratio: {:?}",
+            pca.explained_variance_ratio().to_vec()
+        );
+    }
+
+    // the distinct rows are joined to the original table
+    let batches = base_dataset.source(&ctx).await?;
+    base_dataset.make_dataset(&batches)?;
+    let dataset_arr = Array::from_vec(base_dataset.base_dataset())
+        .into_shape((base_dataset.total_rows(), base_dataset.fields().len()))?;
+    let embedding: Array2<f64> = pca.predict(&dataset_arr);
+
+    // add component columns to new table
+    let components: Vec<String> = (1..=args.components).map(|n| format!("pc{n}")).collect();
+    for c in components.iter() {
+        base_dataset.add_field(Field::new(c, DataType::Float64, true));
+    }
+
+    let schema = base_dataset.fields_to_schema();
+    let dataset_arr_trans = dataset_arr.reversed_axes();
+    let mut columns: Vec<array::ArrayRef> = Vec::new();
+    for n in 0..base_dataset.columns().len() {
+        columns.push(Arc::new(array::Float64Array::from(
+            dataset_arr_trans.slice(s!(n, ..)).to_vec(),
+        )))
+    }
+    for n in 0..args.components {
+        columns.push(Arc::new(array::Float64Array::from(
+            embedding.column(n).to_vec(),
+        )))
+    }
     let batch = RecordBatch::try_new(schema, columns)?;
-    //println!("{:?}", batch);
 
-    let df = common::labeled_dataframe(&ctx, base_dataset.columns(), batch).await;
+    let labels: Vec<&str> = components.iter().map(|c| c.as_str()).collect();
+    let df = common::labeled_dataframe(&ctx, base_dataset.columns(), &labels, batch).await;
 
-    print_dataframe(df, as_json).await?;
+    print_dataframe(df, output_format(as_json)).await?;
 
     Ok(())
 }
 
 pub async fn handle(mlargs: MlArgs) -> Result<()> {
     let ctx = session_context();
-    register_source(&ctx, mlargs.inputs).await?;
+    register_source(&ctx, mlargs.inputs, mlargs.auth_user, mlargs.bq_limit).await?;
+
+    if let Some(query) = mlargs.query {
+        let df = ctx.sql(&query).await?;
+        ctx.deregister_table("t0")?;
+        ctx.register_table("t0", df.into_view())?;
+    }
 
     match mlargs.ml_sub_command {
         MlSubCommand::Kmeans(args) => {
@@ -233,5 +471,160 @@ pub async fn handle(mlargs: MlArgs) -> Result<()> {
 
             Ok(())
         }
+        MlSubCommand::LinearRegression(args) => {
+            anyhow::ensure!(
+                0 < args.features.len(),
+                "no features specified. please set feature column with '--features' option."
+            );
+
+            run_linear_regression(mlargs.json, args, ctx).await?;
+
+            Ok(())
+        }
+        MlSubCommand::Pca(args) => {
+            anyhow::ensure!(
+                0 < args.columns.len(),
+                "no columns specified. please set target column with '--columns' option."
+            );
+            anyhow::ensure!(
+                args.components <= args.columns.len(),
+                "components must be less than or equal to the number of columns."
+            );
+
+            run_pca(mlargs.stats, mlargs.json, args, ctx).await?;
+
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::Array;
+
+    #[test]
+    fn noise_label_does_not_collide_with_cluster() {
+        // more rows than u16::MAX, where the noise labels used to wrap around
+        let targets: Vec<Option<usize>> = (0..70_000)
+            .map(|i| if i % 2 == 0 { None } else { Some(i % 3) })
+            .collect();
+        let columns = noise_label_columns(&targets);
+        let labels = columns[0]
+            .as_any()
+            .downcast_ref::<array::UInt32Array>()
+            .unwrap();
+        let noise = columns[1]
+            .as_any()
+            .downcast_ref::<array::BooleanArray>()
+            .unwrap();
+        assert_eq!(labels.len(), targets.len());
+        for (i, t) in targets.iter().enumerate() {
+            match t {
+                Some(v) => {
+                    assert_eq!(labels.value(i), *v as u32);
+                    assert!(!noise.value(i));
+                }
+                None => {
+                    assert!(labels.is_null(i));
+                    assert!(noise.value(i));
+                }
+            }
+        }
+    }
+
+    /// (x, y, label) rows sorted by the coordinates
+    fn labeled_points(batch: &RecordBatch) -> Vec<(String, String, u16)> {
+        let column = |name: &str| {
+            batch
+                .column_by_name(name)
+                .unwrap()
+                .as_any()
+                .downcast_ref::<array::Float64Array>()
+                .unwrap()
+                .clone()
+        };
+        let (xs, ys) = (column("x_"), column("y_"));
+        let labels = batch
+            .column_by_name("label")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<array::UInt16Array>()
+            .unwrap();
+        let mut points: Vec<(String, String, u16)> = (0..batch.num_rows())
+            .map(|i| {
+                (
+                    xs.value(i).to_string(),
+                    ys.value(i).to_string(),
+                    labels.value(i),
+                )
+            })
+            .collect();
+        points.sort();
+        points
+    }
+
+    #[tokio::test]
+    async fn kmeans_with_seed_is_reproducible() -> Result<()> {
+        // single partition keeps the row order of the source query
+        let ctx = SessionContext::new_with_config(
+            datafusion::prelude::SessionConfig::new().with_target_partitions(1),
+        );
+        let schema = Arc::new(datafusion::arrow::datatypes::Schema::new(vec![
+            Field::new("x", DataType::Float64, false),
+            Field::new("y", DataType::Float64, false),
+        ]));
+        // three groups around x = 0, 20 and 40
+        let xs: Vec<f64> = (0..30)
+            .map(|i| (i % 10) as f64 + (i / 10) as f64 * 20.0)
+            .collect();
+        let ys: Vec<f64> = (0..30).map(|i| ((i * 7) % 5) as f64).collect();
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(array::Float64Array::from(xs)),
+                Arc::new(array::Float64Array::from(ys)),
+            ],
+        )?;
+        ctx.register_batch("t0", batch)?;
+
+        let args = KmeansArgs {
+            tolerance: 1e-3,
+            max_iterations: 100,
+            num_clusters: 3,
+            columns: vec!["x".to_string(), "y".to_string()],
+            seed: Some(42),
+            ..Default::default()
+        };
+        let (_, first) = kmeans_labels(false, false, &args, &ctx).await?;
+        let (_, second) = kmeans_labels(false, false, &args, &ctx).await?;
+        assert_eq!(labeled_points(&first), labeled_points(&second));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn source_rows_keeps_duplicated_observations() -> Result<()> {
+        let ctx = SessionContext::new();
+        let schema = Arc::new(datafusion::arrow::datatypes::Schema::new(vec![
+            Field::new("x", DataType::Float64, false),
+            Field::new("y", DataType::Float64, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(array::Float64Array::from(vec![1.0, 1.0, 2.0])),
+                Arc::new(array::Float64Array::from(vec![3.0, 3.0, 5.0])),
+            ],
+        )?;
+        ctx.register_batch("t0", batch)?;
+
+        let mut base_dataset = common::BaseData::new(vec!["x".to_string(), "y".to_string()]);
+        let batches = base_dataset.source_rows(&ctx).await?;
+        base_dataset.make_dataset(&batches)?;
+        assert_eq!(base_dataset.total_rows(), 3);
+        let batches = base_dataset.source(&ctx).await?;
+        base_dataset.make_dataset(&batches)?;
+        assert_eq!(base_dataset.total_rows(), 2);
+        Ok(())
     }
 }
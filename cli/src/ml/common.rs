@@ -1,8 +1,12 @@
+use crate::df::run_query;
 use anyhow::Result;
 use datafusion::arrow::array;
 use datafusion::arrow::datatypes::{DataType, Field, Schema};
 use datafusion::arrow::record_batch::RecordBatch;
 use datafusion::prelude::{DataFrame, SessionContext};
+use ndarray::ArrayView2;
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 macro_rules! get_value {
@@ -84,13 +88,23 @@ impl BaseData {
         self.total_rows = 0;
     }
 
-    pub async fn make_dataset(&mut self, ctx: &SessionContext) -> Result<()> {
-        self.clear();
-
+    /// Query result of the target columns in `t0`. Duplicated rows are grouped into one.
+    pub async fn source(&self, ctx: &SessionContext) -> Result<Vec<RecordBatch>> {
         let query_target = self.columns.join(",");
         let sql = format!("select {query_target} from t0 group by {query_target}");
-        let df = ctx.sql(&sql).await?;
-        let batches = df.collect().await?;
+        run_query(ctx, &sql).await
+    }
+
+    /// Query result of the target columns in `t0`. Duplicated rows are kept as observations.
+    pub async fn source_rows(&self, ctx: &SessionContext) -> Result<Vec<RecordBatch>> {
+        let query_target = self.columns.join(",");
+        let sql = format!("select {query_target} from t0");
+        run_query(ctx, &sql).await
+    }
+
+    pub fn make_dataset(&mut self, batches: &[RecordBatch]) -> Result<()> {
+        self.clear();
+
         for (i, batch) in batches.iter().enumerate() {
             let schema = batch.schema();
             self.total_rows += batch.num_rows();
@@ -123,9 +137,18 @@ impl BaseData {
     }
 }
 
+/// Join the original table `t0` and the result batch.
+///
+/// # Arguments
+///
+/// * `ctx` - session context which `t0` is registered
+/// * `columns` - join key columns. The batch must have them with `_` suffix.
+/// * `labels` - columns in the batch to be added to the original table
+/// * `batch` - result batch
 pub async fn labeled_dataframe(
     ctx: &SessionContext,
     columns: &Vec<String>,
+    labels: &[&str],
     batch: RecordBatch,
 ) -> DataFrame {
     // add new label table
@@ -135,10 +158,105 @@ pub async fn labeled_dataframe(
         .iter()
         .map(|c| format!("t0.{c} = t0_.{c}_"))
         .collect();
+    let label_str: Vec<String> = labels.iter().map(|l| format!("t0_.{l} as {l}")).collect();
     let sql = format!(
-        "select t0.*, t0_.label as label from t0 join t0_ on {}",
+        "select t0.*, {} from t0 join t0_ on {}",
+        label_str.join(", "),
         join_str.join(" and ")
     );
     // join original table and label table to add a clustered label
     ctx.sql(&sql).await.expect("join query failure")
 }
+
+/// Summary of a cluster
+#[derive(Debug, Serialize)]
+pub struct ClusterSummary {
+    pub label: usize,
+    pub count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub centroid: Option<Vec<f64>>,
+}
+
+/// Statistics of the clustering result
+#[derive(Debug, Serialize)]
+pub struct ClusterStats {
+    /// `None` if there are less than 2 clusters
+    pub silhouette_score: Option<f64>,
+    pub clusters: Vec<ClusterSummary>,
+    /// The number of noise points. DBSCAN only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub noise: Option<usize>,
+}
+
+impl ClusterStats {
+    /// Count points per cluster. `None` label is counted as noise.
+    ///
+    /// # Arguments
+    ///
+    /// * `labels` - cluster label of each point
+    /// * `with_noise` - whether the clustering has noise points
+    pub fn new(labels: impl Iterator<Item = Option<usize>>, with_noise: bool) -> Self {
+        let mut counts = BTreeMap::new();
+        let mut noise = 0;
+        for label in labels {
+            match label {
+                Some(l) => *counts.entry(l).or_insert(0) += 1,
+                None => noise += 1,
+            }
+        }
+        ClusterStats {
+            silhouette_score: None,
+            clusters: counts
+                .into_iter()
+                .map(|(label, count)| ClusterSummary {
+                    label,
+                    count,
+                    centroid: None,
+                })
+                .collect(),
+            noise: if with_noise { Some(noise) } else { None },
+        }
+    }
+
+    /// Set the silhouette score. It is computed only when there are 2 clusters or more.
+    pub fn silhouette_score(&mut self, score: impl FnOnce() -> Result<f64>) -> Result<&mut Self> {
+        if 1 < self.clusters.len() {
+            self.silhouette_score = Some(score()?);
+        }
+        Ok(self)
+    }
+
+    /// Set centroids. The row index is the cluster label.
+    pub fn centroids(&mut self, centroids: ArrayView2<f64>) -> &mut Self {
+        for cluster in self.clusters.iter_mut() {
+            if cluster.label < centroids.nrows() {
+                cluster.centroid = Some(centroids.row(cluster.label).to_vec());
+            }
+        }
+        self
+    }
+
+    pub fn print(&self, as_json: bool) -> Result<()> {
+        if as_json {
+            println!("{}", serde_json::to_string(self)?);
+            return Ok(());
+        }
+        match self.silhouette_score {
+            Some(score) => println!("silhouette score: {}", score),
+            None => println!("silhouette score: n/a (less than 2 clusters)"),
+        }
+        for cluster in self.clusters.iter() {
+            match &cluster.centroid {
+                Some(centroid) => println!(
+                    "cluster {}: {} points, centroid {:?}",
+                    cluster.label, cluster.count, centroid
+                ),
+                None => println!("cluster {}: {} points", cluster.label, cluster.count),
+            }
+        }
+        if let Some(noise) = self.noise {
+            println!("noise: {} points", noise);
+        }
+        Ok(())
+    }
+}
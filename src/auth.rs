@@ -13,10 +13,12 @@ use oauth2::{
     authenticator::ApplicationDefaultCredentialsTypes, ApplicationDefaultCredentialsAuthenticator,
     ApplicationDefaultCredentialsFlowOpts,
 };
-use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::{connect::HttpConnector, Client};
+use serde::{Deserialize, Serialize};
 use std::env;
 use std::future::Future;
 use std::pin::Pin;
+use std::time::Duration;
 
 pub type HttpsConnector = hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector>;
 
@@ -25,15 +27,45 @@ pub struct GcpAuth {
     auth: Authenticator<HttpsConnector>,
 }
 
-pub fn new_client() -> Client<HttpsConnector, Body> {
-    Client::builder(
-        hyper_util::rt::TokioExecutor::new()
-    ).build(
+/// HTTP client used by the API clients
+pub type HttpClient = Client<HttpsConnector, Body>;
+
+/// Settings of the HTTP client
+///
+/// Only the connect timeout is configurable. There is no request timeout or proxy setting.
+#[derive(Clone, Debug, Default)]
+pub struct ClientConfig {
+    /// Timeout to establish a connection
+    pub connect_timeout: Option<Duration>,
+}
+
+impl ClientConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn connect_timeout(&mut self, v: Duration) -> &mut Self {
+        self.connect_timeout = Some(v);
+        self
+    }
+}
+
+pub fn new_client() -> HttpClient {
+    new_client_with(&ClientConfig::default())
+}
+
+/// Build HTTP client with the settings
+pub fn new_client_with(config: &ClientConfig) -> HttpClient {
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    http.set_connect_timeout(config.connect_timeout);
+    Client::builder(hyper_util::rt::TokioExecutor::new()).build(
         hyper_rustls::HttpsConnectorBuilder::new()
-            .with_native_roots().unwrap()
+            .with_native_roots()
+            .unwrap()
             .https_or_http()
             .enable_http1()
-            .build(),
+            .wrap_connector(http),
     )
 }
 
@@ -42,7 +74,7 @@ pub fn new_client() -> Client<HttpsConnector, Body> {
 /// when the browser did not open for example, the user still see's the URL.
 async fn browser_user_url(url: &str, need_code: bool) -> Result<String, String> {
     if webbrowser::open(url).is_ok() {
-        println!("webbrowser was successfully opened.");
+        tracing::info!("webbrowser was successfully opened.");
     }
     let def_delegate = DefaultInstalledFlowDelegate;
     def_delegate.present_user_url(url, need_code).await
@@ -67,11 +99,58 @@ impl InstalledFlowDelegate for InstalledFlowBrowserDelegate {
     }
 }
 
+const GOOGLE_TOKEN_INFO_URL: &str = "https://oauth2.googleapis.com/tokeninfo";
+pub const CLOUD_PLATFORM_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+/// Scope to get the email of the user. Needed for the email of the user token info.
+pub const USERINFO_EMAIL_SCOPE: &str = "https://www.googleapis.com/auth/userinfo.email";
+
+/// Access token information from the tokeninfo endpoint
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TokenInfo {
+    /// Email of the identity. This is not set if the token does not have the email scope.
+    pub email: Option<String>,
+    /// Space separated scopes of the token
+    pub scope: Option<String>,
+    /// Seconds until the token expires
+    pub expires_in: Option<String>,
+}
+
 impl GcpAuth {
     pub fn authenticator(&self) -> Authenticator<HttpsConnector> {
         self.auth.clone()
     }
 
+    /// Get the identity and the scopes of the access token.
+    ///
+    /// The token for the cloud-platform scope is inspected with the tokeninfo endpoint.
+    pub async fn token_info(&self) -> Result<TokenInfo> {
+        self.token_info_with_scopes(&[CLOUD_PLATFORM_SCOPE]).await
+    }
+
+    /// Get the identity and the scopes of the access token for the scopes.
+    ///
+    /// The email of the user is set only if the scopes have `USERINFO_EMAIL_SCOPE`.
+    pub async fn token_info_with_scopes(&self, scopes: &[&str]) -> Result<TokenInfo> {
+        let token = self.auth.token(scopes).await?;
+        let uri = format!(
+            "{}?access_token={}",
+            GOOGLE_TOKEN_INFO_URL,
+            token.token().unwrap_or_default()
+        )
+        .parse()?;
+        let resp = new_client().get(uri).await?;
+        let status = resp.status();
+        let bytes = resp.into_body().boxed().collect().await?.to_bytes();
+        if status != hyper::StatusCode::OK {
+            anyhow::bail!(
+                "tokeninfo request failure. status: {}, body: {}",
+                status,
+                String::from_utf8_lossy(&bytes)
+            )
+        }
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
     /// Authenticate with service account.
     ///
     /// If there is `GOOGLE_APPLICATION_CREDENTIALS` in environment variables, use it first. Unless
@@ -128,14 +207,12 @@ impl GcpAuth {
             .service_accounts_generate_id_token(req, &name)
             .doit()
             .await;
-        println!("{:?}", result);
         match result {
             Ok((_, response)) => {
-                println!("{:?}", response);
                 return response.token.map(|t| t.clone());
             }
             Err(e) => {
-                println!("{:?}", e);
+                tracing::error!("{:?}", e);
             }
         }
         None
@@ -181,7 +258,7 @@ pub async fn verify_token(token: &String) -> Result<()> {
 
     let resp = client.get(uri).await?;
     if resp.status() != hyper::StatusCode::OK {
-        println!("resp: {:?}", resp);
+        tracing::debug!("resp: {:?}", resp);
         anyhow::bail!("Access to secret api failure")
     }
 
@@ -208,8 +285,8 @@ pub async fn verify_token(token: &String) -> Result<()> {
             &jwt::DecodingKey::from_rsa_pem(secret.to_string().as_bytes())?,
             &validation,
         )?;
-        println!("{:?}", token_message);
-        println!(
+        tracing::debug!("{:?}", token_message);
+        tracing::debug!(
             "{:?}, {:?}",
             get_iat(&token_message.claims),
             get_exp(&token_message.claims)
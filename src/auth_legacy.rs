@@ -22,13 +22,27 @@ pub struct GcpAuth {
     auth: Authenticator<HttpsConnector>,
 }
 
-pub fn new_client() -> hyper::Client<HttpsConnector> {
+/// HTTP client used by the API clients
+pub type HttpClient = hyper::Client<HttpsConnector>;
+
+pub use crate::auth::ClientConfig;
+
+pub fn new_client() -> HttpClient {
+    new_client_with(&ClientConfig::default())
+}
+
+/// Build HTTP client with the settings
+pub fn new_client_with(config: &ClientConfig) -> HttpClient {
+    let mut http = hyper::client::connect::HttpConnector::new();
+    http.enforce_http(false);
+    http.set_connect_timeout(config.connect_timeout);
     hyper::Client::builder().build(
         hyper_rustls::HttpsConnectorBuilder::new()
-            .with_native_roots().unwrap()
+            .with_native_roots()
+            .unwrap()
             .https_only()
             .enable_http1()
-            .build(),
+            .wrap_connector(http),
     )
 }
 
@@ -37,7 +51,7 @@ pub fn new_client() -> hyper::Client<HttpsConnector> {
 /// when the browser did not open for example, the user still see's the URL.
 async fn browser_user_url(url: &str, need_code: bool) -> Result<String, String> {
     if webbrowser::open(url).is_ok() {
-        println!("webbrowser was successfully opened.");
+        tracing::info!("webbrowser was successfully opened.");
     }
     let def_delegate = DefaultInstalledFlowDelegate;
     def_delegate.present_user_url(url, need_code).await
@@ -152,7 +166,7 @@ pub async fn verify_token(token: &String) -> Result<()> {
 
     let resp = client.get(uri).await?;
     if resp.status() != hyper::StatusCode::OK {
-        println!("resp: {:?}", resp);
+        tracing::debug!("resp: {:?}", resp);
         anyhow::bail!("Access to secret api failure")
     }
 
@@ -179,8 +193,8 @@ pub async fn verify_token(token: &String) -> Result<()> {
             &jwt::DecodingKey::from_rsa_pem(secret.to_string().as_bytes())?,
             &validation,
         )?;
-        println!("{:?}", token_message);
-        println!(
+        tracing::debug!("{:?}", token_message);
+        tracing::debug!(
             "{:?}, {:?}",
             get_iat(&token_message.claims),
             get_exp(&token_message.claims)
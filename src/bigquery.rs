@@ -1,26 +1,37 @@
 use crate::auth_legacy as auth;
+use crate::common::error::{is_retryable_status, BadRequest};
+use crate::common::{explode_row, flatten_row, RetryPolicy};
 use bigquery::api::{
-    Job, JobConfiguration, JobConfigurationQuery, JsonObject, JsonValue, QueryRequest, Table,
-    TableCell, TableDataInsertAllRequest, TableDataInsertAllRequestRows, TableFieldSchema,
-    TableReference, TableRow, TableSchema,
+    DatasetAccess, Job, JobConfiguration, JobConfigurationLoad, JobConfigurationQuery,
+    JobReference, JsonObject, JsonValue, QueryRequest, Table, TableCell, TableDataInsertAllRequest,
+    TableDataInsertAllRequestRows, TableFieldSchema, TableReference, TableRow, TableSchema,
 };
 use bigquery::{Bigquery, Error, Result as GcpResult, hyper, hyper_rustls};
 use chrono::prelude::*;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
 use google_bigquery2 as bigquery;
 
 use anyhow;
 use anyhow::Result;
 use async_recursion::async_recursion;
 use rayon::prelude::*;
+use serde::de::DeserializeOwned;
 use serde::ser::{Serialize as Serialize1, SerializeMap, SerializeSeq, Serializer};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Map, Value};
 use std::collections::HashMap;
-use std::time::Duration;
-use std::{string, thread};
+use std::io::Write;
 use std::convert::*;
+use std::fmt;
+use std::str::FromStr;
+use std::string;
 use uuid::Uuid;
 
+#[cfg(feature = "arrow")]
+mod record_batch;
+#[cfg(feature = "arrow")]
+pub use record_batch::to_record_batch;
+
 /// Project ID
 type ProjectId = String;
 
@@ -30,6 +41,9 @@ type DatasetId = String;
 /// Table ID
 type TableId = String;
 
+/// Error reason when the query exceeds `maximum_bytes_billed`
+const BYTES_BILLED_LIMIT_EXCEEDED: &str = "bytesBilledLimitExceeded";
+
 pub struct Bq {
     /// BigQuery API endpoint
     api: Bigquery<auth::HttpsConnector>,
@@ -37,6 +51,12 @@ pub struct Bq {
     /// GCP Project ID
     project: ProjectId,
     max_data: usize,
+
+    /// Location where the jobs run. It must be same as the dataset's location.
+    location: Option<String>,
+
+    /// Retry policy of the API calls
+    retry_policy: RetryPolicy,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -50,6 +70,8 @@ pub struct BqProject {
 pub struct BqListParam {
     max_results: Option<u32>,
     page_token: Option<String>,
+    num_result_limit: Option<usize>,
+    fields: Option<Vec<String>>,
 }
 
 impl BqListParam {
@@ -57,9 +79,39 @@ impl BqListParam {
         BqListParam {
             max_results: Default::default(),
             page_token: Default::default(),
+            num_result_limit: Default::default(),
+            fields: Default::default(),
+        }
+    }
+
+    /// Partial response fields of `list_dataset` and `list_tables`. e.g. `tables/id`
+    ///
+    /// This overrides the default minimal projection. `nextPageToken` is always requested.
+    pub fn fields(&mut self, fields: Vec<&str>) -> &mut Self {
+        self.fields = Some(fields.iter().map(|f| f.to_string()).collect());
+        self
+    }
+
+    /// Value of the `fields` request parameter
+    fn fields_param(&self, default: &str) -> String {
+        match &self.fields {
+            Some(fields) => {
+                let mut fields = fields.clone();
+                if !fields.iter().any(|f| f == "nextPageToken") {
+                    fields.push("nextPageToken".to_string());
+                }
+                fields.join(",")
+            }
+            None => default.to_string(),
         }
     }
 
+    /// Maximum number of rows fetched across pages
+    pub fn num_result_limit(&mut self, limit: usize) -> &mut Self {
+        self.num_result_limit = Some(limit);
+        self
+    }
+
     pub fn max_results(&mut self, max_results: u32) -> &mut Self {
         self.max_results = Some(max_results);
         self
@@ -136,6 +188,137 @@ pub enum WriteDisposition {
     Empty,
 }
 
+impl WriteDisposition {
+    fn as_str(&self) -> &str {
+        match self {
+            WriteDisposition::Empty => "WRITE_EMPTY",
+            WriteDisposition::Append => "WRITE_APPEND",
+            WriteDisposition::Truncate => "WRITE_TRUNCATE",
+        }
+    }
+}
+
+/// File format of the load job source
+#[derive(Clone, Debug)]
+pub enum SourceFormat {
+    Csv,
+    Json,
+    Avro,
+    Parquet,
+    Orc,
+}
+
+impl SourceFormat {
+    fn as_str(&self) -> &str {
+        match self {
+            SourceFormat::Csv => "CSV",
+            SourceFormat::Json => "NEWLINE_DELIMITED_JSON",
+            SourceFormat::Avro => "AVRO",
+            SourceFormat::Parquet => "PARQUET",
+            SourceFormat::Orc => "ORC",
+        }
+    }
+
+    /// Returns true if the file carries its own schema
+    fn is_self_describing(&self) -> bool {
+        matches!(
+            self,
+            SourceFormat::Avro | SourceFormat::Parquet | SourceFormat::Orc
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BqLoadParam {
+    source_uris: Vec<String>,
+    table_ref: TableReference,
+    source_format: SourceFormat,
+    schema: Option<TableSchema>,
+    autodetect: bool,
+    write_disposition: WriteDisposition,
+    skip_leading_rows: Option<i32>,
+}
+
+impl BqLoadParam {
+    /// # Arguments
+    ///
+    /// * `project` - project of the destination table
+    /// * `dataset` - dataset of the destination table
+    /// * `table` - destination table
+    /// * `source_uris` - GCS URIs like `gs://bucket/path/*.parquet`
+    /// * `source_format` - file format of the sources
+    pub fn new(
+        project: &str,
+        dataset: &str,
+        table: &str,
+        source_uris: Vec<&str>,
+        source_format: SourceFormat,
+    ) -> Self {
+        let mut table_ref = TableReference::default();
+        table_ref.project_id = Some(project.to_string());
+        table_ref.dataset_id = Some(dataset.to_string());
+        table_ref.table_id = Some(table.to_string());
+        BqLoadParam {
+            source_uris: source_uris.iter().map(|u| u.to_string()).collect(),
+            table_ref,
+            source_format,
+            schema: None,
+            autodetect: false,
+            write_disposition: WriteDisposition::Empty,
+            skip_leading_rows: None,
+        }
+    }
+
+    /// Set the table schema. This is optional for Avro, Parquet and ORC.
+    pub fn schema<T: BqSchemaBuilder>(&mut self) -> &mut Self {
+        self.schema = Some(TableSchema {
+            fields: Some(
+                T::bq_schema()
+                    .iter()
+                    .map(|s| s.to_table_field_schema())
+                    .collect(),
+            ),
+        });
+        self
+    }
+
+    /// Let BigQuery infer the schema of CSV and JSON sources.
+    pub fn autodetect(&mut self, autodetect: bool) -> &mut Self {
+        self.autodetect = autodetect;
+        self
+    }
+
+    pub fn write_disposition(&mut self, write_disposition: WriteDisposition) -> &mut Self {
+        self.write_disposition = write_disposition;
+        self
+    }
+
+    /// Number of header rows to skip in CSV sources
+    pub fn skip_leading_rows(&mut self, rows: i32) -> &mut Self {
+        self.skip_leading_rows = Some(rows);
+        self
+    }
+
+    fn to_load_config(&self) -> Result<JobConfigurationLoad> {
+        anyhow::ensure!(
+            self.schema.is_some() || self.autodetect || self.source_format.is_self_describing(),
+            "schema or autodetect is necessary for {} source",
+            self.source_format.as_str()
+        );
+        let mut req = JobConfigurationLoad::default();
+        req.source_uris = Some(self.source_uris.clone());
+        req.source_format = Some(self.source_format.as_str().to_string());
+        req.destination_table = Some(self.table_ref.clone());
+        req.schema = self.schema.clone();
+        if self.autodetect {
+            req.autodetect = Some(true);
+        }
+        req.write_disposition = Some(self.write_disposition.as_str().to_string());
+        req.skip_leading_rows = self.skip_leading_rows;
+        Ok(req)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum JobPriority {
     Interactive,
@@ -150,6 +333,7 @@ pub struct BqQueryToTableParam {
     dry_run: bool,
     priority: JobPriority,
     write_disposition: WriteDisposition,
+    maximum_bytes_billed: Option<i64>,
 }
 
 impl BqQueryToTableParam {
@@ -165,6 +349,7 @@ impl BqQueryToTableParam {
             dry_run: false,
             priority: JobPriority::Interactive,
             write_disposition: WriteDisposition::Empty,
+            maximum_bytes_billed: None,
         }
     }
 
@@ -183,6 +368,15 @@ impl BqQueryToTableParam {
         self
     }
 
+    /// Fail the query if it would bill more bytes than this limit.
+    ///
+    /// The limit is checked while the job is running, so the error is returned by
+    /// `wait_job_complete` or `query_to_table_and_stream`.
+    pub fn maximum_bytes_billed(&mut self, bytes: i64) -> &mut Self {
+        self.maximum_bytes_billed = Some(bytes);
+        self
+    }
+
     fn to_query_config(&self) -> JobConfigurationQuery {
         let mut req = JobConfigurationQuery::default();
         req.query = Some(self.query.clone());
@@ -191,12 +385,9 @@ impl BqQueryToTableParam {
             JobPriority::Batch => Some(String::from("BATCH")),
             JobPriority::Interactive => Some(String::from("INTERACTIVE")),
         };
-        req.write_disposition = match self.write_disposition {
-            WriteDisposition::Empty => Some(String::from("WRITE_EMPTY")),
-            WriteDisposition::Append => Some(String::from("WRITE_APPEND")),
-            WriteDisposition::Truncate => Some(String::from("WRITE_TRUNCATE")),
-        };
+        req.write_disposition = Some(self.write_disposition.as_str().to_string());
         req.use_legacy_sql = Some(self.use_legacy_sql);
+        req.maximum_bytes_billed = self.maximum_bytes_billed;
         req
     }
 }
@@ -208,6 +399,8 @@ pub struct BqQueryParam {
     max_results: u32,
     num_result_limit: Option<usize>,
     dry_run: bool,
+    maximum_bytes_billed: Option<i64>,
+    timeout_ms: Option<u32>,
 }
 
 impl BqQueryParam {
@@ -218,6 +411,8 @@ impl BqQueryParam {
             max_results: 1000,
             num_result_limit: None,
             dry_run: false,
+            maximum_bytes_billed: None,
+            timeout_ms: None,
         }
     }
 
@@ -241,12 +436,26 @@ impl BqQueryParam {
         self
     }
 
+    /// Fail the query if it would bill more bytes than this limit.
+    pub fn maximum_bytes_billed(&mut self, bytes: i64) -> &mut Self {
+        self.maximum_bytes_billed = Some(bytes);
+        self
+    }
+
+    /// How long to wait for the query to complete in the first response.
+    pub fn timeout_ms(&mut self, timeout_ms: u32) -> &mut Self {
+        self.timeout_ms = Some(timeout_ms);
+        self
+    }
+
     fn to_query_request(&self) -> QueryRequest {
         let mut req = QueryRequest::default();
         req.query = Some(self.query.clone());
         req.max_results = Some(self.max_results);
         req.use_legacy_sql = Some(self.use_legacy_sql);
         req.dry_run = Some(self.dry_run);
+        req.maximum_bytes_billed = self.maximum_bytes_billed;
+        req.timeout_ms = self.timeout_ms;
         req
     }
 }
@@ -266,6 +475,59 @@ impl BqDataset {
     }
 }
 
+/// Access entry of the dataset
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BqDatasetAccess {
+    pub role: Option<String>,
+    /// The kind of the entity like `userByEmail`, `groupByEmail`, `domain`, `specialGroup`, `iamMember` and `view`
+    pub entity_type: String,
+    pub entity: String,
+}
+
+impl BqDatasetAccess {
+    fn from_dataset_access(access: &DatasetAccess) -> Option<Self> {
+        let (entity_type, entity) = if let Some(user) = &access.user_by_email {
+            ("userByEmail", user.clone())
+        } else if let Some(group) = &access.group_by_email {
+            ("groupByEmail", group.clone())
+        } else if let Some(domain) = &access.domain {
+            ("domain", domain.clone())
+        } else if let Some(special) = &access.special_group {
+            ("specialGroup", special.clone())
+        } else if let Some(member) = &access.iam_member {
+            ("iamMember", member.clone())
+        } else if let Some(view) = &access.view {
+            (
+                "view",
+                format!(
+                    "{}.{}.{}",
+                    view.project_id.as_ref().unwrap_or(&"".to_string()),
+                    view.dataset_id.as_ref().unwrap_or(&"".to_string()),
+                    view.table_id.as_ref().unwrap_or(&"".to_string())
+                ),
+            )
+        } else {
+            return None;
+        };
+        Some(BqDatasetAccess {
+            role: access.role.clone(),
+            entity_type: entity_type.to_string(),
+            entity,
+        })
+    }
+}
+
+/// Dataset metadata
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BqDatasetInfo {
+    pub dataset: BqDataset,
+    pub location: Option<String>,
+    pub description: Option<String>,
+    pub default_table_expiration_ms: Option<i64>,
+    pub labels: Option<HashMap<String, String>>,
+    pub access: Vec<BqDatasetAccess>,
+}
+
 #[derive(Debug, Default)]
 pub struct BqCreateTableParam {
     /// description about the table.
@@ -273,6 +535,9 @@ pub struct BqCreateTableParam {
 
     /// Table Schema. You need to implement `BqSchemaBuilder` to set schema in the request.
     schema: Option<TableSchema>,
+
+    /// Return the existing table instead of an error if the table already exists.
+    if_not_exists: bool,
 }
 
 impl BqCreateTableParam {
@@ -280,9 +545,16 @@ impl BqCreateTableParam {
         BqCreateTableParam {
             description: None,
             schema: None,
+            if_not_exists: false,
         }
     }
 
+    /// Return the existing table instead of an error if the table already exists.
+    pub fn if_not_exists(&mut self, if_not_exists: bool) -> &mut Self {
+        self.if_not_exists = if_not_exists;
+        self
+    }
+
     pub fn schema<T: BqSchemaBuilder>(&mut self) -> &mut Self {
         self.schema = Some(TableSchema {
             fields: Some(
@@ -303,6 +575,7 @@ pub struct BqInsertAllParam {
     skip_invalid_rows: bool,
     ignore_unknown_values: bool,
     trace_id: Option<String>,
+    insert_id_field: Option<String>,
 }
 
 impl BqInsertAllParam {
@@ -313,6 +586,7 @@ impl BqInsertAllParam {
             skip_invalid_rows: false,
             ignore_unknown_values: false,
             trace_id: None,
+            insert_id_field: None,
         }
     }
 
@@ -331,6 +605,24 @@ impl BqInsertAllParam {
         self.trace_id = Some(uuid.to_string());
         &self.trace_id
     }
+
+    /// Use the value of the field as `insertId` of each row.
+    ///
+    /// BigQuery deduplicates the rows with the same `insertId`, so the retried request does not
+    /// create duplicated rows. Non string value is converted to JSON string.
+    pub fn insert_id_field(&mut self, field: &str) -> &mut Self {
+        self.insert_id_field = Some(field.to_owned());
+        self
+    }
+
+    fn insert_id<'a>(&self, find: impl FnOnce(&str) -> Option<&'a Value>) -> Option<String> {
+        let field = self.insert_id_field.as_ref()?;
+        match find(field)? {
+            Value::String(s) => Some(s.clone()),
+            Value::Null => None,
+            v => Some(v.to_string()),
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -341,6 +633,13 @@ pub struct BqTable {
     pub schemas: Option<Vec<BqTableSchema>>,
     pub created_at: Option<u64>,
     pub expired_at: Option<u64>,
+    pub labels: Option<HashMap<String, String>>,
+    /// Table description. This is not available in the list_tables result.
+    pub description: Option<String>,
+    /// Number of rows. This is not available in the list_tables result.
+    pub num_rows: Option<u64>,
+    /// Logical size of the table in bytes. This is not available in the list_tables result.
+    pub size_bytes: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -358,28 +657,106 @@ pub trait BqSchemaBuilder {
 }
 
 impl BqTableSchema {
+    /// Infer the table schema from sample JSON rows.
+    ///
+    /// Fields are NULLABLE when any sample omits them or has null. Fields present with non-null
+    /// values in all samples are REQUIRED. Arrays are REPEATED and nested objects are RECORD with
+    /// the inferred sub-fields.
+    /// Mixed INTEGER and FLOAT become FLOAT. Other mixed types and null only fields become STRING.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - sample rows. Values other than JSON objects are ignored.
+    pub fn infer_from_json(samples: &[Value]) -> Vec<BqTableSchema> {
+        let objects: Vec<&Map<String, Value>> =
+            samples.iter().filter_map(|s| s.as_object()).collect();
+        let mut names: Vec<&String> = Vec::new();
+        for o in &objects {
+            for k in o.keys() {
+                if !names.contains(&k) {
+                    names.push(k);
+                }
+            }
+        }
+        names
+            .iter()
+            .map(|name| {
+                let values: Vec<&Value> = objects.iter().filter_map(|o| o.get(*name)).collect();
+                let repeated = values.iter().any(|v| v.is_array());
+                let elements: Vec<&Value> = if repeated {
+                    values
+                        .iter()
+                        .flat_map(|v| match v {
+                            Value::Array(arr) => arr.iter().collect(),
+                            v => vec![*v],
+                        })
+                        .collect()
+                } else {
+                    values.clone()
+                };
+                let (type_, fields) = Self::infer_type(&elements);
+                let mode = if repeated {
+                    BqMode::REPEATED
+                } else if values.iter().filter(|v| !v.is_null()).count() == objects.len() {
+                    BqMode::REQUIRED
+                } else {
+                    BqMode::NULLABLE
+                };
+                BqTableSchema {
+                    name: Some(name.to_string()),
+                    type_,
+                    mode,
+                    fields: Box::new(fields),
+                    description: None,
+                }
+            })
+            .collect()
+    }
+
+    /// Infer the column type and the sub-fields for RECORD from the values.
+    fn infer_type(values: &[&Value]) -> (BqType, Vec<BqTableSchema>) {
+        let mut inferred: Option<BqType> = None;
+        for v in values {
+            let type_ = match v {
+                Value::Null => continue,
+                Value::Bool(_) => BqType::BOOLEAN,
+                Value::Number(n) if n.is_f64() => BqType::FLOAT,
+                Value::Number(_) => BqType::INTEGER,
+                Value::Object(_) => BqType::RECORD,
+                // nested arrays are not supported by BigQuery
+                Value::String(_) | Value::Array(_) => BqType::STRING,
+            };
+            inferred = match (inferred, type_) {
+                (None, t) => Some(t),
+                (Some(a), b) if a == b => Some(a),
+                (Some(BqType::INTEGER), BqType::FLOAT) | (Some(BqType::FLOAT), BqType::INTEGER) => {
+                    Some(BqType::FLOAT)
+                }
+                _ => Some(BqType::STRING),
+            };
+        }
+        match inferred {
+            Some(BqType::RECORD) => {
+                let records: Vec<Value> = values.iter().map(|v| (*v).clone()).collect();
+                (BqType::RECORD, Self::infer_from_json(&records))
+            }
+            Some(t) => (t, vec![]),
+            None => (BqType::STRING, vec![]),
+        }
+    }
+
     fn to_table_field_schema(&self) -> TableFieldSchema {
         let mut schema = TableFieldSchema::default();
         schema.name = self.name.as_ref().map(|n| n.clone());
         schema.mode = match self.mode {
-            BqMode::REQUIRED => Some("REQUIRED".to_string()),
-            BqMode::NULLABLE => Some("NULLABLE".to_string()),
-            BqMode::REPEATED => Some("REPEATED".to_string()),
-            _ => None,
+            BqMode::UNKNOWN => None,
+            _ => Some(self.mode.to_string()),
         };
         schema.type_ = match self.type_ {
-            BqType::STRING => Some("STRING".to_string()),
-            BqType::FLOAT => Some("NUMERIC".to_string()),
-            BqType::INTEGER => Some("INTEGER".to_string()),
-            BqType::BOOLEAN => Some("BOOLEAN".to_string()),
-            BqType::TIMESTAMP => Some("TIMESTAMP".to_string()),
-            BqType::DATETIME => Some("DATETIME".to_string()),
-            BqType::DATE => Some("DATE".to_string()),
-            BqType::TIME => Some("TIME".to_string()),
-            BqType::RECORD => Some("RECORD".to_string()),
-            BqType::JSON => Some("JSON".to_string()),
-            _ => None,
+            BqType::UNKNOWN => None,
+            _ => Some(self.type_.to_string()),
         };
+        schema.description = self.description.clone();
         let fields: Vec<TableFieldSchema> = self
             .fields
             .iter()
@@ -391,27 +768,16 @@ impl BqTableSchema {
 
     fn from_table_field_schema(s: &TableFieldSchema) -> Self {
         let name = s.name.as_ref().unwrap_or(&"".to_string()).to_string();
-        let type_ = match s.type_.as_ref().unwrap().as_str() {
-            "STRING" => BqType::STRING,
-            "FLOAT" => BqType::FLOAT,
-            "INTEGER" => BqType::INTEGER,
-            "NUMERIC" => BqType::FLOAT,
-            "BOOLEAN" => BqType::BOOLEAN,
-            "TIMESTAMP" => BqType::TIMESTAMP,
-            "DATE" => BqType::DATE,
-            "DATETIME" => BqType::DATETIME,
-            "TIME" => BqType::TIME,
-            "RECORD" => BqType::RECORD,
-            "JSON" => BqType::JSON,
-            _ => BqType::UNKNOWN,
-        };
-        let default = String::from("");
-        let mode = match s.mode.as_ref().unwrap_or_else(|| &default).as_str() {
-            "REQUIRED" => BqMode::REQUIRED,
-            "NULLABLE" => BqMode::NULLABLE,
-            "REPEATED" => BqMode::REPEATED,
-            _ => BqMode::UNKNOWN,
-        };
+        let type_ = s
+            .type_
+            .as_ref()
+            .and_then(|t| t.parse::<BqType>().ok())
+            .unwrap_or(BqType::UNKNOWN);
+        let mode = s
+            .mode
+            .as_ref()
+            .and_then(|m| m.parse::<BqMode>().ok())
+            .unwrap_or(BqMode::UNKNOWN);
         let schemas = s
             .fields
             .as_ref()
@@ -455,10 +821,81 @@ pub enum BqType {
     DATETIME,
     RECORD,
     JSON,
+    RANGE,
+    INTERVAL,
     UNKNOWN,
 }
 
-#[derive(Debug, Deserialize)]
+impl fmt::Display for BqMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            BqMode::REQUIRED => "REQUIRED",
+            BqMode::NULLABLE => "NULLABLE",
+            BqMode::REPEATED => "REPEATED",
+            BqMode::UNKNOWN => "UNKNOWN",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for BqMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "REQUIRED" => Ok(BqMode::REQUIRED),
+            "NULLABLE" => Ok(BqMode::NULLABLE),
+            "REPEATED" => Ok(BqMode::REPEATED),
+            _ => anyhow::bail!("unknown mode: {}", s),
+        }
+    }
+}
+
+impl fmt::Display for BqType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            BqType::STRING => "STRING",
+            BqType::INTEGER => "INTEGER",
+            BqType::FLOAT => "FLOAT",
+            BqType::BOOLEAN => "BOOLEAN",
+            BqType::TIMESTAMP => "TIMESTAMP",
+            BqType::DATE => "DATE",
+            BqType::TIME => "TIME",
+            BqType::DATETIME => "DATETIME",
+            BqType::RECORD => "RECORD",
+            BqType::JSON => "JSON",
+            BqType::RANGE => "RANGE",
+            BqType::INTERVAL => "INTERVAL",
+            BqType::UNKNOWN => "UNKNOWN",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Parse the type name. Standard SQL aliases like `INT64` and `STRUCT` are also accepted.
+impl FromStr for BqType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "STRING" => Ok(BqType::STRING),
+            "INTEGER" | "INT64" => Ok(BqType::INTEGER),
+            "FLOAT" | "FLOAT64" | "NUMERIC" => Ok(BqType::FLOAT),
+            "BOOLEAN" | "BOOL" => Ok(BqType::BOOLEAN),
+            "TIMESTAMP" => Ok(BqType::TIMESTAMP),
+            "DATE" => Ok(BqType::DATE),
+            "TIME" => Ok(BqType::TIME),
+            "DATETIME" => Ok(BqType::DATETIME),
+            "RECORD" | "STRUCT" => Ok(BqType::RECORD),
+            "JSON" => Ok(BqType::JSON),
+            "RANGE" => Ok(BqType::RANGE),
+            "INTERVAL" => Ok(BqType::INTERVAL),
+            _ => anyhow::bail!("unknown type: {}", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct BqRow {
     /// To keep column order
     _name_index: HashMap<String, i32>,
@@ -493,6 +930,106 @@ impl BqRow {
     pub fn len(&self) -> usize {
         self.columns.len()
     }
+
+    /// Deserialize the row into `T` through its JSON representation.
+    ///
+    /// TIMESTAMP is RFC3339 and DATETIME is `%Y-%m-%dT%H:%M:%S%.6f`, so they can be
+    /// deserialized into `DateTime<Utc>` and `NaiveDateTime` of chrono.
+    pub fn deserialize_into<T: DeserializeOwned>(&self) -> Result<T> {
+        let value = serde_json::to_value(self)?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Flatten nested columns into rows of (column name, cell string) pairs.
+    ///
+    /// Fields of `BqStruct` become dotted column names like `addr.city`.
+    /// `BqRepeated` is handled by `mode`. Exploding multiple repeated columns produces
+    /// the cartesian product of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - how to flatten `BqRepeated` values
+    pub fn flatten(&self, mode: &BqFlatten) -> Vec<Vec<(String, String)>> {
+        let rows = match mode {
+            BqFlatten::Join(delimiter) => vec![flatten_row(self, Some(delimiter))],
+            BqFlatten::Explode => explode_row(self)
+                .iter()
+                .map(|r| flatten_row(r, None))
+                .collect(),
+        };
+        rows.into_iter()
+            .map(|r| {
+                r.into_iter()
+                    .map(|(name, value)| (name, value.to_cell_string()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Write the rows as delimited text with flattened columns.
+    ///
+    /// The header is the union of the flattened column names in order of appearance.
+    /// Missing cells are written as empty strings.
+    ///
+    /// # Arguments
+    ///
+    /// * `rows` - rows to be written
+    /// * `writer` - destination
+    /// * `mode` - how to flatten `BqRepeated` values
+    /// * `delimiter` - field delimiter. `b','` for CSV and `b'\t'` for TSV.
+    pub fn write_flattened<W: Write>(
+        rows: &[BqRow],
+        writer: W,
+        mode: &BqFlatten,
+        delimiter: u8,
+    ) -> Result<()> {
+        let flat_rows: Vec<Vec<(String, String)>> =
+            rows.iter().flat_map(|r| r.flatten(mode)).collect();
+        let mut header: Vec<String> = Vec::new();
+        for row in &flat_rows {
+            for (name, _) in row {
+                if !header.contains(name) {
+                    header.push(name.clone());
+                }
+            }
+        }
+
+        let mut wtr = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .from_writer(writer);
+        wtr.write_record(&header)?;
+        for row in flat_rows {
+            let cells: HashMap<String, String> = row.into_iter().collect();
+            wtr.write_record(
+                header
+                    .iter()
+                    .map(|h| cells.get(h).map(|c| c.as_str()).unwrap_or("")),
+            )?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+}
+
+/// How to flatten `BqRepeated` values
+#[derive(Debug, Clone, PartialEq)]
+pub enum BqFlatten {
+    /// Each element becomes a separate row
+    Explode,
+    /// Elements are joined into a single cell with the delimiter
+    Join(String),
+}
+
+impl From<BqRow> for Map<String, Value> {
+    fn from(row: BqRow) -> Self {
+        row.columns
+            .into_iter()
+            .map(|c| {
+                let value = serde_json::to_value(&c.value).unwrap_or(Value::Null);
+                (c.name.unwrap_or_default(), value)
+            })
+            .collect()
+    }
 }
 
 impl string::ToString for BqRow {
@@ -508,7 +1045,7 @@ impl string::ToString for BqRow {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct BqColumn {
     /// column name
     name: Option<String>,
@@ -555,6 +1092,16 @@ impl string::ToString for BqColumn {
 impl BqColumn {
     fn value_to_bq_value(v: Option<Value>, schema: &BqTableSchema) -> BqValue {
         v.map(|val| match val {
+            // null DATE/TIME/TIMESTAMP cells sometimes arrive as an empty string
+            Value::String(s)
+                if s.trim().is_empty()
+                    && matches!(
+                        schema.type_,
+                        BqType::TIMESTAMP | BqType::DATETIME | BqType::DATE | BqType::TIME
+                    ) =>
+            {
+                BqValue::BqNull
+            }
             Value::String(s) => match schema.type_ {
                 BqType::STRING => BqValue::BqString(s),
                 BqType::INTEGER => BqValue::BqInteger(s.parse::<i64>().unwrap_or(0)),
@@ -573,6 +1120,10 @@ impl BqColumn {
                     NaiveTime::parse_from_str(&s, "%H:%M:%S")
                         .unwrap_or_else(|_| NaiveTime::parse_from_str(&s, "%H:%M:%S.%f").unwrap()),
                 ),
+                BqType::RANGE => BqRange::parse(&s)
+                    .map(BqValue::BqRange)
+                    .unwrap_or(BqValue::BqString(s)),
+                BqType::INTERVAL => BqValue::BqInterval(s),
                 _ => BqValue::BqNull,
             },
             Value::Number(n) => match schema.type_ {
@@ -592,9 +1143,17 @@ impl BqColumn {
                 _ => BqValue::BqNull,
             },
             Value::Array(arr) => {
+                // each element is wrapped like `{"v": ...}`. for REPEATED RECORD, the inner
+                // value is `{"f": [...]}` and decoded as a struct with the same schema fields.
                 let columns: Vec<Box<BqValue>> = arr
                     .iter()
-                    .map(|s| Box::new(Self::value_to_bq_value(Some(s.clone()), &schema)))
+                    .map(|s| {
+                        let elem = match s {
+                            Value::Object(o) if o.contains_key("v") => o.get("v").cloned(),
+                            _ => Some(s.clone()),
+                        };
+                        Box::new(Self::value_to_bq_value(elem, &schema))
+                    })
                     .collect();
                 BqValue::BqRepeated(columns)
             }
@@ -602,10 +1161,8 @@ impl BqColumn {
                 if let Some(Value::Array(arr)) = &o.get("f") {
                     let columns: Vec<BqColumn> = arr
                         .iter()
-                        .enumerate()
-                        .map(|(i, s)| {
-                            BqColumn::new(&TableCell { v: Some(s.clone()) }, &schema.fields[i])
-                        })
+                        .zip(schema.fields.iter())
+                        .map(|(s, f)| BqColumn::new(&TableCell { v: Some(s.clone()) }, f))
                         .collect();
                     BqValue::BqStruct(BqRow::new(columns))
                 } else if o.get("v").is_some() {
@@ -632,10 +1189,18 @@ impl BqColumn {
     pub fn value(&self) -> &BqValue {
         &self.value
     }
+
+    /// Same column with the other value
+    pub(crate) fn with_value(&self, value: BqValue) -> Self {
+        BqColumn {
+            name: self.name.clone(),
+            value,
+        }
+    }
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub enum BqValue {
     /// STRING
     BqString(String),
@@ -657,17 +1222,168 @@ pub enum BqValue {
     BqStruct(BqRow),
     /// REPEATED(Array)
     BqRepeated(Vec<Box<BqValue>>),
+    /// RANGE
+    BqRange(BqRange),
+    /// INTERVAL. The representation from the API like `1-2 3 4:5:6` is kept as is.
+    BqInterval(String),
     /// NULL
     BqNull,
 }
 
+/// RANGE value. The bounds are kept in the representation of the element type(DATE, DATETIME
+/// or TIMESTAMP). `None` means UNBOUNDED.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BqRange {
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+impl BqRange {
+    /// Parse the API representation like `[2024-01-01, UNBOUNDED)`
+    fn parse(s: &str) -> Option<Self> {
+        let inner = s.trim().strip_prefix('[')?.strip_suffix(')')?;
+        let (start, end) = inner.split_once(',')?;
+        let bound = |b: &str| match b.trim() {
+            "UNBOUNDED" | "NULL" => None,
+            b => Some(b.to_string()),
+        };
+        Some(BqRange {
+            start: bound(start),
+            end: bound(end),
+        })
+    }
+}
+
+impl fmt::Display for BqRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[{}, {})",
+            self.start.as_deref().unwrap_or("UNBOUNDED"),
+            self.end.as_deref().unwrap_or("UNBOUNDED")
+        )
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Deserialize, Serialize)]
 pub enum QueryResult {
+    /// Dry run result
     #[serde(rename = "schema")]
-    Schema(Vec<BqTableSchema>),
+    Schema {
+        schemas: Vec<BqTableSchema>,
+        /// Estimated bytes to be processed by the query
+        total_bytes_processed: Option<i64>,
+    },
     #[serde(rename = "data")]
-    Data(Vec<BqRow>),
+    Data {
+        rows: Vec<BqRow>,
+        /// Total number of rows of the query result. This can be larger than the `rows`.
+        total_rows: Option<u64>,
+        /// Schema of the query result
+        schema: Vec<BqTableSchema>,
+    },
+}
+
+impl QueryResult {
+    /// Write the rows as CSV with a header line.
+    ///
+    /// Nested `BqStruct`/`BqRepeated` values are written as JSON strings in the cells.
+    /// For the dry run result, only the header is written.
+    pub fn to_csv<W: Write>(&self, writer: W) -> Result<()> {
+        let mut wtr = csv::Writer::from_writer(writer);
+        match self {
+            QueryResult::Schema { schemas, .. } => {
+                wtr.write_record(schemas.iter().map(|s| s.name.clone().unwrap_or_default()))?;
+            }
+            QueryResult::Data { rows, .. } => {
+                if let Some(first) = rows.first() {
+                    wtr.write_record(first.columns.iter().map(|c| c.name().unwrap_or_default()))?;
+                }
+                for row in rows {
+                    wtr.write_record(row.columns.iter().map(|c| c.value.to_cell_string()))?;
+                }
+            }
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+
+    /// Write the rows as new line delimited JSON.
+    ///
+    /// For the dry run result, nothing is written.
+    pub fn to_ndjson<W: Write>(&self, mut writer: W) -> Result<()> {
+        if let QueryResult::Data { rows, .. } = self {
+            for row in rows {
+                serde_json::to_writer(&mut writer, row)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl BqValue {
+    /// Returns the string if the value is STRING.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            BqValue::BqString(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer if the value is INTEGER.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            BqValue::BqInteger(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Returns the float if the value is FLOAT. INTEGER is converted to float.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            BqValue::BqFloat(n) => Some(*n),
+            BqValue::BqInteger(n) => Some(*n as f64),
+            _ => None,
+        }
+    }
+
+    /// Returns the boolean if the value is BOOLEAN.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            BqValue::BqBool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Returns the timestamp if the value is TIMESTAMP. DATETIME is treated as UTC.
+    pub fn as_datetime(&self) -> Option<DateTime<Utc>> {
+        match self {
+            BqValue::BqTimestamp(t) => Some(*t),
+            BqValue::BqDateTime(d) => Some(DateTime::from_naive_utc_and_offset(*d, Utc)),
+            _ => None,
+        }
+    }
+
+    /// Returns the elements if the value is REPEATED.
+    pub fn as_array(&self) -> Option<&[Box<BqValue>]> {
+        match self {
+            BqValue::BqRepeated(rs) => Some(rs),
+            _ => None,
+        }
+    }
+
+    /// String representation for a tabular cell. NULL becomes an empty string and nested values
+    /// become JSON.
+    pub(crate) fn to_cell_string(&self) -> String {
+        match serde_json::to_value(self) {
+            Ok(Value::String(s)) => s,
+            Ok(Value::Null) | Err(_) => String::from(""),
+            Ok(v) => v.to_string(),
+        }
+    }
 }
 
 impl Serialize1 for BqValue {
@@ -703,6 +1419,8 @@ impl Serialize1 for BqValue {
             }
             BqValue::BqDate(d) => serializer.serialize_str(&d.format("%Y-%m-%d").to_string()),
             BqValue::BqTime(d) => serializer.serialize_str(&d.format("%H:%M:%S").to_string()),
+            BqValue::BqRange(r) => r.serialize(serializer),
+            BqValue::BqInterval(i) => serializer.serialize_str(i),
             BqValue::BqNull => serializer.serialize_none(),
         }
     }
@@ -719,6 +1437,8 @@ impl string::ToString for BqValue {
             BqValue::BqDateTime(d) => format!("\"{}\"", d.format("%Y-%m-%dT%H:%M:%S%.6f")),
             BqValue::BqDate(d) => format!("\"{}\"", d.format("%Y-%m-%d")),
             BqValue::BqTime(d) => format!("\"{}\"", d.format("%H:%M:%S")),
+            BqValue::BqRange(r) => format!("\"{}\"", r),
+            BqValue::BqInterval(i) => format!("\"{}\"", i),
             BqValue::BqStruct(rs) => {
                 let rs_str = rs
                     .columns
@@ -754,6 +1474,10 @@ impl BqTable {
             schemas: Default::default(),
             created_at: Default::default(),
             expired_at: Default::default(),
+            labels: Default::default(),
+            description: Default::default(),
+            num_rows: Default::default(),
+            size_bytes: Default::default(),
         }
     }
 }
@@ -766,12 +1490,28 @@ impl Bq {
     /// * `auth` - Gcp Authentication instance
     /// * `project` - Project ID
     pub fn new(auth: &auth::GcpAuth, project: &str) -> Result<Bq> {
-        let client = auth::new_client();
+        Bq::with_client(auth, project, auth::new_client())
+    }
+
+    /// Create BigQuery API interface with the HTTP client built by `auth::new_client_with`
+    ///
+    /// # Arguments
+    ///
+    /// * `auth` - Gcp Authentication instance
+    /// * `project` - Project ID
+    /// * `client` - HTTP client
+    pub fn with_client(
+        auth: &auth::GcpAuth,
+        project: &str,
+        client: auth::HttpClient,
+    ) -> Result<Bq> {
         let hub = Bigquery::new(client, auth.authenticator());
         Ok(Bq {
             api: hub,
             project: project.to_string(),
             max_data: 10,
+            location: None,
+            retry_policy: RetryPolicy::default(),
         })
     }
 
@@ -780,6 +1520,20 @@ impl Bq {
         self
     }
 
+    pub fn retry_policy(&mut self, policy: RetryPolicy) -> &mut Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Set the location(like `US`, `EU` or `asia-northeast1`) of the jobs.
+    ///
+    /// Jobs must run in the location of the dataset they access. If it is not set, BigQuery
+    /// decides the location from the query.
+    pub fn location(&mut self, location: &str) -> &mut Self {
+        self.location = Some(location.to_string());
+        self
+    }
+
     /// call list_project API.
     /// this will return list of project.
     pub async fn list_project(auth: auth::GcpAuth) -> Result<Vec<BqProject>> {
@@ -827,10 +1581,8 @@ impl Bq {
         if let Some(token) = &p.page_token {
             list_api = list_api.page_token(&token);
         }
-        list_api = list_api.param(
-            "fields",
-            "datasets/id, datasets/datasetReference, nextPageToken",
-        );
+        let fields = p.fields_param("datasets/id, datasets/datasetReference, nextPageToken");
+        list_api = list_api.param("fields", fields.as_str());
         let res = list_api.doit().await;
         match Bq::handle_error(res) {
             Ok(result) => {
@@ -869,6 +1621,58 @@ impl Bq {
         }
     }
 
+    /// Check if the dataset exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `dataset` - target dataset
+    pub async fn dataset_exists(&self, dataset: &DatasetId) -> Result<bool> {
+        let res = self
+            .api
+            .datasets()
+            .get(&self.project, dataset)
+            .param("fields", "id")
+            .doit()
+            .await;
+        if Bq::is_error_status(&res, 404) {
+            return Ok(false);
+        }
+        Bq::handle_error(res).map(|_| true)
+    }
+
+    /// call datasets.get API.
+    /// this will return the dataset metadata like location.
+    ///
+    /// # Arguments
+    ///
+    /// * `dataset` - target dataset
+    pub async fn get_dataset(&self, dataset: &DatasetId) -> Result<BqDatasetInfo> {
+        let api = self.api.datasets().get(&self.project, dataset);
+        let res = api.doit().await;
+        match Bq::handle_error(res) {
+            Ok(result) => {
+                let d = result.1;
+                Ok(BqDatasetInfo {
+                    dataset: BqDataset::new(&self.project, dataset),
+                    location: d.location,
+                    description: d.description,
+                    default_table_expiration_ms: d.default_table_expiration_ms,
+                    labels: d.labels,
+                    access: d
+                        .access
+                        .map(|access| {
+                            access
+                                .iter()
+                                .filter_map(BqDatasetAccess::from_dataset_access)
+                                .collect()
+                        })
+                        .unwrap_or(vec![]),
+                })
+            }
+            Err(e) => Err(anyhow::anyhow!("{}", e)),
+        }
+    }
+
     fn to_bq_table(&self, t: Table) -> BqTable {
         let default = "".to_string();
         let schemas = if let Some(schema) = t.schema {
@@ -894,6 +1698,10 @@ impl Bq {
             schemas: Some(schemas),
             created_at: t.creation_time.map(|t| t as u64),
             expired_at: t.expiration_time.map(|t| t as u64),
+            labels: t.labels.clone(),
+            description: t.description.clone(),
+            num_rows: t.num_rows.map(|n| n as u64),
+            size_bytes: t.num_bytes.map(|n| n as u64),
         }
     }
 
@@ -909,6 +1717,53 @@ impl Bq {
         }
     }
 
+    /// Check if the table exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `dataset` - dataset for table
+    /// * `table` - target table name
+    pub async fn table_exists(&self, dataset: &DatasetId, table: &TableId) -> Result<bool> {
+        let res = self
+            .api
+            .tables()
+            .get(&self.project, dataset, table)
+            .param("fields", "id")
+            .doit()
+            .await;
+        if Bq::is_error_status(&res, 404) {
+            return Ok(false);
+        }
+        Bq::handle_error(res).map(|_| true)
+    }
+
+    /// Get the table schema only.
+    ///
+    /// # Arguments
+    ///
+    /// * `dataset` - dataset for table
+    /// * `table` - target table name
+    pub async fn get_table_schema(
+        &self,
+        dataset: &DatasetId,
+        table: &TableId,
+    ) -> Result<Vec<BqTableSchema>> {
+        let api = self
+            .api
+            .tables()
+            .get(&self.project, &dataset, table)
+            .param("fields", "schema");
+        let res = api.doit().await;
+        match Bq::handle_error(res) {
+            Ok(result) => Ok(result
+                .1
+                .schema
+                .map(|schema| self.to_schemas(&schema))
+                .unwrap_or(vec![])),
+            Err(e) => Err(anyhow::anyhow!("{}", e)),
+        }
+    }
+
     /// Call tables insert API.
     ///
     /// # Arguments
@@ -936,11 +1791,11 @@ impl Bq {
         }
         let api = self.api.tables().insert(req, &self.project, &dataset);
         let res = api.doit().await;
+        if p.if_not_exists && Bq::is_error_status(&res, 409) {
+            return self.get_table(dataset, table).await;
+        }
         match Bq::handle_error(res) {
-            Ok(result) => {
-                println!("{:?}", result.1);
-                Ok(self.to_bq_table(result.1))
-            }
+            Ok(result) => Ok(self.to_bq_table(result.1)),
             Err(e) => Err(anyhow::anyhow!("{}", e)),
         }
     }
@@ -957,7 +1812,7 @@ impl Bq {
         let res = api.doit().await;
         match Bq::handle_error(res) {
             Ok(result) => {
-                println!("{:?}", result);
+                tracing::debug!("{:?}", result);
                 Ok(())
             }
             Err(e) => Err(anyhow::anyhow!("{}", e)),
@@ -967,6 +1822,7 @@ impl Bq {
     /// Call tables.list API
     ///
     /// This will return only table id(project id and dataset id) and timestamp for now.
+    /// The API does not return row count and size. Use `get_table` to get them.
     #[async_recursion]
     pub async fn list_tables(
         &'async_recursion self,
@@ -980,8 +1836,8 @@ impl Bq {
         if let Some(token) = &p.page_token {
             list_api = list_api.page_token(&token);
         }
-        list_api = list_api.param("fields",
-            "tables/id, tables/tableReference, tables/creationTime, tables/expirationTime, nextPageToken, totalItems");
+        let fields = p.fields_param("tables/id, tables/tableReference, tables/creationTime, tables/expirationTime, tables/labels, nextPageToken, totalItems");
+        list_api = list_api.param("fields", fields.as_str());
         let res = list_api.doit().await;
         //println!("{:?}", res);
         match Bq::handle_error(res) {
@@ -1006,6 +1862,10 @@ impl Bq {
                                 schemas: None,
                                 created_at: t.creation_time.map(|t| t as u64),
                                 expired_at: t.expiration_time.map(|t| t as u64),
+                                labels: t.labels.clone(),
+                                description: None,
+                                num_rows: None,
+                                size_bytes: None,
                             }
                         })
                         .collect(),
@@ -1029,12 +1889,15 @@ impl Bq {
         &'async_recursion self,
         p: &'async_recursion BqGetQueryResultParam,
     ) -> Result<Vec<BqRow>> {
-        let api = self
+        let mut api = self
             .api
             .jobs()
             .get_query_results(&self.project, &p.job_id)
             .page_token(&p.page_token)
             .max_results(p.max_results);
+        if let Some(location) = &self.location {
+            api = api.location(location);
+        }
         let resp = Bq::handle_error(api.doit().await);
         match resp {
             Ok(result) => {
@@ -1052,14 +1915,18 @@ impl Bq {
                                 token,
                             );
                             param.max_results(p.max_results);
-                            if let Some(num_limit) = p.num_result_limit {
-                                if tmp_rows.len() < num_limit {
+                            match p.num_result_limit {
+                                Some(num_limit) if num_limit <= tmp_rows.len() => {}
+                                Some(num_limit) => {
+                                    param.num_result_limit(num_limit - tmp_rows.len());
                                     tmp_rows.extend(self.get_query_results(&param).await?);
                                 }
-                            } else {
-                                tmp_rows.extend(self.get_query_results(&param).await?);
+                                None => tmp_rows.extend(self.get_query_results(&param).await?),
                             }
                         }
+                        if let Some(num_limit) = p.num_result_limit {
+                            tmp_rows.truncate(num_limit);
+                        }
                         tmp_rows
                     } else {
                         vec![]
@@ -1110,43 +1977,51 @@ impl Bq {
             .unwrap_or(vec![])
     }
 
-    #[async_recursion]
-    async fn wait_job_done(
-        &'async_recursion self,
-        job_id: &'async_recursion str,
-        retry_count: u64,
-    ) -> Result<()> {
-        let get_api = self.api.jobs().get(&self.project, job_id);
-        let resp = Bq::handle_error(get_api.doit().await);
-        match resp {
-            Ok(result) => {
-                //println!("{:?}", result);
-                let state = result
-                    .1
-                    .status
-                    .map(|st| st.state.map(|state| JobStatus::to_status(&*state)))
-                    .flatten()
-                    .unwrap_or_else(|| JobStatus::Unknown);
-                if state != JobStatus::Done {
-                    let interval = 100 * retry_count.pow(2);
-                    // eprintln!("{}, {}", e, interval);
-                    thread::sleep(Duration::from_millis(interval));
-                    self.wait_job_done(job_id, retry_count + 1).await
-                } else {
-                    Ok(())
+    /// Poll the job until its status become 'DONE' and return the job.
+    ///
+    /// The polling interval follows the retry policy but the number of polling is not limited.
+    /// If the job is done with an error result, it is returned as an error.
+    async fn wait_job_done(&self, job_id: &str) -> Result<Job> {
+        let mut retry = 0;
+        loop {
+            let mut get_api = self.api.jobs().get(&self.project, job_id);
+            if let Some(location) = &self.location {
+                get_api = get_api.location(location);
+            }
+            let job = Bq::handle_error(get_api.doit().await)?.1;
+            let state = job
+                .status
+                .as_ref()
+                .and_then(|st| st.state.as_ref())
+                .map(|state| JobStatus::to_status(state))
+                .unwrap_or_else(|| JobStatus::Unknown);
+            if state == JobStatus::Done {
+                let error_result = job.status.as_ref().and_then(|st| st.error_result.as_ref());
+                if let Some(error_result) = error_result {
+                    let message = error_result.message.clone().unwrap_or_default();
+                    // maximum_bytes_billed is checked while the job is running
+                    if error_result.reason.as_deref() == Some(BYTES_BILLED_LIMIT_EXCEEDED) {
+                        anyhow::bail!("the query exceeds maximum_bytes_billed: {}", message)
+                    }
+                    anyhow::bail!("job {} failed: {}", job_id, message)
                 }
+                return Ok(job);
             }
-            Err(e) => Err(anyhow::anyhow!(format!("{}", e))),
+            tokio::time::sleep(self.retry_policy.delay(retry)).await;
+            retry += 1;
         }
     }
 
     /// Execute get job and wait until the job's status become 'DONE'
     ///
+    /// The job error is returned as an error. Otherwise the final job result is returned.
+    ///
     /// # Arguments
     ///
     /// * `job_id` - target job id.
-    pub async fn wait_job_complete(&self, job_id: &str) -> Result<()> {
-        self.wait_job_done(job_id, 0).await
+    pub async fn wait_job_complete(&self, job_id: &str) -> Result<BqJobResult> {
+        let job = self.wait_job_done(job_id).await?;
+        Ok(Bq::to_job_result(job))
     }
 
     /// Execute job query. This will save query results into destination table.
@@ -1169,8 +2044,15 @@ impl Bq {
         }
         let mut req = Job::default();
         req.configuration = Some(job_ref);
+        if let Some(location) = &self.location {
+            req.job_reference = Some(JobReference {
+                location: Some(location.clone()),
+                project_id: Some(self.project.clone()),
+                ..Default::default()
+            });
+        }
         let query_api = self.api.jobs().insert(req, &self.project);
-        let resp = Bq::handle_error(query_api.doit_without_upload().await);
+        let resp = Bq::handle_query_error(query_api.doit_without_upload().await);
         //println!("{:?}", resp);
         match resp {
             Ok(result) => {
@@ -1186,41 +2068,154 @@ impl Bq {
                     result.status = state;
                     Ok(result)
                 } else {
-                    let self_link = result.1.self_link;
-                    let job_id = result.1.job_reference.map(|jr| jr.job_id).flatten();
-                    let state = result
-                        .1
-                        .status
-                        .map(|st| {
-                            let (message, reason) = if let Some(error_result) = st.error_result {
-                                (error_result.message, error_result.reason)
-                            } else {
-                                (None, None)
-                            };
-                            Some((st.state, message, reason))
-                        })
-                        .flatten();
-                    let status = state
-                        .as_ref()
-                        .map(|s| s.0.as_ref().map(|st| JobStatus::to_status(&*st.clone())))
-                        .flatten()
-                        .unwrap_or_else(|| JobStatus::Unknown);
-                    let error_message = state.as_ref().map(|s| s.1.clone()).flatten();
-                    let error_reason = state.map(|s| s.2).flatten();
-                    let result = BqJobResult {
-                        self_link,
-                        job_id,
-                        status,
-                        error_message,
-                        error_reason,
-                    };
-                    Ok(result)
+                    Ok(Bq::to_job_result(result.1))
                 }
             }
             Err(e) => Err(anyhow::anyhow!(format!("{}", e))),
         }
     }
 
+    fn to_job_result(job: Job) -> BqJobResult {
+        let self_link = job.self_link;
+        let job_id = job.job_reference.map(|jr| jr.job_id).flatten();
+        let state = job
+            .status
+            .map(|st| {
+                let (message, reason) = if let Some(error_result) = st.error_result {
+                    (error_result.message, error_result.reason)
+                } else {
+                    (None, None)
+                };
+                Some((st.state, message, reason))
+            })
+            .flatten();
+        let status = state
+            .as_ref()
+            .map(|s| s.0.as_ref().map(|st| JobStatus::to_status(&*st.clone())))
+            .flatten()
+            .unwrap_or_else(|| JobStatus::Unknown);
+        let error_message = state.as_ref().map(|s| s.1.clone()).flatten();
+        let error_reason = state.map(|s| s.2).flatten();
+        BqJobResult {
+            self_link,
+            job_id,
+            status,
+            error_message,
+            error_reason,
+        }
+    }
+
+    /// Execute load job from GCS into the destination table.
+    ///
+    /// This returns when the job is inserted. Use `wait_job_complete` to wait for the job. It
+    /// returns an error if the load job failed.
+    ///
+    /// # Arguments
+    ///
+    /// * `p` - request parameters.
+    pub async fn load(&self, p: &BqLoadParam) -> Result<BqJobResult> {
+        let mut config = JobConfiguration::default();
+        config.load = Some(p.to_load_config()?);
+        let mut req = Job::default();
+        req.configuration = Some(config);
+        if let Some(location) = &self.location {
+            req.job_reference = Some(JobReference {
+                location: Some(location.clone()),
+                project_id: Some(self.project.clone()),
+                ..Default::default()
+            });
+        }
+        let api = self.api.jobs().insert(req, &self.project);
+        let result = Bq::handle_error(api.doit_without_upload().await)?;
+        Ok(Bq::to_job_result(result.1))
+    }
+
+    /// Execute job query and stream the rows of the destination table page by page.
+    ///
+    /// This is suitable for the large result which `query` can not hold in memory.
+    /// 'dry_run' parameter is not supported.
+    ///
+    /// # Arguments
+    ///
+    /// * `p` - request parameters.
+    pub fn query_to_table_and_stream<'a>(
+        &'a self,
+        p: &'a BqQueryToTableParam,
+    ) -> impl Stream<Item = Result<BqRow>> + 'a {
+        stream::once(async move {
+            anyhow::ensure!(!p.dry_run, "dry run is not supported for streaming");
+            let result = self.query_to_table(p).await?;
+            if let Some(message) = result.error_message {
+                anyhow::bail!(message)
+            }
+            let job_id = result
+                .job_id
+                .ok_or_else(|| anyhow::anyhow!("there is no job id"))?;
+            self.wait_job_done(&job_id).await?;
+            let table = self
+                .api
+                .tables()
+                .get(
+                    p.table_ref.project_id.as_ref().unwrap_or(&self.project),
+                    p.table_ref.dataset_id.as_ref().unwrap_or(&"".to_string()),
+                    p.table_ref.table_id.as_ref().unwrap_or(&"".to_string()),
+                )
+                .param("fields", "schema")
+                .doit()
+                .await;
+            let schema = Bq::handle_error(table)?.1.schema.unwrap_or_default();
+            // the first page has no page token. `None` state means there is no more page.
+            let pages = stream::try_unfold(Some(None), move |token: Option<Option<String>>| {
+                let schema = schema.clone();
+                async move {
+                    let token = match token {
+                        Some(token) => token,
+                        None => return Ok(None),
+                    };
+                    let (rows, next) = self
+                        .list_tabledata_page(&p.table_ref, &schema, token, None)
+                        .await?;
+                    let rows = stream::iter(rows.into_iter().map(Ok::<BqRow, anyhow::Error>));
+                    Ok::<_, anyhow::Error>(Some((rows, next.map(Some))))
+                }
+            });
+            Ok(pages.try_flatten())
+        })
+        .try_flatten()
+    }
+
+    /// Call tabledata.list API for a page.
+    ///
+    /// Returns the rows and the next page token.
+    async fn list_tabledata_page(
+        &self,
+        table_ref: &TableReference,
+        schema: &TableSchema,
+        page_token: Option<String>,
+        max_results: Option<u32>,
+    ) -> Result<(Vec<BqRow>, Option<String>)> {
+        let default = "".to_string();
+        let mut list_api = self.api.tabledata().list(
+            table_ref.project_id.as_ref().unwrap_or(&self.project),
+            table_ref.dataset_id.as_ref().unwrap_or(&default),
+            table_ref.table_id.as_ref().unwrap_or(&default),
+        );
+        if let Some(token) = &page_token {
+            list_api = list_api.page_token(token);
+        }
+        if let Some(max_results) = max_results {
+            list_api = list_api.max_results(max_results);
+        }
+        let res = Bq::handle_error(list_api.doit().await)?;
+        let rows = res
+            .1
+            .rows
+            .as_ref()
+            .map(|rows| self.to_rows(schema, rows))
+            .unwrap_or(vec![]);
+        Ok((rows, res.1.page_token))
+    }
+
     /// Execute query.
     ///
     /// If 'dry_run' parameter is set, result would be the result table schema.
@@ -1233,9 +2228,10 @@ impl Bq {
         &'async_recursion self,
         p: &'async_recursion BqQueryParam,
     ) -> Result<QueryResult> {
-        let req = p.to_query_request();
+        let mut req = p.to_query_request();
+        req.location = self.location.clone();
         let query_api = self.api.jobs().query(req, &self.project);
-        let resp = Bq::handle_error(query_api.doit().await);
+        let resp = Bq::handle_query_error(query_api.doit().await);
         match resp {
             Ok(result) => {
                 //println!("{:?}", result);
@@ -1245,10 +2241,18 @@ impl Bq {
                     } else {
                         vec![]
                     };
-                    Ok(QueryResult::Schema(schemas))
+                    Ok(QueryResult::Schema {
+                        schemas,
+                        total_bytes_processed: result.1.total_bytes_processed,
+                    })
                 } else {
-                    // TODO: should return total rows for local memory
-                    //let total_rows = result.1.total_rows.map(|n| n.parse().unwrap_or(-1)).unwrap_or(-1);
+                    let total_rows = result.1.total_rows;
+                    let schemas = result
+                        .1
+                        .schema
+                        .as_ref()
+                        .map(|schema| self.to_schemas(schema))
+                        .unwrap_or_default();
                     let bq_rows: Vec<BqRow> =
                         if let (Some(schema), Some(rows)) = (result.1.schema, result.1.rows) {
                             let mut tmp_rows: Vec<BqRow> = self.to_rows(&schema, &rows);
@@ -1262,24 +2266,88 @@ impl Bq {
                                     token,
                                 );
                                 param.max_results(p.max_results);
-                                p.num_result_limit.map(|l| param.num_result_limit(l));
-                                let resp = self.get_query_results(&param).await;
-                                match resp {
-                                    Ok(result) => tmp_rows.extend(result),
-                                    _ => println!("{:?}", resp),
+                                let fetch = match p.num_result_limit {
+                                    Some(num_limit) if num_limit <= tmp_rows.len() => false,
+                                    Some(num_limit) => {
+                                        param.num_result_limit(num_limit - tmp_rows.len());
+                                        true
+                                    }
+                                    None => true,
+                                };
+                                if fetch {
+                                    let resp = self.get_query_results(&param).await;
+                                    match resp {
+                                        Ok(result) => tmp_rows.extend(result),
+                                        _ => tracing::warn!("{:?}", resp),
+                                    }
                                 }
                             }
+                            if let Some(num_limit) = p.num_result_limit {
+                                tmp_rows.truncate(num_limit);
+                            }
                             tmp_rows
                         } else {
                             vec![]
                         };
-                    Ok(QueryResult::Data(bq_rows))
+                    Ok(QueryResult::Data {
+                        rows: bq_rows,
+                        total_rows,
+                        schema: schemas,
+                    })
                 }
             }
             Err(e) => Err(anyhow::anyhow!(format!("{}", e))),
         }
     }
 
+    /// Execute queries concurrently.
+    ///
+    /// The results are in the same order as `params`. Each query fetches all pages like `query`.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - request parameters of each query
+    /// * `concurrency` - maximum number of queries running at the same time
+    pub async fn query_many(
+        &self,
+        params: Vec<BqQueryParam>,
+        concurrency: usize,
+    ) -> Result<Vec<QueryResult>> {
+        let mut results: Vec<(usize, QueryResult)> = stream::iter(params.iter().enumerate())
+            .map(|(i, p)| async move { self.query(p).await.map(|r| (i, r)) })
+            .buffer_unordered(concurrency.max(1))
+            .try_collect()
+            .await?;
+        results.sort_by_key(|(i, _)| *i);
+        Ok(results.into_iter().map(|(_, r)| r).collect())
+    }
+
+    /// Same as `handle_error` but explains the error when the query exceeds `maximum_bytes_billed`.
+    fn handle_query_error<T>(result: GcpResult<T>) -> Result<T> {
+        if let Err(Error::BadRequest(value)) = &result {
+            if let Some(br) = BadRequest::from_value(value) {
+                if br.has_reason(BYTES_BILLED_LIMIT_EXCEEDED) {
+                    anyhow::bail!(
+                        "the query exceeds maximum_bytes_billed: {}",
+                        br.request_error()
+                    )
+                }
+            }
+        }
+        Bq::handle_error(result)
+    }
+
+    /// Returns true if the request failed with the HTTP status code.
+    fn is_error_status<T>(result: &GcpResult<T>, code: u16) -> bool {
+        match result {
+            Err(Error::BadRequest(v)) => BadRequest::from_value(v)
+                .map(|br| br.status_code() == code)
+                .unwrap_or(false),
+            Err(Error::Failure(res)) => res.status().as_u16() == code,
+            _ => false,
+        }
+    }
+
     fn handle_error<T>(result: GcpResult<T>) -> Result<T> {
         match result {
             Err(e) => match e {
@@ -1293,7 +2361,7 @@ impl Bq {
                 | Error::BadRequest(_)
                 | Error::FieldClash(_)
                 | Error::JsonDecodeError(_, _) => {
-                    eprintln!("{}", e);
+                    tracing::error!("{}", e);
                     Err(anyhow::anyhow!("{}", e))
                 }
             },
@@ -1313,20 +2381,20 @@ impl Bq {
         p: BqInsertAllParam,
     ) -> Result<()> {
         let mut create_param = BqCreateTableParam::new();
-        create_param.schema::<T>();
-        println!(
-            "{:?}",
-            self.create_table(&p.dataset, &p.table, create_param).await
-        );
+        create_param.schema::<T>().if_not_exists(true);
+        let created = self.create_table(&p.dataset, &p.table, create_param).await;
+        tracing::debug!("{:?}", created);
 
         let content: Vec<TableDataInsertAllRequestRows> = data
             .iter()
             .map(|d| {
                 let jstring = serde_json::to_string(d).unwrap();
                 let origin: HashMap<String, Value> = serde_json::from_str(&jstring).unwrap();
+                let insert_id = p.insert_id(|f| origin.get(f));
                 let content: HashMap<String, JsonValue> =
                     origin.into_iter().map(|(k, v)| (k, JsonValue(v))).collect();
                 let mut rows = TableDataInsertAllRequestRows::default();
+                rows.insert_id = insert_id;
                 rows.json = Some(JsonObject(Some(content)));
                 rows
             })
@@ -1336,57 +2404,76 @@ impl Bq {
         req.skip_invalid_rows = Some(p.skip_invalid_rows);
         req.rows = Some(content);
 
-        self.call_insert_all(&p, &req, 0).await
+        self.call_insert_all(&p, &req).await
+    }
+
+    /// Call insert_all API with JSON rows.
+    ///
+    /// Unlike `insert_all`, the table is not created. The table must exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `rows` - loading data. Each row must be a JSON object.
+    /// * `p` - request parameters
+    pub async fn insert_rows_json(&self, rows: Vec<Value>, p: BqInsertAllParam) -> Result<()> {
+        let content = rows
+            .into_iter()
+            .map(|row| match row {
+                Value::Object(obj) => {
+                    let insert_id = p.insert_id(|f| obj.get(f));
+                    let content: HashMap<String, JsonValue> =
+                        obj.into_iter().map(|(k, v)| (k, JsonValue(v))).collect();
+                    let mut rows = TableDataInsertAllRequestRows::default();
+                    rows.insert_id = insert_id;
+                    rows.json = Some(JsonObject(Some(content)));
+                    Ok(rows)
+                }
+                _ => Err(anyhow::anyhow!("row must be a JSON object: {}", row)),
+            })
+            .collect::<Result<Vec<TableDataInsertAllRequestRows>>>()?;
+        let mut req = TableDataInsertAllRequest::default();
+        req.ignore_unknown_values = Some(p.ignore_unknown_values);
+        req.skip_invalid_rows = Some(p.skip_invalid_rows);
+        req.rows = Some(content);
+
+        self.call_insert_all(&p, &req).await
     }
 
-    /// Call insert_all API recursively.
+    /// Call insert_all API with retry.
     ///
     /// We have to wait until the table become available if the table was created right before
-    /// calling this function.
-    #[async_recursion]
+    /// calling this function. 404, 429 and 503 are retried according to the retry policy.
     async fn call_insert_all(
         &self,
         p: &BqInsertAllParam,
         req: &TableDataInsertAllRequest,
-        retry_count: u64,
     ) -> Result<()> {
-        let mut insert_all =
-            self.api
-                .tabledata()
-                .insert_all(req.clone(), &self.project, &p.dataset, &p.table);
-        if let Some(trace_id) = p.trace_id.clone() {
-            insert_all = insert_all.param("traceid", &trace_id);
-        }
-
-        let res = insert_all.doit().await;
-        match res {
-            Err(e) => match e {
-                Error::BadRequest(_) => {
-                    if 5 < retry_count {
-                        eprintln!("{}", e);
-                        Err(anyhow::anyhow!("{}", e))
-                    } else {
-                        let interval = 100 * retry_count.pow(2);
-                        // eprintln!("{}, {}", e, interval);
-                        thread::sleep(Duration::from_millis(interval));
-                        self.call_insert_all(p, req, retry_count + 1).await
+        let res = self
+            .retry_policy
+            .retry(
+                || {
+                    let mut insert_all = self.api.tabledata().insert_all(
+                        req.clone(),
+                        &self.project,
+                        &p.dataset,
+                        &p.table,
+                    );
+                    if let Some(trace_id) = p.trace_id.as_ref() {
+                        insert_all = insert_all.param("traceid", trace_id);
                     }
-                }
-                Error::HttpError(_)
-                | Error::Io(_)
-                | Error::MissingAPIKey
-                | Error::MissingToken(_)
-                | Error::Cancelled
-                | Error::UploadSizeLimitExceeded(_, _)
-                | Error::Failure(_)
-                | Error::FieldClash(_)
-                | Error::JsonDecodeError(_, _) => {
-                    eprintln!("{}", e);
-                    Err(anyhow::anyhow!("{}", e))
-                }
-            },
-            Ok(_) => Ok(()),
-        }
+                    insert_all.doit()
+                },
+                |e| match e {
+                    // 404 is also retried because the table may not be available yet.
+                    Error::BadRequest(v) => BadRequest::from_value(v)
+                        .map(|br| br.is_retryable() || br.status_code() == 404)
+                        .unwrap_or(false),
+                    Error::Failure(res) => is_retryable_status(res.status().as_u16()),
+                    _ => false,
+                },
+            )
+            .await;
+        Bq::handle_error(res).map(|_| ())
     }
 
     /// Call list_tabledata API.
@@ -1441,7 +2528,17 @@ impl Bq {
             if let Some(token) = &res.1.page_token {
                 let mut param = p.clone();
                 param.page_token(&token);
-                tmp_rows.extend(self.list_tabledata(table, &param).await?);
+                match p.num_result_limit {
+                    Some(num_limit) if num_limit <= tmp_rows.len() => {}
+                    Some(num_limit) => {
+                        param.num_result_limit(num_limit - tmp_rows.len());
+                        tmp_rows.extend(self.list_tabledata(table, &param).await?);
+                    }
+                    None => tmp_rows.extend(self.list_tabledata(table, &param).await?),
+                }
+            }
+            if let Some(num_limit) = p.num_result_limit {
+                tmp_rows.truncate(num_limit);
             }
             tmp_rows
         } else {
@@ -1450,4 +2547,180 @@ impl Bq {
 
         Ok(bq_rows)
     }
+
+    /// Stream table rows page by page.
+    ///
+    /// Unlike `list_tabledata`, the rows are not held in memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - table to list
+    /// * `p` - `max_results` is the page size and `num_result_limit` limits the number of rows.
+    pub fn list_tabledata_stream<'a>(
+        &'a self,
+        table: &'a BqTable,
+        p: &'a BqListParam,
+    ) -> impl Stream<Item = Result<BqRow>> + 'a {
+        let table_ref = TableReference {
+            project_id: Some(table.dataset.project.clone()),
+            dataset_id: Some(table.dataset.dataset.clone()),
+            table_id: Some(table.table_id.clone()),
+        };
+        stream::once(async move {
+            let table_info = self
+                .api
+                .tables()
+                .get(
+                    &table.dataset.project,
+                    &table.dataset.dataset,
+                    &table.table_id,
+                )
+                .param("fields", "schema")
+                .doit()
+                .await;
+            let schema = Bq::handle_error(table_info)?.1.schema.unwrap_or_default();
+            // `None` state means there is no more page.
+            let pages = stream::try_unfold(
+                Some(p.page_token.clone()),
+                move |token: Option<Option<String>>| {
+                    let schema = schema.clone();
+                    let table_ref = table_ref.clone();
+                    async move {
+                        let token = match token {
+                            Some(token) => token,
+                            None => return Ok(None),
+                        };
+                        let (rows, next) = self
+                            .list_tabledata_page(&table_ref, &schema, token, p.max_results)
+                            .await?;
+                        let rows = stream::iter(rows.into_iter().map(Ok::<BqRow, anyhow::Error>));
+                        Ok::<_, anyhow::Error>(Some((rows, next.map(Some))))
+                    }
+                },
+            );
+            Ok(pages.try_flatten())
+        })
+        .try_flatten()
+        .take(p.num_result_limit.unwrap_or(usize::MAX))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use serde_json::json;
+
+    fn field(name: &str, type_: BqType, mode: BqMode, fields: Vec<BqTableSchema>) -> BqTableSchema {
+        BqTableSchema {
+            name: Some(name.to_string()),
+            type_,
+            mode,
+            fields: Box::new(fields),
+            description: None,
+        }
+    }
+
+    #[test]
+    fn repeated_record_is_decoded_as_structs() {
+        let schema = field(
+            "items",
+            BqType::RECORD,
+            BqMode::REPEATED,
+            vec![
+                field("name", BqType::STRING, BqMode::NULLABLE, vec![]),
+                field("qty", BqType::INTEGER, BqMode::NULLABLE, vec![]),
+            ],
+        );
+        let cell = json!({"v": [
+            {"v": {"f": [{"v": "apple"}, {"v": "3"}]}},
+            {"v": {"f": [{"v": "banana"}, {"v": "5"}]}},
+        ]});
+
+        let rows = match BqColumn::value_to_bq_value(Some(cell), &schema) {
+            BqValue::BqRepeated(rows) => rows,
+            v => panic!("expected BqRepeated, got {:?}", v),
+        };
+        assert_eq!(rows.len(), 2);
+        let expected = [("apple", 3), ("banana", 5)];
+        for (row, (name, qty)) in rows.iter().zip(expected) {
+            match row.as_ref() {
+                BqValue::BqStruct(r) => {
+                    assert!(matches!(r.get("name"), Some(BqValue::BqString(s)) if s == name));
+                    assert!(matches!(r.get("qty"), Some(BqValue::BqInteger(i)) if *i == qty));
+                }
+                v => panic!("expected BqStruct, got {:?}", v),
+            }
+        }
+    }
+
+    #[rstest]
+    #[case(BqType::DATE, "")]
+    #[case(BqType::TIME, "")]
+    #[case(BqType::DATETIME, "")]
+    #[case(BqType::TIMESTAMP, "")]
+    #[case(BqType::DATE, "  ")]
+    #[case(BqType::TIME, " ")]
+    #[case(BqType::DATETIME, "\t")]
+    #[case(BqType::TIMESTAMP, " \n")]
+    fn blank_date_time_string_is_null(#[case] type_: BqType, #[case] s: &str) {
+        let schema = field("t", type_, BqMode::NULLABLE, vec![]);
+        let value = BqColumn::value_to_bq_value(Some(json!(s)), &schema);
+        assert!(matches!(value, BqValue::BqNull), "got {:?}", value);
+    }
+
+    #[test]
+    fn nested_description_survives_round_trip() {
+        let mut child = field("city", BqType::STRING, BqMode::NULLABLE, vec![]);
+        child.description = Some("city name".to_string());
+        let mut parent = field("address", BqType::RECORD, BqMode::NULLABLE, vec![child]);
+        parent.description = Some("postal address".to_string());
+
+        let tfs = parent.to_table_field_schema();
+        assert_eq!(tfs.description.as_deref(), Some("postal address"));
+        let back = BqTableSchema::from_table_field_schema(&tfs);
+
+        assert_eq!(back.name.as_deref(), Some("address"));
+        assert_eq!(back.type_, BqType::RECORD);
+        assert_eq!(back.description.as_deref(), Some("postal address"));
+        assert_eq!(back.fields.len(), 1);
+        assert_eq!(back.fields[0].name.as_deref(), Some("city"));
+        assert_eq!(back.fields[0].type_, BqType::STRING);
+        assert_eq!(back.fields[0].mode, BqMode::NULLABLE);
+        assert_eq!(back.fields[0].description.as_deref(), Some("city name"));
+    }
+
+    #[test]
+    fn infer_nullable_from_missing_field() {
+        let samples = vec![
+            json!({
+                "id": 1, "name": "a", "score": 0.5, "tags": ["x"], "address": {"city": "Tokyo"}
+            }),
+            json!({
+                "id": 2, "score": null, "tags": [], "address": {"city": "Osaka", "zip": "530"}
+            }),
+        ];
+        let schemas = BqTableSchema::infer_from_json(&samples);
+        // the key order depends on serde_json features, so compare by name
+        let find = |schemas: &[BqTableSchema], name: &str| {
+            let s = schemas
+                .iter()
+                .find(|s| s.name.as_deref() == Some(name))
+                .unwrap()
+                .clone();
+            (s.type_.clone(), s.mode.clone(), s)
+        };
+        assert_eq!(schemas.len(), 5);
+        assert_eq!(find(&schemas, "id").0, BqType::INTEGER);
+        assert_eq!(find(&schemas, "id").1, BqMode::REQUIRED);
+        assert_eq!(find(&schemas, "name").1, BqMode::NULLABLE);
+        assert_eq!(find(&schemas, "score").0, BqType::FLOAT);
+        assert_eq!(find(&schemas, "score").1, BqMode::NULLABLE);
+        assert_eq!(find(&schemas, "tags").1, BqMode::REPEATED);
+        let (type_, mode, address) = find(&schemas, "address");
+        assert_eq!(type_, BqType::RECORD);
+        assert_eq!(mode, BqMode::REQUIRED);
+        assert_eq!(find(&address.fields, "city").1, BqMode::REQUIRED);
+        assert_eq!(find(&address.fields, "zip").1, BqMode::NULLABLE);
+    }
 }
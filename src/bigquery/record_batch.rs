@@ -0,0 +1,211 @@
+use super::{BqMode, BqRow, BqTableSchema, BqType, BqValue, QueryResult};
+use anyhow::Result;
+use arrow::array::{ArrayRef, BooleanArray, Float64Array, Int64Array, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Cell string of the value. Nested values become JSON.
+fn cell_string(value: &BqValue) -> Option<String> {
+    match serde_json::to_value(value) {
+        Ok(Value::Null) | Err(_) => None,
+        Ok(Value::String(s)) => Some(s),
+        // serialize the value itself to keep the field order of the struct
+        Ok(_) => serde_json::to_string(value).ok(),
+    }
+}
+
+fn to_array(rows: &[BqRow], col: usize, data_type: &DataType) -> ArrayRef {
+    let values = rows.iter().map(|r| r.columns().get(col).map(|c| c.value()));
+    match data_type {
+        DataType::Int64 => Arc::new(Int64Array::from(
+            values
+                .map(|v| v.and_then(|v| v.as_i64()))
+                .collect::<Vec<_>>(),
+        )),
+        DataType::Float64 => Arc::new(Float64Array::from(
+            values
+                .map(|v| v.and_then(|v| v.as_f64()))
+                .collect::<Vec<_>>(),
+        )),
+        DataType::Boolean => Arc::new(BooleanArray::from(
+            values
+                .map(|v| v.and_then(|v| v.as_bool()))
+                .collect::<Vec<_>>(),
+        )),
+        _ => Arc::new(StringArray::from(
+            values.map(|v| v.and_then(cell_string)).collect::<Vec<_>>(),
+        )),
+    }
+}
+
+/// Convert the rows into a `RecordBatch` with the schema.
+///
+/// INTEGER, FLOAT and BOOLEAN columns keep their types. Other columns are read as string and
+/// nested or repeated columns become JSON.
+pub fn to_record_batch(schemas: &[BqTableSchema], rows: &[BqRow]) -> Result<RecordBatch> {
+    let mut fields = Vec::new();
+    let mut columns = Vec::new();
+    for (i, schema) in schemas.iter().enumerate() {
+        let data_type = match (&schema.mode, &schema.type_) {
+            (BqMode::REPEATED, _) => DataType::Utf8,
+            (_, BqType::INTEGER) => DataType::Int64,
+            (_, BqType::FLOAT) => DataType::Float64,
+            (_, BqType::BOOLEAN) => DataType::Boolean,
+            _ => DataType::Utf8,
+        };
+        columns.push(to_array(rows, i, &data_type));
+        fields.push(Field::new(
+            schema
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("column_{}", i)),
+            data_type,
+            true,
+        ));
+    }
+    let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?;
+    Ok(batch)
+}
+
+impl QueryResult {
+    /// Convert the rows into arrow `RecordBatch`es. See `to_record_batch` for the column types.
+    ///
+    /// The dry run result has no rows, so it is converted into no batch.
+    pub fn to_record_batches(&self) -> Result<Vec<RecordBatch>> {
+        match self {
+            QueryResult::Data { rows, schema, .. } => Ok(vec![to_record_batch(schema, rows)?]),
+            QueryResult::Schema { .. } => Ok(vec![]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::BqColumn;
+    use super::*;
+    use arrow::array::Array;
+    use google_bigquery2::api::TableCell;
+    use serde_json::json;
+
+    fn field(name: &str, type_: BqType, mode: BqMode, fields: Vec<BqTableSchema>) -> BqTableSchema {
+        BqTableSchema {
+            name: Some(name.to_string()),
+            type_,
+            mode,
+            fields: Box::new(fields),
+            description: None,
+        }
+    }
+
+    fn row(schemas: &[BqTableSchema], cells: Vec<Value>) -> BqRow {
+        BqRow::new(
+            schemas
+                .iter()
+                .zip(cells)
+                .map(|(s, v)| BqColumn::new(&TableCell { v: Some(v) }, s))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn convert_rows_by_column_type() -> Result<()> {
+        let schemas = vec![
+            field("id", BqType::INTEGER, BqMode::REQUIRED, vec![]),
+            field("score", BqType::FLOAT, BqMode::NULLABLE, vec![]),
+            field("active", BqType::BOOLEAN, BqMode::NULLABLE, vec![]),
+            field("tags", BqType::STRING, BqMode::REPEATED, vec![]),
+            field(
+                "address",
+                BqType::RECORD,
+                BqMode::NULLABLE,
+                vec![
+                    field("city", BqType::STRING, BqMode::NULLABLE, vec![]),
+                    field("zip", BqType::STRING, BqMode::NULLABLE, vec![]),
+                ],
+            ),
+        ];
+        let rows = vec![
+            row(
+                &schemas,
+                vec![
+                    json!("1"),
+                    json!("0.5"),
+                    json!("true"),
+                    json!([{"v": "a"}, {"v": "b"}]),
+                    json!({"f": [{"v": "Tokyo"}, {"v": "100"}]}),
+                ],
+            ),
+            row(
+                &schemas,
+                vec![json!("2"), Value::Null, Value::Null, json!([]), Value::Null],
+            ),
+        ];
+
+        let batch = to_record_batch(&schemas, &rows)?;
+        assert_eq!(batch.num_rows(), 2);
+        let types: Vec<DataType> = batch
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| f.data_type().clone())
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                DataType::Int64,
+                DataType::Float64,
+                DataType::Boolean,
+                DataType::Utf8,
+                DataType::Utf8
+            ]
+        );
+
+        let ids = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(ids.values().to_vec(), vec![1, 2]);
+        let scores = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(scores.value(0), 0.5);
+        assert!(scores.is_null(1));
+        let active = batch
+            .column(2)
+            .as_any()
+            .downcast_ref::<BooleanArray>()
+            .unwrap();
+        assert!(active.value(0));
+        assert!(active.is_null(1));
+        let tags = batch
+            .column(3)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(tags.value(0), r#"["a","b"]"#);
+        assert_eq!(tags.value(1), "[]");
+        let address = batch
+            .column(4)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(address.value(0), r#"{"city":"Tokyo","zip":"100"}"#);
+        assert!(address.is_null(1));
+        Ok(())
+    }
+
+    #[test]
+    fn dry_run_result_has_no_batch() -> Result<()> {
+        let result = QueryResult::Schema {
+            schemas: vec![field("id", BqType::INTEGER, BqMode::NULLABLE, vec![])],
+            total_bytes_processed: Some(0),
+        };
+        assert!(result.to_record_batches()?.is_empty());
+        Ok(())
+    }
+}
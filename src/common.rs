@@ -1 +1,10 @@
 pub mod error;
+#[cfg(feature = "bigquery")]
+mod flatten;
+mod retry;
+
+#[cfg(feature = "bigquery")]
+pub(crate) use flatten::explode_row;
+#[cfg(feature = "bigquery")]
+pub use flatten::flatten_row;
+pub use retry::RetryPolicy;
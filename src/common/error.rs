@@ -34,6 +34,17 @@ impl fmt::Display for RequestError {
     }
 }
 
+impl RequestError {
+    /// HTTP status code of the error
+    pub fn status_code(&self) -> u16 {
+        match self {
+            RequestError::NotFound { code, .. }
+            | RequestError::Forbidden { code, .. }
+            | RequestError::Undefined { code, .. } => *code,
+        }
+    }
+}
+
 impl Error for RequestError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         None
@@ -43,7 +54,38 @@ impl Error for RequestError {
 const UNKNOWN_CODE: u16 = 500;
 const UNKNOWN_MESSAGE: &str = "Unknown error";
 
+/// Returns true if the request should be retried with the HTTP status code.
+///
+/// 429(Too Many Requests) and 503(Service Unavailable) are transient errors.
+pub fn is_retryable_status(code: u16) -> bool {
+    matches!(code, 429 | 503)
+}
+
 impl BadRequest {
+    /// Parse the error response body
+    pub fn from_value(value: &serde_json::Value) -> Option<BadRequest> {
+        serde_json::from_value(value.clone()).ok()
+    }
+
+    /// HTTP status code in the error response
+    pub fn status_code(&self) -> u16 {
+        self.code_message().0
+    }
+
+    /// Returns true if the request should be retried
+    pub fn is_retryable(&self) -> bool {
+        is_retryable_status(self.status_code())
+    }
+
+    /// Returns true if one of the error details has the reason
+    pub fn has_reason(&self, reason: &str) -> bool {
+        self.error
+            .as_ref()
+            .and_then(|e| e.errors.as_ref())
+            .map(|errors| errors.iter().any(|d| d.reason.as_deref() == Some(reason)))
+            .unwrap_or(false)
+    }
+
     fn code_message(&self) -> (u16, String) {
         self.error
             .as_ref()
@@ -87,3 +129,59 @@ pub struct ErrorDetail {
     pub message: Option<String>,
     pub reason: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use serde_json::json;
+
+    fn error_body(code: u16, reason: &str) -> serde_json::Value {
+        json!({
+            "error": {
+                "code": code,
+                "errors": [{"domain": "global", "message": "error message", "reason": reason}],
+                "message": "error message"
+            }
+        })
+    }
+
+    #[rstest]
+    #[case(429, "rateLimitExceeded", true)]
+    #[case(503, "backendError", true)]
+    #[case(404, "notFound", false)]
+    fn parse_error_response(#[case] code: u16, #[case] reason: &str, #[case] retryable: bool) {
+        let br = BadRequest::from_value(&error_body(code, reason)).unwrap();
+        assert_eq!(br.status_code(), code);
+        assert_eq!(br.is_retryable(), retryable);
+        assert!(br.has_reason(reason));
+        assert!(!br.has_reason("otherReason"));
+    }
+
+    #[test]
+    fn not_found_request_error() {
+        let br = BadRequest::from_value(&error_body(404, "notFound")).unwrap();
+        match br.request_error() {
+            RequestError::NotFound { code, message } => {
+                assert_eq!(code, 404);
+                assert_eq!(message, "error message");
+            }
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[rstest]
+    #[case(json!("Service Unavailable"))]
+    #[case(json!(503))]
+    fn non_json_object_body(#[case] body: serde_json::Value) {
+        assert!(BadRequest::from_value(&body).is_none());
+    }
+
+    #[test]
+    fn body_without_error() {
+        let br = BadRequest::from_value(&json!({})).unwrap();
+        assert_eq!(br.status_code(), UNKNOWN_CODE);
+        assert!(!br.is_retryable());
+        assert!(!br.has_reason("notFound"));
+    }
+}
@@ -0,0 +1,96 @@
+use crate::bigquery::{BqColumn, BqRow, BqValue};
+use indexmap::IndexMap;
+
+/// Flatten nested columns of the row into a single map with dotted keys.
+///
+/// Fields of `BqStruct` become keys like `addr.city`. Elements of `BqRepeated` become keys like
+/// `tags[0]`, or a single string joined with `join` if it is set. The column order is kept.
+///
+/// # Arguments
+///
+/// * `row` - row to be flattened
+/// * `join` - delimiter to join `BqRepeated` values. `None` indexes each element.
+pub fn flatten_row(row: &BqRow, join: Option<&str>) -> IndexMap<String, BqValue> {
+    let mut flat = IndexMap::new();
+    flatten_into(&mut flat, "", row, join);
+    flat
+}
+
+fn flatten_into(
+    flat: &mut IndexMap<String, BqValue>,
+    prefix: &str,
+    row: &BqRow,
+    join: Option<&str>,
+) {
+    for c in row.columns() {
+        let name = format!("{}{}", prefix, c.name().unwrap_or_default());
+        flatten_value(flat, name, c.value(), join);
+    }
+}
+
+fn flatten_value(
+    flat: &mut IndexMap<String, BqValue>,
+    name: String,
+    value: &BqValue,
+    join: Option<&str>,
+) {
+    match value {
+        BqValue::BqStruct(row) => flatten_into(flat, &format!("{}.", name), row, join),
+        BqValue::BqRepeated(values) => match join {
+            Some(delimiter) => {
+                let joined = values
+                    .iter()
+                    .map(|v| v.to_cell_string())
+                    .collect::<Vec<_>>()
+                    .join(delimiter);
+                flat.insert(name, BqValue::BqString(joined));
+            }
+            None => {
+                for (i, v) in values.iter().enumerate() {
+                    flatten_value(flat, format!("{}[{}]", name, i), v, join);
+                }
+            }
+        },
+        _ => {
+            flat.insert(name, value.clone());
+        }
+    }
+}
+
+/// Expand `BqRepeated` values into rows which have one element of each repeated value.
+///
+/// Multiple repeated values produce the cartesian product of them. Empty repeated values become
+/// `BqNull`. The result has no `BqRepeated`, so `flatten_row` gives the exploded columns.
+///
+/// # Arguments
+///
+/// * `row` - row to be expanded
+pub(crate) fn explode_row(row: &BqRow) -> Vec<BqRow> {
+    let mut rows: Vec<Vec<BqColumn>> = vec![vec![]];
+    for c in row.columns() {
+        let values = explode_value(c.value());
+        rows = rows
+            .iter()
+            .flat_map(|r| {
+                values.iter().map(move |v| {
+                    let mut r = r.clone();
+                    r.push(c.with_value(v.clone()));
+                    r
+                })
+            })
+            .collect();
+    }
+    rows.into_iter().map(BqRow::new).collect()
+}
+
+fn explode_value(value: &BqValue) -> Vec<BqValue> {
+    match value {
+        BqValue::BqStruct(row) => explode_row(row)
+            .into_iter()
+            .map(BqValue::BqStruct)
+            .collect(),
+        BqValue::BqRepeated(values) if values.is_empty() => vec![BqValue::BqNull],
+        BqValue::BqRepeated(values) => values.iter().flat_map(explode_value).collect(),
+        _ => vec![value.clone()],
+    }
+}
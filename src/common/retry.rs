@@ -0,0 +1,208 @@
+use rand::Rng;
+use std::future::Future;
+use std::time::Duration;
+
+/// Retry policy of API calls.
+///
+/// The delay before the n-th retry is `base_delay * n^2` and capped by `max_delay`.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts including the first call
+    pub max_attempts: u32,
+    /// Base delay of the backoff
+    pub base_delay: Duration,
+    /// Maximum delay of the backoff
+    pub max_delay: Duration,
+    /// Randomize the delay between a half and the whole of it
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 7,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            jitter: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_attempts(&mut self, v: u32) -> &mut Self {
+        self.max_attempts = v;
+        self
+    }
+
+    pub fn base_delay(&mut self, v: Duration) -> &mut Self {
+        self.base_delay = v;
+        self
+    }
+
+    pub fn max_delay(&mut self, v: Duration) -> &mut Self {
+        self.max_delay = v;
+        self
+    }
+
+    pub fn jitter(&mut self, v: bool) -> &mut Self {
+        self.jitter = v;
+        self
+    }
+
+    /// Delay before the `retry`-th retry.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(retry.saturating_mul(retry))
+            .min(self.max_delay);
+        if self.jitter && !delay.is_zero() {
+            let half = delay / 2;
+            half + rand::thread_rng().gen_range(Duration::ZERO..=half)
+        } else {
+            delay
+        }
+    }
+
+    /// Call `f` until it succeeds, the error is not retryable, or the attempts reach `max_attempts`.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - function to be called
+    /// * `retryable` - returns true if the error should be retried
+    pub async fn retry<F, Fut, T, E>(
+        &self,
+        mut f: F,
+        retryable: impl Fn(&E) -> bool,
+    ) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match f().await {
+                Err(e) if attempt < self.max_attempts && retryable(&e) => {
+                    let delay = self.delay(attempt);
+                    tracing::warn!(attempt, ?delay, "retrying request");
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        let mut policy = RetryPolicy::new();
+        policy
+            .max_attempts(max_attempts)
+            .base_delay(Duration::from_millis(1))
+            .max_delay(Duration::from_millis(5));
+        policy
+    }
+
+    #[test]
+    fn delay_grows_quadratically() {
+        let mut policy = RetryPolicy::new();
+        policy
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_secs(100));
+        assert_eq!(policy.delay(0), Duration::ZERO);
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(400));
+        assert_eq!(policy.delay(3), Duration::from_millis(900));
+    }
+
+    #[test]
+    fn delay_is_capped_by_max_delay() {
+        let mut policy = RetryPolicy::new();
+        policy
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_secs(1));
+        assert_eq!(policy.delay(4), Duration::from_secs(1));
+        assert_eq!(policy.delay(100), Duration::from_secs(1));
+        // no overflow for a large retry count
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_is_between_half_and_whole() {
+        let mut policy = RetryPolicy::new();
+        policy
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_secs(10))
+            .jitter(true);
+        for retry in 1..=5 {
+            let whole = Duration::from_millis(100) * retry * retry;
+            for _ in 0..100 {
+                let delay = policy.delay(retry);
+                assert!(whole / 2 <= delay && delay <= whole, "{:?}", delay);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn retry_gives_up_after_max_attempts() {
+        let calls = Cell::new(0);
+        let result: Result<(), &str> = policy(3)
+            .retry(
+                || {
+                    calls.set(calls.get() + 1);
+                    async { Err("unavailable") }
+                },
+                |_| true,
+            )
+            .await;
+        assert_eq!(result, Err("unavailable"));
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn retry_returns_first_success() {
+        let calls = Cell::new(0);
+        let result: Result<u32, &str> = policy(5)
+            .retry(
+                || {
+                    calls.set(calls.get() + 1);
+                    let n = calls.get();
+                    async move {
+                        if n < 3 {
+                            Err("unavailable")
+                        } else {
+                            Ok(n)
+                        }
+                    }
+                },
+                |_| true,
+            )
+            .await;
+        assert_eq!(result, Ok(3));
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn non_retryable_error_is_not_retried() {
+        let calls = Cell::new(0);
+        let result: Result<(), &str> = policy(5)
+            .retry(
+                || {
+                    calls.set(calls.get() + 1);
+                    async { Err("not found") }
+                },
+                |e| *e != "not found",
+            )
+            .await;
+        assert_eq!(result, Err("not found"));
+        assert_eq!(calls.get(), 1);
+    }
+}
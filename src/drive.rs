@@ -1,4 +1,5 @@
-use super::common::error::BadRequest;
+use super::common::error::{is_retryable_status, BadRequest};
+use super::common::RetryPolicy;
 use crate::auth;
 use anyhow;
 use anyhow::Result;
@@ -7,11 +8,12 @@ use http_body_util::BodyExt;
 use http_body_util::combinators::BoxBody;
 use chrono::{DateTime, Utc};
 use drive::{
-    api::{File, Scope},
+    api::{Change, File, Permission, Scope},
     hyper,
     common::Body,
     DriveHub, Error,
 };
+use futures::stream::{self, StreamExt, TryStreamExt};
 use google_drive3 as drive;
 use mime_guess;
 use rayon::prelude::*;
@@ -20,6 +22,18 @@ use std::io::prelude::*;
 
 pub struct Drive {
     api: DriveHub<auth::HttpsConnector>,
+    retry_policy: RetryPolicy,
+}
+
+/// Returns true if the error is transient and the request should be retried
+fn is_retryable(e: &Error) -> bool {
+    match e {
+        Error::BadRequest(v) => BadRequest::from_value(v)
+            .map(|br| br.is_retryable())
+            .unwrap_or(false),
+        Error::Failure(res) => is_retryable_status(res.status().as_u16()),
+        _ => false,
+    }
 }
 
 pub trait Exportable {
@@ -97,7 +111,7 @@ impl Exportable for SheetExportMimeType {
     fn mime_type(&self) -> &'static str {
         match self {
             SheetExportMimeType::Excel => {
-                "	application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
             }
             SheetExportMimeType::OpenDocument => "application/x-vnd.oasis.opendocument.spreadsheet",
             SheetExportMimeType::PDF => "application/pdf",
@@ -247,13 +261,130 @@ impl DriveFile {
     }
 }
 
+/// Role of the Drive permission
+#[derive(Debug, Clone, PartialEq)]
+pub enum DriveRole {
+    Reader,
+    Commenter,
+    Writer,
+    Owner,
+}
+
+impl DriveRole {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DriveRole::Reader => "reader",
+            DriveRole::Commenter => "commenter",
+            DriveRole::Writer => "writer",
+            DriveRole::Owner => "owner",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DrivePermission {
+    /// Permission ID
+    pub id: Option<String>,
+
+    /// Role like `reader`, `writer`
+    pub role: Option<String>,
+
+    /// Grantee type like `user`, `group`, `domain`, `anyone`
+    pub type_: Option<String>,
+
+    /// Email address of the user or group
+    pub email_address: Option<String>,
+
+    /// Display name of the grantee
+    pub display_name: Option<String>,
+}
+
+impl DrivePermission {
+    fn from_permission(p: &Permission) -> Self {
+        DrivePermission {
+            id: p.id.to_owned(),
+            role: p.role.to_owned(),
+            type_: p.type_.to_owned(),
+            email_address: p.email_address.to_owned(),
+            display_name: p.display_name.to_owned(),
+        }
+    }
+}
+
+const PERMISSION_FIELDS: &str = "id,role,type,emailAddress,displayName";
+
+/// Kind of the change
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum DriveChangeKind {
+    /// The file was created. The file which has never been modified after creation is treated as added.
+    Added,
+    Modified,
+    /// The file was removed or trashed
+    Removed,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DriveChange {
+    pub kind: DriveChangeKind,
+
+    /// Drive's file ID
+    pub file_id: Option<String>,
+
+    /// Changed file. This is not set for the removed file.
+    pub file: Option<DriveFile>,
+
+    /// Time of the change
+    pub time: Option<DateTime<Utc>>,
+}
+
+impl DriveChange {
+    fn from_change(c: &Change) -> Self {
+        let trashed = c.file.as_ref().and_then(|f| f.trashed).unwrap_or(false);
+        let file = c.file.as_ref().map(|f| DriveFile::from_file(f));
+        let kind = if c.removed.unwrap_or(false) || trashed {
+            DriveChangeKind::Removed
+        } else if file
+            .as_ref()
+            .map(|f| f.created_at.is_some() && f.created_at == f.modified_at)
+            .unwrap_or(false)
+        {
+            DriveChangeKind::Added
+        } else {
+            DriveChangeKind::Modified
+        };
+        let file = if kind == DriveChangeKind::Removed {
+            None
+        } else {
+            file
+        };
+        DriveChange {
+            kind,
+            file_id: c.file_id.to_owned(),
+            file,
+            time: c.time,
+        }
+    }
+}
+
 const RESPONSE_FIELDS: &str = "id,name,createdTime,modifiedTime,size,mimeType,fileExtension,driveId,parents,webViewLink,owners";
 
 impl Drive {
     pub fn new(auth: &auth::GcpAuth) -> Self {
-        let client = auth::new_client();
+        Drive::with_client(auth, auth::new_client())
+    }
+
+    /// Create with the HTTP client built by `auth::new_client_with`
+    pub fn with_client(auth: &auth::GcpAuth, client: auth::HttpClient) -> Self {
         let api = DriveHub::new(client, auth.authenticator());
-        Drive { api }
+        Drive {
+            api,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    pub fn retry_policy(&mut self, policy: RetryPolicy) -> &mut Self {
+        self.retry_policy = policy;
+        self
     }
 
     /// Upload a loacal file to Drive.
@@ -266,19 +397,28 @@ impl Drive {
     pub async fn create_file(&self, name: &str, parents: Option<Vec<String>>) -> Result<DriveFile> {
         let path = std::path::Path::new(&name);
         let file_name = path.file_name().unwrap().to_str();
-        let mime = mime_guess::from_path(path).first_or_octet_stream();
-        let infile = std::fs::File::open(&name)?;
+        let mime = &mime_guess::from_path(path).first_or_octet_stream();
 
         let mut file = File::default();
         file.name = Some(String::from(file_name.unwrap()));
         file.mime_type = Some(mime.to_string());
         file.parents = parents.to_owned();
+        let file = &file;
         let res = self
-            .api
-            .files()
-            .create(file)
-            .param("fields", RESPONSE_FIELDS)
-            .upload_resumable(infile, mime)
+            .retry_policy
+            .retry(
+                || async move {
+                    // open the file for each attempt to upload from the beginning
+                    let infile = std::fs::File::open(name).map_err(Error::Io)?;
+                    self.api
+                        .files()
+                        .create(file.clone())
+                        .param("fields", RESPONSE_FIELDS)
+                        .upload_resumable(infile, mime.clone())
+                        .await
+                },
+                is_retryable,
+            )
             .await;
         let result = match res {
             Ok(result) => result,
@@ -299,7 +439,7 @@ impl Drive {
                 | Error::Failure(_)
                 | Error::FieldClash(_)
                 | Error::JsonDecodeError(_, _) => {
-                    eprintln!("{}", e);
+                    tracing::error!("{}", e);
                     anyhow::bail!(e)
                 }
             },
@@ -351,7 +491,7 @@ impl Drive {
                 | Error::Failure(_)
                 | Error::FieldClash(_)
                 | Error::JsonDecodeError(_, _) => {
-                    eprintln!("{}", e);
+                    tracing::error!("{}", e);
                     anyhow::bail!(e)
                 }
             },
@@ -375,27 +515,35 @@ impl Drive {
         &'async_recursion self,
         p: &'async_recursion DriveListParam,
     ) -> Result<Vec<DriveFile>> {
-        let mut list = self
-            .api
-            .files()
-            .list()
-            .corpora("allDrives")
-            //.drive_id(&p.drive_id)
-            .include_items_from_all_drives(true)
-            .supports_all_drives(true)
-            .param(
-                "fields",
-                &format!("nextPageToken, files({})", RESPONSE_FIELDS),
-            );
-        if let Some(query) = &p.query {
-            list = list.q(&format!("{} and trashed=false", query));
-        } else {
-            list = list.q("trashed=false");
-        }
-        if let Some(token) = &p.next_token {
-            list = list.page_token(&token);
-        }
-        let res = list.doit().await;
+        let res = self
+            .retry_policy
+            .retry(
+                || {
+                    let mut list = self
+                        .api
+                        .files()
+                        .list()
+                        .corpora("allDrives")
+                        //.drive_id(&p.drive_id)
+                        .include_items_from_all_drives(true)
+                        .supports_all_drives(true)
+                        .param(
+                            "fields",
+                            &format!("nextPageToken, files({})", RESPONSE_FIELDS),
+                        );
+                    if let Some(query) = &p.query {
+                        list = list.q(&format!("{} and trashed=false", query));
+                    } else {
+                        list = list.q("trashed=false");
+                    }
+                    if let Some(token) = &p.next_token {
+                        list = list.page_token(&token);
+                    }
+                    list.doit()
+                },
+                is_retryable,
+            )
+            .await;
         //println!("{:?}", res);
         let result = match res {
             Ok(result) => result,
@@ -416,7 +564,7 @@ impl Drive {
                 | Error::Failure(_)
                 | Error::FieldClash(_)
                 | Error::JsonDecodeError(_, _) => {
-                    eprintln!("{}", e);
+                    tracing::error!("{}", e);
                     anyhow::bail!(e)
                 }
             },
@@ -439,6 +587,55 @@ impl Drive {
         Ok(files)
     }
 
+    /// List the files in the trash.
+    pub async fn list_trashed(&self) -> Result<Vec<DriveFile>> {
+        let mut files = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let token = &page_token;
+            let res = self
+                .retry_policy
+                .retry(
+                    || {
+                        let mut list = self
+                            .api
+                            .files()
+                            .list()
+                            .corpora("allDrives")
+                            .include_items_from_all_drives(true)
+                            .supports_all_drives(true)
+                            .q("trashed=true")
+                            .param(
+                                "fields",
+                                &format!("nextPageToken, files({})", RESPONSE_FIELDS),
+                            );
+                        if let Some(token) = token {
+                            list = list.page_token(token);
+                        }
+                        list.doit()
+                    },
+                    is_retryable,
+                )
+                .await?;
+            if let Some(fs) = res.1.files {
+                files.extend(fs.iter().map(|f| DriveFile::from_file(f)));
+            }
+            page_token = res.1.next_page_token;
+            if page_token.is_none() {
+                break;
+            }
+        }
+        Ok(files)
+    }
+
+    /// Permanently delete all the files in the trash of the user.
+    pub async fn empty_trash(&self) -> Result<()> {
+        self.retry_policy
+            .retry(|| self.api.files().empty_trash().doit(), is_retryable)
+            .await?;
+        Ok(())
+    }
+
     /// Get file metadata from Drive.
     /// metadata:
     /// - id
@@ -457,18 +654,41 @@ impl Drive {
     /// * `file_id`: target file's drive id
     pub async fn get_file_meta_by_id(&self, file_id: &str) -> Result<DriveFile> {
         let res = self
-            .api
-            .files()
-            .get(file_id)
-            .param("fields", RESPONSE_FIELDS)
-            .add_scope(Scope::Readonly)
-            .doit()
+            .retry_policy
+            .retry(
+                || {
+                    self.api
+                        .files()
+                        .get(file_id)
+                        .param("fields", RESPONSE_FIELDS)
+                        .add_scope(Scope::Readonly)
+                        .doit()
+                },
+                is_retryable,
+            )
             .await?;
         //println!("{:?}", res);
         let file = DriveFile::from_file(&res.1);
         Ok(file)
     }
 
+    /// Get metadata of the files concurrently. The result is in the same order as `ids`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids`: target files' drive id
+    /// * `concurrency`: the number of requests running at the same time
+    pub async fn get_files_meta(&self, ids: &[&str], concurrency: usize) -> Result<Vec<DriveFile>> {
+        anyhow::ensure!(0 < concurrency, "concurrency must be greater than 0");
+        let mut files: Vec<(usize, DriveFile)> = stream::iter(ids.iter().enumerate())
+            .map(|(i, id)| async move { self.get_file_meta_by_id(id).await.map(|f| (i, f)) })
+            .buffer_unordered(concurrency)
+            .try_collect()
+            .await?;
+        files.sort_by_key(|(i, _)| *i);
+        Ok(files.into_iter().map(|(_, f)| f).collect())
+    }
+
     /// Get(download) file from Drive. The target file may be downloaded and saved locally.
     ///
     /// # Arguments
@@ -481,12 +701,18 @@ impl Drive {
 
     pub async fn get_file_stream(&self, file_id: &String) -> Result<hyper::Response<Body>> {
         let res = self
-            .api
-            .files()
-            .get(file_id)
-            .param("alt", "media")
-            .add_scope(Scope::Readonly)
-            .doit()
+            .retry_policy
+            .retry(
+                || {
+                    self.api
+                        .files()
+                        .get(file_id)
+                        .param("alt", "media")
+                        .add_scope(Scope::Readonly)
+                        .doit()
+                },
+                is_retryable,
+            )
             .await?;
         Ok(res.0)
     }
@@ -497,14 +723,23 @@ impl Drive {
     ///
     /// * `file`: target file object. Before calling, you need to list and get the file object.
     pub async fn get_file(&self, file: DriveFile) -> Result<DriveFile> {
+        let bytes = self.get_file_bytes(&file).await?;
+        let mut f = std::fs::File::create(&file.name).unwrap();
+        f.write_all(&bytes)?;
+        Ok(file)
+    }
+
+    /// Get(download) file content from Drive into memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `file`: target file object. Before calling, you need to list and get the file object.
+    pub async fn get_file_bytes(&self, file: &DriveFile) -> Result<Vec<u8>> {
         anyhow::ensure!(file.id.is_some(), "input file does not have id");
 
         let res = self.get_file_stream(file.id.as_ref().unwrap()).await?;
-        //println!("{:?}", res);
-        let mut f = std::fs::File::create(&file.name).unwrap();
         let bytes = res.into_body().collect().await?.to_bytes();
-        f.write_all(&bytes)?;
-        Ok(file)
+        Ok(bytes.into())
     }
 
     /// Export file from Drive. The target file shall be downloaded and saved locally.
@@ -533,6 +768,46 @@ impl Drive {
         file: DriveFile,
         mime_type: impl Exportable,
     ) -> Result<DriveFile> {
+        let bytes = self.export_file_bytes(&file, &mime_type).await?;
+        let mut f =
+            std::fs::File::create(&format!("{}.{}", file.name, mime_type.extension())).unwrap();
+        f.write_all(&bytes)?;
+        Ok(file)
+    }
+
+    /// Export Google Docs, Sheets and Slides file as Word, Excel and PowerPoint file respectively.
+    ///
+    /// # Arguments
+    ///
+    /// * `file`: target file object. Before calling, you need to list and get the file object.
+    pub async fn export_file_default(&self, file: DriveFile) -> Result<DriveFile> {
+        let mime_type = file.mime_type.clone().unwrap_or_default();
+        match mime_type.as_str() {
+            "application/vnd.google-apps.document" => {
+                self.export_file(file, DocumentExportMimeType::Word).await
+            }
+            "application/vnd.google-apps.spreadsheet" => {
+                self.export_file(file, SheetExportMimeType::Excel).await
+            }
+            "application/vnd.google-apps.presentation" => {
+                self.export_file(file, PresentationExportMimeType::PowerPoint)
+                    .await
+            }
+            _ => anyhow::bail!("{} is not exportable: {}", file.name, mime_type),
+        }
+    }
+
+    /// Export file from Drive into memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `file`: target file object. Before calling, you need to list and get the file object.
+    /// * `mime_type`: export mime type
+    pub async fn export_file_bytes(
+        &self,
+        file: &DriveFile,
+        mime_type: &impl Exportable,
+    ) -> Result<Vec<u8>> {
         anyhow::ensure!(
             mime_type.valid(file.mime_type.as_ref().unwrap_or(&String::from(""))),
             format!(
@@ -550,11 +825,157 @@ impl Drive {
             .add_scope(Scope::Readonly)
             .doit()
             .await?;
-        //println!("{:?}", res);
-        let mut f =
-            std::fs::File::create(&format!("{}.{}", file.name, mime_type.extension())).unwrap();
         let bytes = res.into_body().collect().await?.to_bytes();
-        f.write_all(&bytes)?;
-        Ok(file)
+        Ok(bytes.into())
+    }
+
+    /// Share the file with the user.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_id`: target file's drive id
+    /// * `email`: email address of the user
+    /// * `role`: role to be granted. `Owner` transfers the ownership.
+    /// * `notify`: send notification email to the user
+    pub async fn share_file(
+        &self,
+        file_id: &str,
+        email: &str,
+        role: DriveRole,
+        notify: bool,
+    ) -> Result<()> {
+        let mut permission = Permission::default();
+        permission.type_ = Some(String::from("user"));
+        permission.role = Some(role.as_str().to_string());
+        permission.email_address = Some(email.to_string());
+        let permission = &permission;
+        let transfer_ownership = role == DriveRole::Owner;
+        self.retry_policy
+            .retry(
+                || {
+                    self.api
+                        .permissions()
+                        .create(permission.clone(), file_id)
+                        .send_notification_email(notify)
+                        .transfer_ownership(transfer_ownership)
+                        .supports_all_drives(true)
+                        .doit()
+                },
+                is_retryable,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// List permissions of the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `file_id`: target file's drive id
+    pub async fn list_permissions(&self, file_id: &str) -> Result<Vec<DrivePermission>> {
+        let mut permissions = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let token = &page_token;
+            let res = self
+                .retry_policy
+                .retry(
+                    || {
+                        let mut list = self
+                            .api
+                            .permissions()
+                            .list(file_id)
+                            .supports_all_drives(true)
+                            .param(
+                                "fields",
+                                &format!("nextPageToken, permissions({})", PERMISSION_FIELDS),
+                            );
+                        if let Some(token) = token {
+                            list = list.page_token(token);
+                        }
+                        list.doit()
+                    },
+                    is_retryable,
+                )
+                .await?;
+            if let Some(ps) = res.1.permissions {
+                permissions.extend(ps.iter().map(|p| DrivePermission::from_permission(p)));
+            }
+            page_token = res.1.next_page_token;
+            if page_token.is_none() {
+                break;
+            }
+        }
+        Ok(permissions)
+    }
+
+    /// Get the token to start listing the future changes.
+    pub async fn get_start_page_token(&self) -> Result<String> {
+        let res = self
+            .retry_policy
+            .retry(
+                || {
+                    self.api
+                        .changes()
+                        .get_start_page_token()
+                        .supports_all_drives(true)
+                        .doit()
+                },
+                is_retryable,
+            )
+            .await?;
+        res.1
+            .start_page_token
+            .ok_or_else(|| anyhow::anyhow!("there is no start page token"))
+    }
+
+    /// List the changes since the token and return the token for the next call.
+    ///
+    /// # Arguments
+    ///
+    /// * `start_page_token`: token returned by the previous call or `get_start_page_token`. If it
+    ///   is not set, the changes from now are tracked, so the result is empty.
+    pub async fn list_changes(
+        &self,
+        start_page_token: Option<String>,
+    ) -> Result<(Vec<DriveChange>, String)> {
+        let mut page_token = match start_page_token {
+            Some(token) => token,
+            None => self.get_start_page_token().await?,
+        };
+        let fields = format!(
+            "nextPageToken,newStartPageToken,changes(fileId,removed,time,file({},trashed))",
+            RESPONSE_FIELDS
+        );
+        let mut changes = Vec::new();
+        loop {
+            let token = &page_token;
+            let res = self
+                .retry_policy
+                .retry(
+                    || {
+                        self.api
+                            .changes()
+                            .list(token)
+                            .include_removed(true)
+                            .supports_all_drives(true)
+                            .include_items_from_all_drives(true)
+                            .param("fields", &fields)
+                            .doit()
+                    },
+                    is_retryable,
+                )
+                .await?;
+            if let Some(cs) = res.1.changes {
+                changes.extend(cs.iter().map(|c| DriveChange::from_change(c)));
+            }
+            if let Some(new_start) = res.1.new_start_page_token {
+                return Ok((changes, new_start));
+            }
+            page_token = res
+                .1
+                .next_page_token
+                .ok_or_else(|| anyhow::anyhow!("there is no next page token"))?;
+        }
     }
 }
@@ -1,23 +1,41 @@
-use super::common::error::BadRequest;
+use super::common::error::{is_retryable_status, BadRequest, RequestError};
+use super::common::RetryPolicy;
 use crate::auth;
-use gcs::{api::Object, hyper, Error, Storage};
+use auth::hyper_util::client::legacy::Client;
+use gcs::{
+    api::{Bucket, Buckets, Object},
+    hyper, Error, Storage,
+};
 use http_body_util::BodyExt;
 use http_body_util::combinators::BoxBody;
+use http_body_util::Full;
 use hyper::body::Bytes;
 use google_storage1 as gcs;
 use mime;
+use std::collections::HashMap;
 use std::fs;
 use std::io::Cursor;
 use urlencoding;
 
 use chrono::{DateTime, Utc};
+use flate2::{write::GzEncoder, Compression};
+use futures::stream::{self, StreamExt};
 
 use anyhow;
 use anyhow::Result;
 use async_recursion::async_recursion;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use md5::{Digest, Md5};
+#[cfg(test)]
+use mockall::automock;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::io::{Read, Seek};
+use std::future::Future;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWriteExt, ReadBuf};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct GcsObject {
@@ -42,9 +60,37 @@ pub struct GcsObject {
     /// Updated At
     pub updated_at: Option<DateTime<Utc>>,
 
-    /// The content
+    /// User-provided metadata
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<HashMap<String, String>>,
+
+    /// Storage class like STANDARD, NEARLINE
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage_class: Option<String>,
+
+    /// MD5 hash of the data, encoded using base64
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub md5_hash: Option<String>,
+
+    /// CRC32c checksum, encoded using base64 in big-endian byte order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crc32c: Option<String>,
+
+    /// The content generation of this object
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generation: Option<i64>,
+
+    /// Content-Encoding of the object like `gzip`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_encoding: Option<String>,
+
+    /// The content as text. This is set only if the content is valid UTF-8.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content: Option<String>,
+
+    /// The raw content
+    #[serde(skip)]
+    pub content_bytes: Option<Vec<u8>>,
 }
 
 impl GcsObject {
@@ -57,7 +103,14 @@ impl GcsObject {
             self_link: None,
             created_at: None,
             updated_at: None,
+            metadata: None,
+            storage_class: None,
+            md5_hash: None,
+            crc32c: None,
+            generation: None,
+            content_encoding: None,
             content: None,
+            content_bytes: None,
         }
     }
 
@@ -78,6 +131,12 @@ impl GcsObject {
         self
     }
 
+    /// Get the raw content
+    ///
+    pub fn bytes(&self) -> Option<&[u8]> {
+        self.content_bytes.as_deref()
+    }
+
     /// Get path of this object
     ///
     pub fn url(&self) -> String {
@@ -102,8 +161,15 @@ impl GcsObject {
             size,
             self_link,
             content: None,
+            content_bytes: None,
             created_at,
             updated_at,
+            metadata: item.metadata.clone(),
+            storage_class: item.storage_class.clone(),
+            md5_hash: item.md5_hash.clone(),
+            crc32c: item.crc32c.clone(),
+            generation: item.generation,
+            content_encoding: item.content_encoding.clone(),
         }
     }
 
@@ -115,16 +181,81 @@ impl GcsObject {
         object.self_link = self.self_link.as_ref().map(|l| l.to_string());
         object.time_created = self.created_at;
         object.updated = self.updated_at;
+        object.metadata = self.metadata.clone();
+        object.storage_class = self.storage_class.clone();
         object
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct GcsInsertParam {}
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GcsBucket {
+    /// Bucket name
+    pub name: String,
+
+    /// Location like US, ASIA-NORTHEAST1
+    pub location: Option<String>,
+
+    /// Location type like multi-region, region
+    pub location_type: Option<String>,
+
+    /// Default storage class like STANDARD, NEARLINE
+    pub storage_class: Option<String>,
+
+    /// Created At
+    pub created_at: Option<DateTime<Utc>>,
+
+    /// Updated At
+    pub updated_at: Option<DateTime<Utc>>,
+
+    /// Object versioning is enabled
+    pub versioning: bool,
+
+    /// User-provided labels
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<HashMap<String, String>>,
+}
+
+impl GcsBucket {
+    pub fn from_bucket(bucket: &Bucket) -> Self {
+        GcsBucket {
+            name: bucket.name.clone().unwrap_or_default(),
+            location: bucket.location.clone(),
+            location_type: bucket.location_type.clone(),
+            storage_class: bucket.storage_class.clone(),
+            created_at: bucket.time_created,
+            updated_at: bucket.updated,
+            versioning: bucket
+                .versioning
+                .as_ref()
+                .and_then(|v| v.enabled)
+                .unwrap_or(false),
+            labels: bucket.labels.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct GcsInsertParam {
+    content_encoding: Option<String>,
+    cache_control: Option<String>,
+}
 
 impl GcsInsertParam {
     pub fn new() -> Self {
-        Self {}
+        Self::default()
+    }
+
+    /// Content-Encoding of the object like `gzip`. GCS decompresses it on download if the
+    /// client does not accept the encoding.
+    pub fn content_encoding(&mut self, v: &str) -> &mut Self {
+        self.content_encoding = Some(v.to_string());
+        self
+    }
+
+    /// Cache-Control of the object like `public, max-age=3600`
+    pub fn cache_control(&mut self, v: &str) -> &mut Self {
+        self.cache_control = Some(v.to_string());
+        self
     }
 }
 
@@ -148,6 +279,9 @@ pub struct GcsListParam {
 
     /// End offset
     end_offset: Option<String>,
+
+    /// Maximum number of objects fetched across pages
+    limit: Option<usize>,
 }
 
 impl GcsListParam {
@@ -189,18 +323,92 @@ impl GcsListParam {
         self.end_offset = Some(p.to_string());
         self
     }
+
+    /// Maximum number of objects fetched across pages
+    pub fn limit(&mut self, limit: usize) -> &mut Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+/// Returns true if the error is transient and the request should be retried
+fn is_retryable(e: &Error) -> bool {
+    match e {
+        Error::BadRequest(v) => BadRequest::from_value(v)
+            .map(|br| br.is_retryable())
+            .unwrap_or(false),
+        Error::Failure(res) => is_retryable_status(res.status().as_u16()),
+        _ => false,
+    }
+}
+
+/// Resumable upload session.
+///
+/// The session is valid for a week. Save it to resume the upload after the process restarts.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ResumableSession {
+    /// Session URI
+    pub uri: String,
+
+    /// Bucket name
+    pub bucket: String,
+
+    /// Name of the object
+    pub name: Option<String>,
+}
+
+impl ResumableSession {
+    /// Save the session as JSON file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Load the session from JSON file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
 }
 
+const UPLOAD_URL: &str = "https://storage.googleapis.com/upload/storage/v1/b";
+const DOWNLOAD_URL: &str = "https://storage.googleapis.com/storage/v1/b";
+const STORAGE_SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_write";
+/// Chunk size of the resumable upload. This must be a multiple of 256 KiB.
+const RESUMABLE_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+/// Size of a range request of `GcsObjectReader`
+const READER_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+#[derive(Clone)]
 pub struct Gcs {
     api: Storage<auth::HttpsConnector>,
     bucket: String,
+    retry_policy: RetryPolicy,
+
+    /// Used for the requests which the API client does not support
+    client: Client<auth::HttpsConnector, gcs::common::Body>,
+    auth: auth::Authenticator<auth::HttpsConnector>,
 }
 
 impl Gcs {
     pub fn new(auth: &auth::GcpAuth, bucket: String) -> Gcs {
-        let client = auth::new_client();
-        let api = Storage::new(client, auth.authenticator());
-        Gcs { api, bucket }
+        Gcs::with_client(auth, bucket, auth::new_client())
+    }
+
+    /// Create with the HTTP client built by `auth::new_client_with`
+    pub fn with_client(auth: &auth::GcpAuth, bucket: String, client: auth::HttpClient) -> Gcs {
+        let api = Storage::new(client.clone(), auth.authenticator());
+        Gcs {
+            api,
+            bucket,
+            retry_policy: RetryPolicy::default(),
+            client,
+            auth: auth.authenticator(),
+        }
+    }
+
+    pub fn retry_policy(&mut self, policy: RetryPolicy) -> &mut Self {
+        self.retry_policy = policy;
+        self
     }
 
     /// call objects/list API
@@ -213,31 +421,39 @@ impl Gcs {
         &'async_recursion self,
         p: &'async_recursion GcsListParam,
     ) -> Result<Vec<GcsObject>> {
-        let mut gcs = self.api.objects().list(&self.bucket);
-        if let Some(mr) = p.max_results {
-            gcs = gcs.max_results(mr);
-        }
-        if let Some(pf) = &p.prefix {
-            gcs = gcs.prefix(&pf);
-        }
-        if let Some(de) = &p.delimiter {
-            gcs = gcs.delimiter(&de);
-        } else {
-            // get necessary parameters only.
-            // reference: https://cloud.google.com/storage/docs/json_api/v1/objects
-            gcs = gcs.param("fields",
-                "items/id,items/bucket,items/name,items/selfLink,items/size,items/contentType,items/timeCreated,items/updated,nextPageToken,prefixes");
-        }
-        if let Some(token) = &p.next_token {
-            gcs = gcs.page_token(&token);
-        }
-        if let Some(so) = &p.start_offset {
-            gcs = gcs.start_offset(&so);
-        }
-        if let Some(eo) = &p.end_offset {
-            gcs = gcs.end_offset(&eo);
-        }
-        let res = gcs.doit().await;
+        let res = self
+            .retry_policy
+            .retry(
+                || {
+                    let mut gcs = self.api.objects().list(&self.bucket);
+                    if let Some(mr) = p.max_results {
+                        gcs = gcs.max_results(mr);
+                    }
+                    if let Some(pf) = &p.prefix {
+                        gcs = gcs.prefix(&pf);
+                    }
+                    if let Some(de) = &p.delimiter {
+                        gcs = gcs.delimiter(&de);
+                    } else {
+                        // get necessary parameters only.
+                        // reference: https://cloud.google.com/storage/docs/json_api/v1/objects
+                        gcs = gcs.param("fields",
+                            "items/id,items/bucket,items/name,items/selfLink,items/size,items/contentType,items/timeCreated,items/updated,items/metadata,items/storageClass,items/md5Hash,items/crc32c,items/generation,nextPageToken,prefixes");
+                    }
+                    if let Some(token) = &p.next_token {
+                        gcs = gcs.page_token(&token);
+                    }
+                    if let Some(so) = &p.start_offset {
+                        gcs = gcs.start_offset(&so);
+                    }
+                    if let Some(eo) = &p.end_offset {
+                        gcs = gcs.end_offset(&eo);
+                    }
+                    gcs.doit()
+                },
+                is_retryable,
+            )
+            .await;
         let result = match res {
             Ok(result) => result,
             Err(e) => match e {
@@ -257,12 +473,12 @@ impl Gcs {
                 | Error::Failure(_)
                 | Error::FieldClash(_)
                 | Error::JsonDecodeError(_, _) => {
-                    eprintln!("{}", e);
+                    tracing::error!("{}", e);
                     anyhow::bail!(e)
                 }
             },
         };
-        let objects = match &p.delimiter {
+        let mut objects = match &p.delimiter {
             Some(_) => match result.1.prefixes {
                 Some(prefixes) => prefixes
                     .par_iter()
@@ -273,8 +489,15 @@ impl Gcs {
                         size: None,
                         self_link: None,
                         content: None,
+                        content_bytes: None,
                         created_at: None,
                         updated_at: None,
+                        metadata: None,
+                        storage_class: None,
+                        md5_hash: None,
+                        crc32c: None,
+                        generation: None,
+                        content_encoding: None,
                     })
                     .collect(),
                 None => Vec::new(),
@@ -290,16 +513,70 @@ impl Gcs {
                 if let Some(token) = result.1.next_page_token {
                     let mut param = p.clone();
                     param.next_token(&token);
-                    let additionals = self.list_objects(&param).await?;
-                    objects.extend(additionals);
+                    match p.limit {
+                        Some(limit) if limit <= objects.len() => {}
+                        Some(limit) => {
+                            param.limit(limit - objects.len());
+                            objects.extend(self.list_objects(&param).await?);
+                        }
+                        None => objects.extend(self.list_objects(&param).await?),
+                    }
                 };
 
                 objects
             }
         };
+        if let Some(limit) = p.limit {
+            objects.truncate(limit);
+        }
         Ok(objects)
     }
 
+    /// call buckets/list API
+    ///
+    /// All pages are fetched by following the next page token.
+    ///
+    /// # Arguments
+    ///
+    /// * `project` - project ID which owns the buckets
+    /// * `p` - request parameters. `prefix`, `max_results` and `next_token` are used.
+    pub async fn list_buckets(&self, project: &str, p: &GcsListParam) -> Result<Vec<GcsBucket>> {
+        collect_buckets(self, project, p).await
+    }
+
+    /// Get the bucket metadata.
+    pub async fn get_bucket(&self) -> Result<GcsBucket> {
+        let res = self
+            .retry_policy
+            .retry(|| self.api.buckets().get(&self.bucket).doit(), is_retryable)
+            .await;
+        let content = match res {
+            Ok(result) => result,
+            Err(e) => match e {
+                Error::BadRequest(badrequest) => {
+                    if let Ok(br) = serde_json::from_value::<BadRequest>(badrequest.clone()) {
+                        anyhow::bail!(br.request_error())
+                    } else {
+                        anyhow::bail!(badrequest)
+                    }
+                }
+                Error::HttpError(_)
+                | Error::Io(_)
+                | Error::MissingAPIKey
+                | Error::MissingToken(_)
+                | Error::Cancelled
+                | Error::UploadSizeLimitExceeded(_, _)
+                | Error::Failure(_)
+                | Error::FieldClash(_)
+                | Error::JsonDecodeError(_, _) => {
+                    tracing::error!("{}", e);
+                    anyhow::bail!(e)
+                }
+            },
+        };
+        Ok(GcsBucket::from_bucket(&content.1))
+    }
+
     /// Get object metadata.
     ///
     /// # Arguments
@@ -307,11 +584,17 @@ impl Gcs {
     /// * `name` - target object name
     pub async fn get_object_metadata(&self, name: String) -> Result<GcsObject> {
         let res = self
-            .api
-            .objects()
-            .get(&self.bucket, &urlencoding::encode(&name))
-            .param("alt", "json")
-            .doit()
+            .retry_policy
+            .retry(
+                || {
+                    self.api
+                        .objects()
+                        .get(&self.bucket, &urlencoding::encode(&name))
+                        .param("alt", "json")
+                        .doit()
+                },
+                is_retryable,
+            )
             .await;
         let content = match res {
             Ok(result) => result,
@@ -332,7 +615,7 @@ impl Gcs {
                 | Error::Failure(_)
                 | Error::FieldClash(_)
                 | Error::JsonDecodeError(_, _) => {
-                    eprintln!("{}", e);
+                    tracing::error!("{}", e);
                     anyhow::bail!(e)
                 }
             },
@@ -340,6 +623,21 @@ impl Gcs {
         Ok(GcsObject::from_object(&self.bucket, &content.1))
     }
 
+    /// Returns true if the object exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - target object name
+    pub async fn exists(&self, name: &str) -> Result<bool> {
+        match self.get_object_metadata(name.to_string()).await {
+            Ok(_) => Ok(true),
+            Err(e) => match e.downcast_ref::<RequestError>() {
+                Some(RequestError::NotFound { .. }) => Ok(false),
+                _ => Err(e),
+            },
+        }
+    }
+
     /// Get object and store `GcsObject` instance
     ///
     /// # Arguments
@@ -349,34 +647,67 @@ impl Gcs {
         match &object.name {
             Some(name) => {
                 let content = self
-                    .api
-                    .objects()
-                    .get(&self.bucket, &urlencoding::encode(&name))
-                    .param("alt", "media")
-                    .doit()
+                    .retry_policy
+                    .retry(
+                        || {
+                            self.api
+                                .objects()
+                                .get(&self.bucket, &urlencoding::encode(&name))
+                                .param("alt", "media")
+                                .doit()
+                        },
+                        is_retryable,
+                    )
                     .await?;
                 //println!("{:?}", content);
-                let bytes = content.0.into_body().collect().await?.to_bytes();
-                object.content = Some(String::from_utf8(bytes.into())?);
+                let bytes: Vec<u8> = content.0.into_body().collect().await?.to_bytes().into();
+                object.content = String::from_utf8(bytes.clone()).ok();
+                object.content_bytes = Some(bytes);
                 Ok(())
             }
             _ => Err(anyhow::anyhow!("there is no object name")),
         }
     }
 
+    /// Get object as UTF-8 text and store `GcsObject` instance
+    ///
+    /// # Arguments
+    ///
+    /// * `object` - to be stored object
+    pub async fn get_object_text(&self, object: &mut GcsObject) -> Result<String> {
+        self.get_object(object).await?;
+        object.content.clone().ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} is not a valid UTF-8 text",
+                object.name.as_ref().unwrap_or(&"".to_string())
+            )
+        })
+    }
+
     /// Get object stream. You need to store data by yourself.
     ///
     /// # Arguments
     ///
     /// * `name` - object name(full path)
     pub async fn get_object_stream(&self, name: String) -> Result<hyper::Response<BoxBody<Bytes, hyper::Error>>> {
-        let resp = self
+        self.get_object_media(&name, None).await
+    }
+
+    /// Get object media. If `generation` is set, the generation is read.
+    async fn get_object_media(
+        &self,
+        name: &str,
+        generation: Option<i64>,
+    ) -> Result<hyper::Response<BoxBody<Bytes, hyper::Error>>> {
+        let mut get = self
             .api
             .objects()
-            .get(&self.bucket, &urlencoding::encode(&name))
-            .param("alt", "media")
-            .doit()
-            .await;
+            .get(&self.bucket, &urlencoding::encode(name))
+            .param("alt", "media");
+        if let Some(generation) = generation {
+            get = get.generation(generation);
+        }
+        let resp = get.doit().await;
         match resp {
             Ok((body, _)) => Ok(body),
             Err(e) => match e {
@@ -390,13 +721,137 @@ impl Gcs {
                 | Error::Failure(_)
                 | Error::FieldClash(_)
                 | Error::JsonDecodeError(_, _) => {
-                    eprintln!("{}", e);
+                    tracing::error!("{}", e);
                     anyhow::bail!(e)
                 }
             },
         }
     }
 
+    /// Get an `AsyncRead` + `AsyncSeek` reader of the object.
+    ///
+    /// The object is read by range requests of 8 MiB on demand, so seeking does not download
+    /// the skipped data. This is useful for the formats like Parquet which read the footer first.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - object name(full path)
+    pub async fn object_reader(&self, name: &str) -> Result<GcsObjectReader> {
+        let object = self.get_object_metadata(name.to_string()).await?;
+        Ok(GcsObjectReader {
+            gcs: self.clone(),
+            name: name.to_string(),
+            generation: object.generation,
+            size: object.size.unwrap_or(0),
+            pos: 0,
+            buffer: Bytes::new(),
+            buffer_start: 0,
+            pending: None,
+        })
+    }
+
+    /// Download the bytes in the range `[start, end]` of the object.
+    ///
+    /// If GCS ignores the range (e.g. decompressive transcoding), the full content is returned
+    /// with 200. It is accepted only if it is the whole object of `size` bytes from the start.
+    async fn get_object_range(
+        &self,
+        name: &str,
+        generation: Option<i64>,
+        start: u64,
+        end: u64,
+        size: u64,
+    ) -> Result<Bytes> {
+        let mut uri = format!(
+            "{}/{}/o/{}?alt=media",
+            DOWNLOAD_URL,
+            self.bucket,
+            urlencoding::encode(name)
+        );
+        if let Some(generation) = generation {
+            uri.push_str(&format!("&generation={}", generation));
+        }
+        let headers = vec![("Range", format!("bytes={}-{}", start, end))];
+        let (status, _, body) = self
+            .raw_request(hyper::Method::GET, &uri, headers, vec![])
+            .await?;
+        match status {
+            hyper::StatusCode::PARTIAL_CONTENT => Ok(body),
+            hyper::StatusCode::OK if start == 0 && body.len() as u64 == size => Ok(body),
+            hyper::StatusCode::OK => anyhow::bail!(
+                "range request is ignored for {}. the object may be served with decompressive transcoding",
+                name
+            ),
+            _ => Err(Gcs::response_error(status, &body)),
+        }
+    }
+
+    /// Download object to a local file. The data is written while receiving.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - object name(full path)
+    /// * `path` - local file path to be written
+    /// * `verify` - compare crc32c and md5 of the downloaded data with the object metadata.
+    ///   Returns error on mismatch. Set false for partial downloads. The objects with
+    ///   Content-Encoding are not verified because GCS serves them decompressed.
+    pub async fn download_to_file<P: AsRef<Path>>(
+        &self,
+        name: String,
+        path: P,
+        verify: bool,
+    ) -> Result<()> {
+        let metadata = if verify {
+            Some(self.get_object_metadata(name.clone()).await?)
+        } else {
+            None
+        };
+        // read the same generation as the metadata, otherwise a concurrent overwrite mismatches
+        let generation = metadata.as_ref().and_then(|o| o.generation);
+        let expected = metadata.filter(|o| o.content_encoding.is_none());
+        let verify = expected.is_some();
+        let resp = self.get_object_media(&name, generation).await?;
+        let mut body = resp.into_body();
+        let mut file = tokio::fs::File::create(path).await?;
+        let mut md5 = Md5::new();
+        let mut crc = 0u32;
+        while let Some(frame) = body.frame().await {
+            if let Some(data) = frame?.data_ref() {
+                file.write_all(data).await?;
+                if verify {
+                    md5.update(data);
+                    crc = crc32c::crc32c_append(crc, data);
+                }
+            }
+        }
+        file.flush().await?;
+
+        if let Some(object) = expected {
+            if let Some(expected) = object.crc32c {
+                let actual = STANDARD.encode(crc.to_be_bytes());
+                anyhow::ensure!(
+                    actual == expected,
+                    "crc32c mismatch for {}: expected {}, got {}",
+                    name,
+                    expected,
+                    actual
+                );
+            }
+            // composite objects have no md5 hash
+            if let Some(expected) = object.md5_hash {
+                let actual = STANDARD.encode(md5.finalize());
+                anyhow::ensure!(
+                    actual == expected,
+                    "md5 mismatch for {}: expected {}, got {}",
+                    name,
+                    expected,
+                    actual
+                );
+            }
+        }
+        Ok(())
+    }
+
     /// Upload File to the bucket
     ///
     /// # Arguments
@@ -429,27 +884,70 @@ impl Gcs {
         self.insert_object(object, Cursor::new(str), p).await
     }
 
+    /// Compress data with gzip and upload it with `Content-Encoding: gzip`.
+    ///
+    /// # Arguments
+    ///
+    /// * `object` - GcsObject instance. The content type should be the one of the original data.
+    /// * `reader` - Data to be compressed.
+    /// * `p` - Request parameter. Content encoding is overwritten.
+    pub async fn insert_gzipped<R: Read>(
+        &self,
+        object: &GcsObject,
+        mut reader: R,
+        p: Option<GcsInsertParam>,
+    ) -> Result<GcsObject> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        std::io::copy(&mut reader, &mut encoder)?;
+        let compressed = encoder.finish()?;
+        let mut p = p.unwrap_or_default();
+        p.content_encoding("gzip");
+        self.insert_object(object, Cursor::new(compressed), Some(p))
+            .await
+    }
+
     /// Upload object stream to Bucket.
     ///
     /// # Arguments
     ///
     /// * `object` - GcsObject instance. The object name is used to store bucket.
     /// * `stream` - Data.
-    /// * `p` - Request parameter. For future use.
+    /// * `p` - Request parameter.
     pub async fn insert_object<T: Seek + Read + Send>(
         &self,
         object: &GcsObject,
-        stream: T,
-        _p: Option<GcsInsertParam>,
+        mut stream: T,
+        p: Option<GcsInsertParam>,
     ) -> Result<GcsObject> {
-        let req = object.to_object();
-        let insert = self.api.objects().insert(req, &self.bucket);
+        let mut req = object.to_object();
+        if let Some(p) = p {
+            req.content_encoding = p.content_encoding;
+            req.cache_control = p.cache_control;
+        }
         let mime_type = if let Some(m) = object.get_mime() {
             m
         } else {
             mime::APPLICATION_OCTET_STREAM
         };
-        let resp = insert.upload_resumable(stream, mime_type).await;
+        // the stream is rewound to upload again on retry
+        let start = stream.stream_position()?;
+        let mut attempt = 1;
+        let resp = loop {
+            stream.seek(SeekFrom::Start(start))?;
+            let insert = self.api.objects().insert(req.clone(), &self.bucket);
+            match insert
+                .upload_resumable(&mut stream, mime_type.clone())
+                .await
+            {
+                Err(e) if attempt < self.retry_policy.max_attempts && is_retryable(&e) => {
+                    let delay = self.retry_policy.delay(attempt);
+                    tracing::warn!(attempt, ?delay, error = %e, "retrying upload");
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                resp => break resp,
+            }
+        };
         match resp {
             Ok(content) => {
                 let obj = GcsObject::from_object(&self.bucket, &content.1);
@@ -472,13 +970,221 @@ impl Gcs {
                 | Error::Failure(_)
                 | Error::FieldClash(_)
                 | Error::JsonDecodeError(_, _) => {
-                    eprintln!("{}", e);
+                    tracing::error!("{}", e);
                     anyhow::bail!(e)
                 }
             },
         }
     }
 
+    /// Start resumable upload session.
+    ///
+    /// # Arguments
+    ///
+    /// * `object` - GcsObject instance. The object name is used to store bucket.
+    pub async fn start_resumable_upload(&self, object: &GcsObject) -> Result<ResumableSession> {
+        let mime_type = object.get_mime().unwrap_or(mime::APPLICATION_OCTET_STREAM);
+        let mut metadata = serde_json::Map::new();
+        if let Some(name) = &object.name {
+            metadata.insert("name".to_string(), serde_json::json!(name));
+        }
+        metadata.insert(
+            "contentType".to_string(),
+            serde_json::json!(mime_type.to_string()),
+        );
+        if let Some(m) = &object.metadata {
+            metadata.insert("metadata".to_string(), serde_json::json!(m));
+        }
+        if let Some(sc) = &object.storage_class {
+            metadata.insert("storageClass".to_string(), serde_json::json!(sc));
+        }
+        let uri = format!(
+            "{}/{}/o?uploadType=resumable",
+            UPLOAD_URL,
+            urlencoding::encode(&self.bucket)
+        );
+        let (status, headers, body) = self
+            .raw_request(
+                hyper::Method::POST,
+                &uri,
+                vec![
+                    (
+                        "content-type",
+                        "application/json; charset=UTF-8".to_string(),
+                    ),
+                    ("x-upload-content-type", mime_type.to_string()),
+                ],
+                serde_json::to_vec(&metadata)?,
+            )
+            .await?;
+        if !status.is_success() {
+            anyhow::bail!(Self::response_error(status, &body))
+        }
+        let uri = headers
+            .get(hyper::header::LOCATION)
+            .and_then(|l| l.to_str().ok())
+            .ok_or_else(|| anyhow::anyhow!("there is no session URI in the response"))?;
+        Ok(ResumableSession {
+            uri: uri.to_string(),
+            bucket: self.bucket.clone(),
+            name: object.name.clone(),
+        })
+    }
+
+    /// Get the offset to resume the upload. `None` means the upload is already completed.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - resumable upload session
+    pub async fn resumable_offset(&self, session: &ResumableSession) -> Result<Option<u64>> {
+        let (status, headers, body) = self
+            .raw_request(
+                hyper::Method::PUT,
+                &session.uri,
+                vec![("content-range", "bytes */*".to_string())],
+                vec![],
+            )
+            .await?;
+        match status.as_u16() {
+            200 | 201 => Ok(None),
+            308 => Ok(Some(Self::uploaded_offset(&headers))),
+            _ => anyhow::bail!(Self::response_error(status, &body)),
+        }
+    }
+
+    /// Upload the data from the offset in chunks.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - resumable upload session
+    /// * `stream` - Data. The whole data, not the remaining part.
+    /// * `offset` - The offset to resume. Use `resumable_offset` to get it from the server.
+    pub async fn resume_upload<T: Read + Seek>(
+        &self,
+        session: &ResumableSession,
+        mut stream: T,
+        offset: u64,
+    ) -> Result<GcsObject> {
+        let total = stream.seek(SeekFrom::End(0))?;
+        anyhow::ensure!(
+            offset <= total,
+            "offset {} exceeds the size {}",
+            offset,
+            total
+        );
+        let mut position = offset;
+        loop {
+            stream.seek(SeekFrom::Start(position))?;
+            let mut chunk = vec![0u8; RESUMABLE_CHUNK_SIZE.min(total - position) as usize];
+            stream.read_exact(&mut chunk)?;
+            let range = if chunk.is_empty() {
+                format!("bytes */{}", total)
+            } else {
+                format!(
+                    "bytes {}-{}/{}",
+                    position,
+                    position + chunk.len() as u64 - 1,
+                    total
+                )
+            };
+            let last = chunk.is_empty();
+            let (status, headers, body) = self
+                .raw_request(
+                    hyper::Method::PUT,
+                    &session.uri,
+                    vec![("content-range", range)],
+                    chunk,
+                )
+                .await?;
+            match status.as_u16() {
+                200 | 201 => {
+                    let object: Object = serde_json::from_slice(&body)?;
+                    return Ok(GcsObject::from_object(&session.bucket, &object));
+                }
+                308 if !last => position = Self::uploaded_offset(&headers),
+                _ => anyhow::bail!(Self::response_error(status, &body)),
+            }
+        }
+    }
+
+    /// The next offset from `Range: bytes=0-N` header of the resumable upload response
+    fn uploaded_offset(headers: &hyper::HeaderMap) -> u64 {
+        headers
+            .get(hyper::header::RANGE)
+            .and_then(|r| r.to_str().ok())
+            .and_then(|r| r.rsplit_once('-'))
+            .and_then(|(_, end)| end.parse::<u64>().ok())
+            .map(|end| end + 1)
+            .unwrap_or(0)
+    }
+
+    fn response_error(status: hyper::StatusCode, body: &[u8]) -> anyhow::Error {
+        match serde_json::from_slice::<BadRequest>(body) {
+            Ok(br) => anyhow::anyhow!(br.request_error()),
+            Err(_) => anyhow::anyhow!("{}: {}", status, String::from_utf8_lossy(body)),
+        }
+    }
+
+    async fn raw_request(
+        &self,
+        method: hyper::Method,
+        uri: &str,
+        headers: Vec<(&str, String)>,
+        body: Vec<u8>,
+    ) -> Result<(hyper::StatusCode, hyper::HeaderMap, Bytes)> {
+        tracing::debug!(%method, uri, "request");
+        let token = self.auth.token(&[STORAGE_SCOPE]).await?;
+        let mut builder = hyper::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(
+                hyper::header::AUTHORIZATION,
+                format!("Bearer {}", token.token().unwrap_or_default()),
+            )
+            .header(hyper::header::CONTENT_LENGTH, body.len());
+        for (name, value) in headers {
+            builder = builder.header(name, value);
+        }
+        let req = builder.body(
+            Full::new(Bytes::from(body))
+                .map_err(|never| match never {})
+                .boxed(),
+        )?;
+        let resp = self.client.request(req).await?;
+        let status = resp.status();
+        tracing::debug!(%status, "response");
+        let headers = resp.headers().clone();
+        let body = resp.into_body().collect().await?.to_bytes();
+        Ok((status, headers, body))
+    }
+
+    /// Upload files to the bucket concurrently. The order of the result is not guaranteed.
+    ///
+    /// A failure does not stop the other uploads. Each input object is returned with its result.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - pairs of GcsObject instance and the file path to be uploaded.
+    /// * `concurrency` - The number of uploads running at the same time.
+    pub async fn insert_many(
+        &self,
+        items: Vec<(GcsObject, PathBuf)>,
+        concurrency: usize,
+    ) -> Result<Vec<(GcsObject, Result<GcsObject>)>> {
+        anyhow::ensure!(0 < concurrency, "concurrency must be greater than 0");
+        Ok(stream::iter(items)
+            .map(|(object, path)| async move {
+                let result = match fs::File::open(&path) {
+                    Ok(file) => self.insert_object(&object, file, None).await,
+                    Err(e) => Err(e.into()),
+                };
+                (object, result)
+            })
+            .buffer_unordered(concurrency)
+            .collect()
+            .await)
+    }
+
     /// Delete object in Bucket.
     ///
     /// # Arguments
@@ -490,13 +1196,282 @@ impl Gcs {
             .objects()
             .delete(&self.bucket, &urlencoding::encode(name));
         let resp = delete.doit().await;
-        println!("{:?}", resp);
+        tracing::debug!("{:?}", resp);
         match resp {
             Ok(_content) => Ok(()),
             Err(e) => {
-                eprintln!("{}", e);
+                tracing::error!("{}", e);
                 anyhow::bail!(e)
             }
         }
     }
+    /// Delete objects in Bucket concurrently. The order of the result is not guaranteed.
+    ///
+    /// A failure does not stop the other deletions. Each name is returned with its result.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - The names of object.
+    /// * `concurrency` - The number of deletions running at the same time.
+    pub async fn delete_many(
+        &self,
+        names: Vec<String>,
+        concurrency: usize,
+    ) -> Result<Vec<(String, Result<()>)>> {
+        anyhow::ensure!(0 < concurrency, "concurrency must be greater than 0");
+        Ok(stream::iter(names)
+            .map(|name| async move {
+                let result = self.delete_object(&name).await;
+                (name, result)
+            })
+            .buffer_unordered(concurrency)
+            .collect()
+            .await)
+    }
+}
+
+/// Fetch one page of buckets/list.
+///
+/// `Gcs::list_buckets` follows the page tokens over this, so the paging is testable without
+/// calling the API.
+#[cfg_attr(test, automock)]
+trait BucketsPager {
+    async fn fetch_buckets_page(&self, project: &str, p: &GcsListParam) -> Result<Buckets>;
+}
+
+impl BucketsPager for Gcs {
+    async fn fetch_buckets_page(&self, project: &str, p: &GcsListParam) -> Result<Buckets> {
+        let res = self
+            .retry_policy
+            .retry(
+                || {
+                    let mut gcs = self.api.buckets().list(project);
+                    if let Some(mr) = p.max_results {
+                        gcs = gcs.max_results(mr);
+                    }
+                    if let Some(pf) = &p.prefix {
+                        gcs = gcs.prefix(&pf);
+                    }
+                    if let Some(token) = &p.next_token {
+                        gcs = gcs.page_token(&token);
+                    }
+                    gcs.doit()
+                },
+                is_retryable,
+            )
+            .await;
+        match res {
+            Ok(result) => Ok(result.1),
+            Err(e) => match e {
+                Error::BadRequest(badrequest) => {
+                    if let Ok(br) = serde_json::from_value::<BadRequest>(badrequest.clone()) {
+                        anyhow::bail!(br.request_error())
+                    } else {
+                        anyhow::bail!(badrequest)
+                    }
+                }
+                Error::HttpError(_)
+                | Error::Io(_)
+                | Error::MissingAPIKey
+                | Error::MissingToken(_)
+                | Error::Cancelled
+                | Error::UploadSizeLimitExceeded(_, _)
+                | Error::Failure(_)
+                | Error::FieldClash(_)
+                | Error::JsonDecodeError(_, _) => {
+                    tracing::error!("{}", e);
+                    anyhow::bail!(e)
+                }
+            },
+        }
+    }
+}
+
+/// Fetch all pages by following the next page token.
+async fn collect_buckets(
+    pager: &impl BucketsPager,
+    project: &str,
+    p: &GcsListParam,
+) -> Result<Vec<GcsBucket>> {
+    let mut param = p.clone();
+    let mut buckets: Vec<GcsBucket> = Vec::new();
+    loop {
+        let page = pager.fetch_buckets_page(project, &param).await?;
+        if let Some(items) = page.items {
+            buckets.extend(items.iter().map(GcsBucket::from_bucket));
+        }
+        match page.next_page_token {
+            Some(token) => {
+                param.next_token(&token);
+            }
+            None => break,
+        }
+    }
+    Ok(buckets)
+}
+
+type RangeFuture = Pin<Box<dyn Future<Output = Result<Bytes>> + Send>>;
+
+/// Reader of the object created by `Gcs::object_reader`
+pub struct GcsObjectReader {
+    gcs: Gcs,
+    name: String,
+    /// Generation read by the metadata request. All ranges are read from this generation.
+    generation: Option<i64>,
+    /// Object size
+    size: u64,
+    /// Current position
+    pos: u64,
+    /// Downloaded chunk
+    buffer: Bytes,
+    /// Position of the first byte of `buffer`
+    buffer_start: u64,
+    /// Range request in progress and its start position
+    pending: Option<(u64, RangeFuture)>,
+}
+
+impl GcsObjectReader {
+    /// Object size
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+impl AsyncRead for GcsObjectReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            if this.size <= this.pos || buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            let buffer_end = this.buffer_start + this.buffer.len() as u64;
+            if this.buffer_start <= this.pos && this.pos < buffer_end {
+                let offset = (this.pos - this.buffer_start) as usize;
+                let n = buf.remaining().min(this.buffer.len() - offset);
+                buf.put_slice(&this.buffer[offset..offset + n]);
+                this.pos += n as u64;
+                return Poll::Ready(Ok(()));
+            }
+            if this.pending.is_none() {
+                let gcs = this.gcs.clone();
+                let name = this.name.clone();
+                let generation = this.generation;
+                let size = this.size;
+                let start = this.pos;
+                let end = (start + READER_CHUNK_SIZE).min(size) - 1;
+                let fut: RangeFuture = Box::pin(async move {
+                    gcs.get_object_range(&name, generation, start, end, size)
+                        .await
+                });
+                this.pending = Some((start, fut));
+            }
+            if let Some((start, fut)) = this.pending.as_mut() {
+                let res = futures::ready!(fut.as_mut().poll(cx));
+                let start = *start;
+                this.pending = None;
+                let bytes =
+                    res.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
+                if bytes.is_empty() {
+                    return Poll::Ready(Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "empty range response",
+                    )));
+                }
+                this.buffer = bytes;
+                this.buffer_start = start;
+            }
+        }
+    }
+}
+
+impl AsyncSeek for GcsObjectReader {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+        let pos = match position {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.size.checked_add_signed(n),
+            SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+        };
+        match pos {
+            Some(pos) => {
+                // the range request in progress is useless for the new position
+                if let Some((start, _)) = &self.pending {
+                    if pos < *start || *start + READER_CHUNK_SIZE <= pos {
+                        self.pending = None;
+                    }
+                }
+                self.pos = pos;
+                Ok(())
+            }
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        Poll::Ready(Ok(self.pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockall::Sequence;
+
+    fn page(names: &[&str], next_page_token: Option<&str>) -> Buckets {
+        Buckets {
+            items: Some(
+                names
+                    .iter()
+                    .map(|n| Bucket {
+                        name: Some(n.to_string()),
+                        ..Default::default()
+                    })
+                    .collect(),
+            ),
+            next_page_token: next_page_token.map(|t| t.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn list_buckets_follows_page_tokens() {
+        let mut pager = MockBucketsPager::new();
+        let mut seq = Sequence::new();
+        pager
+            .expect_fetch_buckets_page()
+            .withf(|project, p| project == "my-project" && p.next_token.is_none())
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Ok(page(&["a", "b"], Some("token-1"))));
+        pager
+            .expect_fetch_buckets_page()
+            .withf(|_, p| p.next_token.as_deref() == Some("token-1"))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Ok(page(&["c"], None)));
+
+        let mut param = GcsListParam::new();
+        param.max_results(2);
+        let buckets = collect_buckets(&pager, "my-project", &param).await.unwrap();
+        let names: Vec<String> = buckets.into_iter().map(|b| b.name).collect();
+        assert_eq!(names, vec!["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn list_buckets_stops_on_error() {
+        let mut pager = MockBucketsPager::new();
+        pager
+            .expect_fetch_buckets_page()
+            .times(1)
+            .returning(|_, _| Err(anyhow::anyhow!("boom")));
+
+        let result = collect_buckets(&pager, "my-project", &GcsListParam::new()).await;
+        assert!(result.is_err());
+    }
 }
@@ -1,9 +1,10 @@
 use crate::auth::{oauth2, hyper_util};
 use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
 use std::convert::Infallible;
 use http_body_util::{Empty, BodyExt};
 use oauth2::hyper_rustls;
-use oauth2::hyper::{body::{Buf, Bytes}, Method, Request};
+use oauth2::hyper::{body::{Buf, Bytes}, Method, Request, StatusCode};
 use hyper_util::client::legacy::Client;
 use serde::{Deserialize, Serialize};
 use std::fmt;
@@ -15,6 +16,10 @@ use mockall::automock;
 
 static METADATA_ROOT: &'static str = "http://metadata.google.internal/computeMetadata/v1/";
 
+/// Timeout of the metadata request. The metadata server responds quickly on GCP, and off GCP
+/// the request may hang instead of failing.
+const METADATA_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
 static REQUEST_TYPE_ACCESS_TOKEN: &'static str = "auth-request-type/at";
 
 // Auth request type
@@ -85,6 +90,36 @@ pub struct ServiceAccountInfo {
     pub scopes: Vec<String>,
 }
 
+#[derive(Debug, Deserialize)]
+struct AccessToken {
+    access_token: String,
+    expires_in: i64,
+}
+
+/// GET the metadata path and return the body.
+///
+/// This fails if it is not running on GCP because the metadata server can not be resolved.
+async fn get_metadata(path: &str) -> Result<String> {
+    let url = format!("{}{}", METADATA_ROOT, path);
+    let client = new_client();
+    let req = Request::builder()
+        .method(Method::GET)
+        .uri(url)
+        .header("Metadata-Flavor", "Google")
+        .body(Empty::<Bytes>::new().boxed())?;
+    let resp = tokio::time::timeout(METADATA_TIMEOUT, client.request(req))
+        .await
+        .map_err(|_| anyhow::anyhow!("metadata server is not available: timed out"))?
+        .map_err(|e| anyhow::anyhow!("metadata server is not available: {}", e))?;
+    let status = resp.status();
+    let bytes = resp.into_body().boxed().collect().await?.to_bytes();
+    let body = String::from_utf8(bytes.into())?;
+    if status != StatusCode::OK {
+        anyhow::bail!("metadata request failure. status: {}, body: {}", status, body)
+    }
+    Ok(body)
+}
+
 #[cfg_attr(test, automock)]
 impl MetadataApi {
     pub fn new() -> Self {
@@ -103,7 +138,9 @@ impl MetadataApi {
             .header("Metadata-Flavor", "Google")
             .body(Empty::<Bytes>::new().boxed())?;
         // println!("req: {:?}", req);
-        let resp = client.request(req).await;
+        let resp = tokio::time::timeout(METADATA_TIMEOUT, client.request(req))
+            .await
+            .map_err(|_| anyhow::anyhow!("metadata server is not available: timed out"))?;
         // println!("resp: {:?}", resp);
         match resp {
             Ok(resp) => {
@@ -114,7 +151,7 @@ impl MetadataApi {
                 Ok(info)
             }
             Err(e) => {
-                println!("err: {:?}", e);
+                tracing::error!("{:?}", e);
                 Err(e.into())
             }
         }
@@ -133,16 +170,16 @@ impl MetadataApi {
             //.header("x-goog-api-client", format!("{} {} {}", , RequestType::IdToken, CredentialType::ServiceAccountMds))
             .body(Empty::<Bytes>::new().boxed())?;
         // println!("req: {:?}", req);
-        let resp = client.request(req).await;
+        let resp = tokio::time::timeout(METADATA_TIMEOUT, client.request(req)).await;
         //  println!("resp: {:?}", resp);
         match resp {
-            Ok(resp) => {
+            Ok(Ok(resp)) => {
                 let bytes = resp.into_body().boxed().collect().await?.to_bytes();
                 let body = String::from_utf8(bytes.into())?;
-                println!("body: {:?}", body);
                 Ok(body)
             }
-            Err(_) => {
+            // not on GCP or timed out
+            Ok(Err(_)) | Err(_) => {
                 let output = Command::new("gcloud")
                     .arg("auth")
                     .arg("print-identity-token")
@@ -154,4 +191,31 @@ impl MetadataApi {
             }
         }
     }
+
+    /// Get the project ID which the instance belongs to.
+    pub async fn project_id(&self) -> Result<String> {
+        let body = get_metadata("project/project-id").await?;
+        Ok(body.trim().to_string())
+    }
+
+    /// Get an access token of the service account.
+    ///
+    /// Returns the token and its expiry.
+    ///
+    /// # Arguments
+    ///
+    /// * `service_account` - service account email or `default`
+    pub async fn access_token(&self, service_account: &str) -> Result<(String, DateTime<Utc>)> {
+        let requested_at = Utc::now();
+        let body = get_metadata(&format!(
+            "instance/service-accounts/{}/token",
+            service_account
+        ))
+        .await?;
+        let token = serde_json::from_str::<AccessToken>(&body)?;
+        Ok((
+            token.access_token,
+            requested_at + Duration::seconds(token.expires_in),
+        ))
+    }
 }
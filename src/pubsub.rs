@@ -1,24 +1,31 @@
 use crate::auth;
+use crate::common::error::{is_retryable_status, BadRequest};
+use crate::common::RetryPolicy;
+use chrono::{DateTime, Utc};
 use google_pubsub1 as pubsub;
 use pubsub::{
-    api::{AcknowledgeRequest, PublishRequest, PubsubMessage, PullRequest},
+    api::{
+        AcknowledgeRequest, PublishRequest, PubsubMessage, PullRequest, ReceivedMessage,
+        Subscription,
+    },
     Error, Pubsub, Result as GcpResult,
 };
 
 use anyhow;
 use anyhow::Result;
-use async_recursion::async_recursion;
-use std::thread;
-use std::time::Duration;
+use std::collections::HashMap;
+use std::future::Future;
 
 pub struct PubSub {
     api: Pubsub<auth::HttpsConnector>,
+    retry_policy: RetryPolicy,
 }
 
 #[derive(Clone, Debug)]
 pub struct PublishParam {
     project: String,
     topic: String,
+    attributes: HashMap<String, String>,
 }
 
 impl PublishParam {
@@ -26,14 +33,54 @@ impl PublishParam {
         PublishParam {
             project: project.to_string(),
             topic: topic.to_string(),
+            attributes: HashMap::new(),
         }
     }
 
+    /// Set an attribute of the published messages.
+    ///
+    /// Subscriptions can filter messages by the attribute with `attributes.<key>`.
+    /// e.g. publish with `type` = `order` to deliver to a subscription created with the
+    /// filter `attributes.type = "order"`.
+    pub fn attribute(&mut self, key: &str, value: &str) -> &mut Self {
+        self.attributes.insert(key.to_string(), value.to_string());
+        self
+    }
+
     fn topic_name(&self) -> String {
         format!("projects/{}/topics/{}", self.project, self.topic)
     }
 }
 
+/// Pulled message
+#[derive(Clone, Debug)]
+pub struct PubSubMessage {
+    /// ID to acknowledge the message
+    pub ack_id: String,
+    pub message_id: Option<String>,
+    pub data: Vec<u8>,
+    pub attributes: Option<HashMap<String, String>>,
+    pub publish_time: Option<DateTime<Utc>>,
+    /// Approximate number of delivery attempts. This is set only if a dead letter policy is
+    /// configured.
+    pub delivery_attempt: Option<i32>,
+}
+
+impl PubSubMessage {
+    fn from_received_message(received: &ReceivedMessage) -> Option<Self> {
+        let ack_id = received.ack_id.as_ref()?.clone();
+        let message = received.message.as_ref();
+        Some(PubSubMessage {
+            ack_id,
+            message_id: message.and_then(|m| m.message_id.clone()),
+            data: message.and_then(|m| m.data.clone()).unwrap_or_default(),
+            attributes: message.and_then(|m| m.attributes.clone()),
+            publish_time: message.and_then(|m| m.publish_time),
+            delivery_attempt: received.delivery_attempt,
+        })
+    }
+}
+
 pub struct SubscriptionParam {
     project: String,
     subscription: String,
@@ -64,9 +111,21 @@ impl SubscriptionParam {
 
 impl PubSub {
     pub fn new(auth: &auth::GcpAuth) -> Result<PubSub> {
-        let client = auth::new_client();
+        PubSub::with_client(auth, auth::new_client())
+    }
+
+    /// Create with the HTTP client built by `auth::new_client_with`
+    pub fn with_client(auth: &auth::GcpAuth, client: auth::HttpClient) -> Result<PubSub> {
         let hub = Pubsub::new(client, auth.authenticator());
-        Ok(PubSub { api: hub })
+        Ok(PubSub {
+            api: hub,
+            retry_policy: RetryPolicy::default(),
+        })
+    }
+
+    pub fn retry_policy(&mut self, policy: RetryPolicy) -> &mut Self {
+        self.retry_policy = policy;
+        self
     }
 
     fn handle_error<T>(result: GcpResult<T>) -> Result<T> {
@@ -84,7 +143,7 @@ impl PubSub {
                 | Error::BadRequest(_)
                 | Error::FieldClash(_)
                 | Error::JsonDecodeError(_, _) => {
-                    println!("{}", e);
+                    tracing::error!("{}", e);
                     Err(anyhow::anyhow!("{}", e))
                 }
             },
@@ -92,48 +151,21 @@ impl PubSub {
         }
     }
 
-    #[async_recursion]
-    async fn call_publish(
-        &self,
-        req: PublishRequest,
-        topic: &str,
-        retry_count: u64,
-    ) -> Result<Vec<String>> {
+    /// Call publish API with retry. 429 and 503 are retried according to the retry policy.
+    async fn call_publish(&self, req: PublishRequest, topic: &str) -> Result<Vec<String>> {
         let res = self
-            .api
-            .projects()
-            .topics_publish(req.clone(), topic)
-            .doit()
+            .retry_policy
+            .retry(
+                || {
+                    self.api
+                        .projects()
+                        .topics_publish(req.clone(), topic)
+                        .doit()
+                },
+                |e| PubSub::status_code(e).map_or(false, is_retryable_status),
+            )
             .await;
-        println!("{:?}", res);
-        match res {
-            Err(e) => match e {
-                Error::BadRequest(_) => {
-                    if 5 < retry_count {
-                        eprintln!("{}", e);
-                        Err(anyhow::anyhow!("{}", e))
-                    } else {
-                        let interval = 100 * retry_count.pow(2);
-                        // eprintln!("{}, {}", e, interval);
-                        thread::sleep(Duration::from_millis(interval));
-                        self.call_publish(req, topic, retry_count + 1).await
-                    }
-                }
-                Error::HttpError(_)
-                | Error::Io(_)
-                | Error::MissingAPIKey
-                | Error::MissingToken(_)
-                | Error::Cancelled
-                | Error::UploadSizeLimitExceeded(_, _)
-                | Error::Failure(_)
-                | Error::FieldClash(_)
-                | Error::JsonDecodeError(_, _) => {
-                    eprintln!("{}", e);
-                    Err(anyhow::anyhow!("{}", e))
-                }
-            },
-            Ok(resp) => Ok(resp.1.message_ids.unwrap_or_default()),
-        }
+        PubSub::handle_error(res).map(|resp| resp.1.message_ids.unwrap_or_default())
     }
 
     /// publish message to topic
@@ -141,118 +173,176 @@ impl PubSub {
     pub async fn publish(&self, p: &PublishParam, data: Vec<u8>) -> Result<Vec<String>> {
         let mut message = PubsubMessage::default();
         message.data = Some(data);
+        if !p.attributes.is_empty() {
+            message.attributes = Some(p.attributes.clone());
+        }
         let mut req = PublishRequest::default();
         req.messages = Some(vec![message]);
-        self.call_publish(req, &p.topic_name(), 0).await
+        self.call_publish(req, &p.topic_name()).await
     }
 
-    async fn send_acknowledge(&self, subscription_name: &str, ack_ids: Vec<String>) -> bool {
-        let mut req = AcknowledgeRequest::default();
-        req.ack_ids = Some(ack_ids);
-
-        let ack_res = self
+    /// Create subscription to the topic
+    ///
+    /// # Arguments
+    ///
+    /// * `p` - subscription to be created
+    /// * `topic` - topic to subscribe
+    /// * `filter` - filter expression on the message attributes. e.g. `attributes.type = "order"`.
+    ///              Only the matched messages are delivered to the subscription.
+    pub async fn create_subscription(
+        &self,
+        p: &SubscriptionParam,
+        topic: &PublishParam,
+        filter: Option<&str>,
+    ) -> Result<String> {
+        let mut req = Subscription::default();
+        req.topic = Some(topic.topic_name());
+        req.filter = filter.map(|f| f.to_string());
+        let res = self
             .api
             .projects()
-            .subscriptions_acknowledge(req, subscription_name)
+            .subscriptions_create(req, &p.subscription_name())
             .doit()
             .await;
-        match ack_res {
-            Err(e) => match e {
-                Error::BadRequest(_)
-                | Error::HttpError(_)
-                | Error::Io(_)
-                | Error::MissingAPIKey
-                | Error::MissingToken(_)
-                | Error::Cancelled
-                | Error::UploadSizeLimitExceeded(_, _)
-                | Error::Failure(_)
-                | Error::FieldClash(_)
-                | Error::JsonDecodeError(_, _) => {
-                    eprintln!("{}", e);
-                    false
+        PubSub::handle_error(res).map(|resp| resp.1.name.unwrap_or_default())
+    }
+
+    /// Call pull API with retry. 429 and 503 are retried according to the retry policy.
+    async fn call_pull(&self, p: &SubscriptionParam) -> GcpResult<Vec<PubSubMessage>> {
+        let mut req = PullRequest::default();
+        req.max_messages = Some(p.max_messages);
+        let subscription = p.subscription_name();
+        let resp = self
+            .retry_policy
+            .retry(
+                || {
+                    self.api
+                        .projects()
+                        .subscriptions_pull(req.clone(), &subscription)
+                        .doit()
+                },
+                |e| PubSub::status_code(e).map_or(false, is_retryable_status),
+            )
+            .await?;
+        Ok(resp
+            .1
+            .received_messages
+            .unwrap_or_default()
+            .iter()
+            .filter_map(PubSubMessage::from_received_message)
+            .collect())
+    }
+
+    fn status_code(e: &Error) -> Option<u16> {
+        match e {
+            Error::BadRequest(v) => BadRequest::from_value(v).map(|br| br.status_code()),
+            Error::Failure(res) => Some(res.status().as_u16()),
+            _ => None,
+        }
+    }
+
+    /// Pull messages from subscription without acknowledging.
+    ///
+    /// Call `ack` with `ack_id` of the messages after processing them.
+    pub async fn pull_raw(&self, p: &SubscriptionParam) -> Result<Vec<PubSubMessage>> {
+        PubSub::handle_error(self.call_pull(p).await)
+    }
+
+    /// Pull and handle messages repeatedly until `shutdown` is resolved.
+    ///
+    /// The messages successfully handled are acknowledged. The others are redelivered after
+    /// the ack deadline. When no message is pulled, the next pull is delayed according to the
+    /// retry policy. DEADLINE_EXCEEDED(504) of the pull is treated as no message.
+    ///
+    /// # Arguments
+    ///
+    /// * `p` - subscription to pull
+    /// * `handler` - message handler
+    /// * `shutdown` - the loop stops when this is resolved. The messages being handled are
+    ///                acknowledged before stopping.
+    pub async fn run_consumer<F, Fut>(
+        &self,
+        p: &SubscriptionParam,
+        handler: F,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<()>
+    where
+        F: Fn(PubSubMessage) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        tokio::pin!(shutdown);
+        let mut empty_pulls: u32 = 0;
+        loop {
+            let pulled = tokio::select! {
+                _ = &mut shutdown => return Ok(()),
+                res = self.call_pull(p) => res,
+            };
+            let messages = match pulled {
+                Ok(messages) => messages,
+                Err(e) if PubSub::status_code(&e) == Some(504) => vec![],
+                Err(e) => return PubSub::handle_error(Err(e)),
+            };
+            if messages.is_empty() {
+                empty_pulls = empty_pulls.saturating_add(1);
+                tokio::select! {
+                    _ = &mut shutdown => return Ok(()),
+                    _ = tokio::time::sleep(self.retry_policy.delay(empty_pulls)) => continue,
                 }
-            },
-            Ok(_) => true,
+            }
+            empty_pulls = 0;
+
+            let mut ack_ids = vec![];
+            for message in messages {
+                let ack_id = message.ack_id.clone();
+                match handler(message).await {
+                    Ok(_) => ack_ids.push(ack_id),
+                    Err(e) => tracing::warn!("handling message failure. {}", e),
+                }
+            }
+            if !ack_ids.is_empty() {
+                self.ack(p, ack_ids).await?;
+            }
         }
     }
 
+    /// Acknowledge messages
+    ///
+    pub async fn ack(&self, p: &SubscriptionParam, ack_ids: Vec<String>) -> Result<()> {
+        let mut req = AcknowledgeRequest::default();
+        req.ack_ids = Some(ack_ids);
+        let res = self
+            .api
+            .projects()
+            .subscriptions_acknowledge(req, &p.subscription_name())
+            .doit()
+            .await;
+        PubSub::handle_error(res).map(|_| ())
+    }
+
     /// Pull message from subscription
     ///
+    /// The messages successfully handled are acknowledged. Empty messages are also acknowledged
+    /// but not handled.
     pub async fn pull_subscription<T>(
         &self,
         p: SubscriptionParam,
         message_handler: fn(&Vec<u8>) -> Result<T>,
     ) -> Result<Vec<T>> {
-        let mut req = PullRequest::default();
-        req.max_messages = Some(p.max_messages);
-        let res = self
-            .api
-            .projects()
-            .subscriptions_pull(req, &p.subscription_name())
-            .doit()
-            .await;
-        println!("{:?}", res);
-        match res {
-            Err(e) => match e {
-                Error::BadRequest(_) => {
-                    eprintln!("{}", e);
-                    Err(anyhow::anyhow!("{}", e))
-                }
-                Error::HttpError(_)
-                | Error::Io(_)
-                | Error::MissingAPIKey
-                | Error::MissingToken(_)
-                | Error::Cancelled
-                | Error::UploadSizeLimitExceeded(_, _)
-                | Error::Failure(_)
-                | Error::FieldClash(_)
-                | Error::JsonDecodeError(_, _) => {
-                    eprintln!("{}", e);
-                    Err(anyhow::anyhow!("{}", e))
-                }
-            },
-            Ok(resp) => {
-                if let Some(receives) = resp.1.received_messages {
-                    let mut handled_results = vec![];
-                    for received in receives {
-                        let message = received
-                            .message
-                            .as_ref()
-                            .map(|pm| pm.data.as_ref())
-                            .flatten();
-                        if let Some(data) = message {
-                            let handled = message_handler(data);
-                            if handled.is_ok() {
-                                // send acknowledge
-                                if let Some(id) = received.ack_id.as_ref() {
-                                    if self
-                                        .send_acknowledge(&p.subscription_name(), vec![id.clone()])
-                                        .await
-                                    {
-                                        handled_results.push(handled.unwrap());
-                                    } else {
-                                        eprintln!(
-                                            "handling message failure. message: {:?}",
-                                            received
-                                        );
-                                    }
-                                } else {
-                                    eprintln!("no ack_id in message. message: {:?}", received);
-                                }
-                            } else {
-                            }
-                        } else if let Some(id) = received.ack_id {
-                            // message is empty but has ack_id. send acknowledge but ignore the
-                            // result
-                            self.send_acknowledge(&p.subscription_name(), vec![id])
-                                .await;
-                        }
-                    }
-                    Ok(handled_results)
-                } else {
-                    Ok(vec![])
+        let messages = self.pull_raw(&p).await?;
+        let mut handled_results = vec![];
+        for message in messages {
+            if message.data.is_empty() {
+                // message is empty but has ack_id. send acknowledge but ignore the result
+                let _ = self.ack(&p, vec![message.ack_id]).await;
+                continue;
+            }
+            if let Ok(handled) = message_handler(&message.data) {
+                match self.ack(&p, vec![message.ack_id.clone()]).await {
+                    Ok(_) => handled_results.push(handled),
+                    Err(_) => tracing::warn!("handling message failure. message: {:?}", message),
                 }
             }
         }
+        Ok(handled_results)
     }
 }
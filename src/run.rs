@@ -5,20 +5,36 @@ use crate::auth;
 
 use anyhow;
 use anyhow::Result;
+use async_recursion::async_recursion;
 use cloud_run::{
     api::{
-        GoogleCloudRunV2CloudSqlInstance, GoogleCloudRunV2Container, GoogleCloudRunV2EnvVar,
-        GoogleCloudRunV2Execution, GoogleCloudRunV2Job, GoogleCloudRunV2ListExecutionsResponse,
-        GoogleCloudRunV2ListJobsResponse, GoogleCloudRunV2ListServicesResponse,
-        GoogleCloudRunV2ResourceRequirements, GoogleCloudRunV2Service, GoogleCloudRunV2Volume,
-        GoogleCloudRunV2VolumeMount, GoogleLongrunningOperation, GoogleCloudRunV2RunJobRequest,
+        GoogleCloudRunV2CancelExecutionRequest, GoogleCloudRunV2CloudSqlInstance,
+        GoogleCloudRunV2Container, GoogleCloudRunV2EnvVar, GoogleCloudRunV2EnvVarSource,
+        GoogleCloudRunV2Execution, GoogleCloudRunV2HTTPGetAction, GoogleCloudRunV2Job,
+        GoogleCloudRunV2ListExecutionsResponse, GoogleCloudRunV2ListJobsResponse,
+        GoogleCloudRunV2ListServicesResponse, GoogleCloudRunV2Probe,
+        GoogleCloudRunV2ResourceRequirements, GoogleCloudRunV2RunJobRequest,
+        GoogleCloudRunV2SecretKeySelector, GoogleCloudRunV2Service, GoogleCloudRunV2Volume,
+        GoogleCloudRunV2VolumeMount, GoogleLongrunningOperation,
     },
     CloudRun as GcpCloudRun, Error, Result as GcpResult,
 };
 use google_run2 as cloud_run;
 use hyper::{Body, Response};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Interval to poll the execution status
+const EXECUTION_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Format of the cpu quantity
+static CPU_RE: OnceLock<Regex> = OnceLock::new();
+
+/// Format of the memory quantity
+static MEMORY_RE: OnceLock<Regex> = OnceLock::new();
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VolumeMount {
@@ -70,18 +86,225 @@ impl Volume {
     }
 }
 
+/// Value of an environment variable
+///
+/// Literal value is represented as a string and secret reference as
+/// `{"secret": "<secret name>", "version": "<version>"}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EnvValue {
+    Value(String),
+    /// Secret Manager secret. `version` is `latest` if omitted.
+    Secret {
+        secret: String,
+        #[serde(default = "EnvValue::latest_version")]
+        version: String,
+    },
+}
+
+impl EnvValue {
+    fn latest_version() -> String {
+        String::from("latest")
+    }
+
+    fn to_env_var(&self, name: &str) -> GoogleCloudRunV2EnvVar {
+        match self {
+            EnvValue::Value(v) => GoogleCloudRunV2EnvVar {
+                name: Some(name.to_string()),
+                value: Some(v.clone()),
+                value_source: None,
+            },
+            EnvValue::Secret { secret, version } => GoogleCloudRunV2EnvVar {
+                name: Some(name.to_string()),
+                value: None,
+                value_source: Some(GoogleCloudRunV2EnvVarSource {
+                    secret_key_ref: Some(GoogleCloudRunV2SecretKeySelector {
+                        secret: Some(secret.clone()),
+                        version: Some(version.clone()),
+                    }),
+                }),
+            },
+        }
+    }
+
+    fn from_env_var(env: &GoogleCloudRunV2EnvVar) -> Self {
+        match env
+            .value_source
+            .as_ref()
+            .and_then(|vs| vs.secret_key_ref.as_ref())
+        {
+            Some(secret_ref) => EnvValue::Secret {
+                secret: secret_ref.secret.clone().unwrap_or_default(),
+                version: secret_ref
+                    .version
+                    .clone()
+                    .unwrap_or_else(EnvValue::latest_version),
+            },
+            None => EnvValue::Value(env.value.clone().unwrap_or_default()),
+        }
+    }
+}
+
+/// Resource limits of a container
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Resources {
+    /// CPU quantity. e.g. `1`, `0.5` or `1000m`
+    pub cpu: Option<String>,
+    /// Memory quantity. e.g. `512Mi` or `2Gi`
+    pub memory: Option<String>,
+    /// Other limits. Only the keys in `Resources::OTHER_KEYS` like `nvidia.com/gpu` are accepted.
+    #[serde(flatten)]
+    pub others: HashMap<String, String>,
+}
+
+impl Resources {
+    const CPU: &'static str = "cpu";
+    const MEMORY: &'static str = "memory";
+    /// Accepted keys other than cpu and memory
+    pub const OTHER_KEYS: &'static [&'static str] = &["nvidia.com/gpu"];
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cpu(&mut self, v: &str) -> &mut Self {
+        self.cpu = Some(v.to_string());
+        self
+    }
+
+    pub fn memory(&mut self, v: &str) -> &mut Self {
+        self.memory = Some(v.to_string());
+        self
+    }
+
+    /// Build from the map of resource limits. Malformed cpu and memory quantities and unknown
+    /// keys are errors.
+    pub fn from_map(map: &HashMap<String, String>) -> Result<Self> {
+        let mut resources = Resources::new();
+        for (k, v) in map {
+            match k.as_str() {
+                Resources::CPU => {
+                    resources.cpu(v);
+                }
+                Resources::MEMORY => {
+                    resources.memory(v);
+                }
+                _ => {
+                    resources.others.insert(k.clone(), v.clone());
+                }
+            }
+        }
+        resources.validate()?;
+        Ok(resources)
+    }
+
+    /// Validate the quantity format of cpu and memory, and the keys of the other limits.
+    pub fn validate(&self) -> Result<()> {
+        let cpu_re = CPU_RE.get_or_init(|| Regex::new(r"^(\d+(\.\d+)?|\d+m)$").unwrap());
+        let memory_re =
+            MEMORY_RE.get_or_init(|| Regex::new(r"^\d+(\.\d+)?(k|M|G|T|Ki|Mi|Gi|Ti)?$").unwrap());
+        if let Some(key) = self
+            .others
+            .keys()
+            .find(|k| !Resources::OTHER_KEYS.contains(&k.as_str()))
+        {
+            return Err(anyhow::anyhow!(
+                "unknown resource limit: {}. accepted keys are {}, {} and {}",
+                key,
+                Resources::CPU,
+                Resources::MEMORY,
+                Resources::OTHER_KEYS.join(", ")
+            ));
+        }
+        if let Some(cpu) = self.cpu.as_ref().filter(|v| !cpu_re.is_match(v)) {
+            return Err(anyhow::anyhow!(
+                "invalid cpu: {}. e.g. 1, 0.5 or 1000m",
+                cpu
+            ));
+        }
+        if let Some(memory) = self.memory.as_ref().filter(|v| !memory_re.is_match(v)) {
+            return Err(anyhow::anyhow!(
+                "invalid memory: {}. e.g. 512Mi, 0.5Gi or 2Gi",
+                memory
+            ));
+        }
+        Ok(())
+    }
+
+    fn to_limits(&self) -> HashMap<String, String> {
+        let mut limits = HashMap::new();
+        if let Some(cpu) = self.cpu.as_ref() {
+            limits.insert(Resources::CPU.to_string(), cpu.clone());
+        }
+        if let Some(memory) = self.memory.as_ref() {
+            limits.insert(Resources::MEMORY.to_string(), memory.clone());
+        }
+        limits.extend(self.others.clone());
+        limits
+    }
+}
+
+/// HTTP health check probe of a container
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Probe {
+    /// HTTP path to access. e.g. `/healthz`
+    pub path: String,
+    /// Port to access. The container port is used if omitted.
+    pub port: Option<i32>,
+    /// Seconds to wait after the container has started before the first probe
+    pub initial_delay_seconds: Option<i32>,
+    /// How often to perform the probe in seconds
+    pub period_seconds: Option<i32>,
+}
+
+impl Probe {
+    pub fn new(path: &str) -> Self {
+        Probe {
+            path: path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn to_probe(&self) -> GoogleCloudRunV2Probe {
+        let mut http_get = GoogleCloudRunV2HTTPGetAction::default();
+        http_get.path = Some(self.path.clone());
+        http_get.port = self.port;
+        let mut probe = GoogleCloudRunV2Probe::default();
+        probe.http_get = Some(http_get);
+        probe.initial_delay_seconds = self.initial_delay_seconds;
+        probe.period_seconds = self.period_seconds;
+        probe
+    }
+
+    /// Only HTTP probe is supported. Others(TCP and gRPC) are ignored.
+    fn from_probe(probe: &GoogleCloudRunV2Probe) -> Option<Self> {
+        probe.http_get.as_ref().map(|http_get| Probe {
+            path: http_get.path.clone().unwrap_or_default(),
+            port: http_get.port,
+            initial_delay_seconds: probe.initial_delay_seconds,
+            period_seconds: probe.period_seconds,
+        })
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Container {
     image: String,
     args: Vec<String>,
     command: Vec<String>,
-    env: HashMap<String, String>,
+    env: HashMap<String, EnvValue>,
     resources: HashMap<String, String>,
     volume_mounts: Vec<VolumeMount>,
+    /// Probe to check the container has started. Use this for the slow starting application.
+    #[serde(default)]
+    startup_probe: Option<Probe>,
+    /// Probe to check the container is alive. The container is restarted if it fails.
+    #[serde(default)]
+    liveness_probe: Option<Probe>,
 }
 
 impl Container {
-    fn to_container(&self) -> GoogleCloudRunV2Container {
+    fn to_container(&self) -> Result<GoogleCloudRunV2Container> {
         let mut container = GoogleCloudRunV2Container::default();
         container.image = Some(self.image.clone());
         container.args = Some(self.args.clone());
@@ -94,21 +317,14 @@ impl Container {
                     .collect(),
             )
         }
-        container.env = Some(
-            self.env
-                .iter()
-                .map(|(k, v)| GoogleCloudRunV2EnvVar {
-                    name: Some(k.clone()),
-                    value: Some(v.clone()),
-                    value_source: None,
-                })
-                .collect(),
-        );
+        container.env = Some(self.env.iter().map(|(k, v)| v.to_env_var(k)).collect());
         let mut resources = GoogleCloudRunV2ResourceRequirements::default();
-        resources.limits = Some(self.resources.clone());
+        resources.limits = Some(Resources::from_map(&self.resources)?.to_limits());
         resources.startup_cpu_boost = Some(true);
         container.resources = Some(resources);
-        container
+        container.startup_probe = self.startup_probe.as_ref().map(|p| p.to_probe());
+        container.liveness_probe = self.liveness_probe.as_ref().map(|p| p.to_probe());
+        Ok(container)
     }
     fn from_container(container: &GoogleCloudRunV2Container) -> Self {
         let image = container.image.as_ref().unwrap().to_string();
@@ -122,25 +338,15 @@ impl Container {
         } else {
             vec![]
         };
-        let default_str = String::from("");
         let env = if let Some(envs) = container.env.as_ref() {
-            let env_map = HashMap::from(
-                envs.iter()
-                    .map(|env| {
-                        (
-                            env.name
-                                .as_ref()
-                                .unwrap_or_else(|| &default_str)
-                                .to_string(),
-                            env.value
-                                .as_ref()
-                                .unwrap_or_else(|| &default_str)
-                                .to_string(),
-                        )
-                    })
-                    .collect::<HashMap<String, String>>(),
-            );
-            env_map
+            envs.iter()
+                .map(|env| {
+                    (
+                        env.name.clone().unwrap_or_default(),
+                        EnvValue::from_env_var(env),
+                    )
+                })
+                .collect::<HashMap<String, EnvValue>>()
         } else {
             HashMap::new()
         };
@@ -160,6 +366,11 @@ impl Container {
         } else {
             vec![]
         };
+        let startup_probe = container.startup_probe.as_ref().and_then(Probe::from_probe);
+        let liveness_probe = container
+            .liveness_probe
+            .as_ref()
+            .and_then(Probe::from_probe);
         Container {
             image,
             args,
@@ -167,6 +378,8 @@ impl Container {
             env,
             resources,
             volume_mounts,
+            startup_probe,
+            liveness_probe,
         }
     }
 }
@@ -174,9 +387,39 @@ pub struct CloudRun {
     api: GcpCloudRun<auth::HttpsConnector>,
 }
 
+#[derive(Clone, Debug, Default)]
+pub struct RunListParam {
+    /// Max result per page
+    page_size: Option<i32>,
+
+    /// Next token
+    /// if there are additional pages, need to set the token for the request.
+    page_token: Option<String>,
+}
+
+impl RunListParam {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn page_size(&mut self, page_size: i32) -> &mut Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    pub fn page_token(&mut self, page_token: &str) -> &mut Self {
+        self.page_token = Some(page_token.to_string());
+        self
+    }
+}
+
 impl CloudRun {
     pub fn new(auth: &auth::GcpAuth) -> Result<CloudRun> {
-        let client = auth::new_client();
+        CloudRun::with_client(auth, auth::new_client())
+    }
+
+    /// Create with the HTTP client built by `auth::new_client_with`
+    pub fn with_client(auth: &auth::GcpAuth, client: auth::HttpClient) -> Result<CloudRun> {
         let hub = GcpCloudRun::new(client, auth.authenticator());
         Ok(CloudRun { api: hub })
     }
@@ -196,7 +439,7 @@ impl CloudRun {
                 | Error::BadRequest(_)
                 | Error::FieldClash(_)
                 | Error::JsonDecodeError(_, _) => {
-                    println!("{}", e);
+                    tracing::error!("{}", e);
                     Err(anyhow::anyhow!("{}", e))
                 }
             },
@@ -220,14 +463,56 @@ impl CloudRun {
             .locations_services_get(&service_name.name())
             .doit()
             .await;
-        println!("{:?}", resp);
         CloudRun::handle_error(resp, &CloudRun::response_to_service)
     }
 
+    fn response_operation_to_service(
+        resp: (Response<Body>, GoogleLongrunningOperation),
+    ) -> Result<service::Service> {
+        let metadata = resp
+            .1
+            .metadata
+            .ok_or_else(|| anyhow::anyhow!("operation has no metadata"))?;
+        let s: GoogleCloudRunV2Service = serde_json::from_value(serde_json::to_value(metadata)?)?;
+        service::Service::from_service(&s)
+    }
+
+    pub async fn services_create(
+        &self,
+        service_name: &service::RunServiceName,
+        service: &service::Service,
+    ) -> Result<service::Service> {
+        let service_id = service_name
+            .service_name()
+            .ok_or_else(|| anyhow::anyhow!("service name is required to create the service"))?;
+        let resp = self
+            .api
+            .projects()
+            .locations_services_create(service.to_service()?, &service_name.parent())
+            .service_id(service_id)
+            .doit()
+            .await;
+        CloudRun::handle_error(resp, &CloudRun::response_operation_to_service)
+    }
+
+    pub async fn services_update(
+        &self,
+        service_name: &service::RunServiceName,
+        service: &service::Service,
+    ) -> Result<service::Service> {
+        let resp = self
+            .api
+            .projects()
+            .locations_services_patch(service.to_service()?, &service_name.name())
+            .doit()
+            .await;
+        CloudRun::handle_error(resp, &CloudRun::response_operation_to_service)
+    }
+
     fn response_to_list_services(
         resp: (Response<Body>, GoogleCloudRunV2ListServicesResponse),
-    ) -> Result<Vec<service::Service>> {
-        Ok(resp
+    ) -> Result<(Vec<service::Service>, Option<String>)> {
+        let services = resp
             .1
             .services
             .as_ref()
@@ -237,21 +522,37 @@ impl CloudRun {
                     .map(|service| service::Service::from_service(service).unwrap())
                     .collect()
             })
-            .unwrap_or_else(|| vec![]))
+            .unwrap_or_else(|| vec![]);
+        Ok((services, resp.1.next_page_token.filter(|t| 0 < t.len())))
     }
 
+    /// List services in the location. All pages are fetched.
+    #[async_recursion]
     pub async fn services_list(
-        &self,
-        service_name: &service::RunServiceName,
+        &'async_recursion self,
+        service_name: &'async_recursion service::RunServiceName,
+        p: &'async_recursion RunListParam,
     ) -> Result<Vec<service::Service>> {
-        let resp = self
+        let mut list_api = self
             .api
             .projects()
-            .locations_services_list(&service_name.parent())
-            .doit()
-            .await;
-        println!("{:?}", resp);
-        CloudRun::handle_error(resp, &CloudRun::response_to_list_services)
+            .locations_services_list(&service_name.parent());
+        if let Some(page_size) = p.page_size {
+            list_api = list_api.page_size(page_size);
+        }
+        if let Some(token) = &p.page_token {
+            list_api = list_api.page_token(&token);
+        }
+        let resp = list_api.doit().await;
+        let (mut services, next_page_token) =
+            CloudRun::handle_error(resp, &CloudRun::response_to_list_services)?;
+        if let Some(token) = next_page_token {
+            let mut param = p.clone();
+            param.page_token(&token);
+            let additionals = self.services_list(service_name, &param).await?;
+            services.extend(additionals);
+        }
+        Ok(services)
     }
 
     fn response_to_job(resp: (Response<Body>, GoogleCloudRunV2Job)) -> Result<job::Job> {
@@ -265,12 +566,10 @@ impl CloudRun {
             .locations_jobs_get(&job_name.name())
             .doit()
             .await;
-        println!("{:?}", resp);
         CloudRun::handle_error(resp, &CloudRun::response_to_job)
     }
 
-    fn response_to_operation(resp: (Response<Body>, GoogleLongrunningOperation)) -> Result<()> {
-        println!("{:?}", &resp.1);
+    fn response_to_operation(_resp: (Response<Body>, GoogleLongrunningOperation)) -> Result<()> {
         Ok(())
     }
 
@@ -284,11 +583,10 @@ impl CloudRun {
         let resp = self
             .api
             .projects()
-            .locations_jobs_create(job.to_job(), &job_name.parent())
+            .locations_jobs_create(job.to_job()?, &job_name.parent())
             .job_id(job_name.job_name().unwrap())
             .doit()
             .await;
-        // println!("{:?}", resp);
         CloudRun::handle_error(resp, &CloudRun::response_operation_to_job)
     }
     pub async fn jobs_delete(&self, job_name: &job::RunJobName) -> Result<job::Job> {
@@ -298,26 +596,72 @@ impl CloudRun {
             .locations_jobs_delete(&job_name.name())
             .doit()
             .await;
-        // println!("{:?}", resp);
         CloudRun::handle_error(resp, &CloudRun::response_operation_to_job)
     }
-    pub async fn jobs_run(&self, job_name: &job::RunJobName) -> Result<job::Job> {
-        // TODO: accept override parameters?
-        let req = GoogleCloudRunV2RunJobRequest::default();
+    /// Run the job. `overrides` changes args, env or task count for this execution only.
+    pub async fn jobs_run(
+        &self,
+        job_name: &job::RunJobName,
+        overrides: Option<&job::RunJobOverrides>,
+    ) -> Result<job::Job> {
+        let mut req = GoogleCloudRunV2RunJobRequest::default();
+        req.overrides = overrides.map(|o| o.to_overrides());
         let resp = self
             .api
             .projects()
             .locations_jobs_run(req, &job_name.name())
             .doit()
             .await;
-        // println!("{:?}", resp);
         CloudRun::handle_error(resp, &CloudRun::response_operation_to_job)
     }
 
+    /// Run the job and wait for the execution to complete.
+    ///
+    /// The execution is polled until it is succeeded or failed. Check the result with
+    /// `Execution::is_succeeded`.
+    ///
+    /// # Arguments
+    ///
+    /// * `job_name` - job to run
+    /// * `overrides` - changes args, env or task count for this execution only
+    /// * `timeout` - returns error if the execution does not complete in this duration
+    pub async fn run_job_and_wait(
+        &self,
+        job_name: &job::RunJobName,
+        overrides: Option<&job::RunJobOverrides>,
+        timeout: Duration,
+    ) -> Result<execution::Execution> {
+        let mut req = GoogleCloudRunV2RunJobRequest::default();
+        req.overrides = overrides.map(|o| o.to_overrides());
+        let resp = self
+            .api
+            .projects()
+            .locations_jobs_run(req, &job_name.name())
+            .doit()
+            .await;
+        let started = CloudRun::handle_error(resp, &CloudRun::response_operation_to_execution)?;
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let exe = self.executions_get(&started.name).await?;
+            if exe.is_completed() {
+                return Ok(exe);
+            }
+            if deadline <= Instant::now() {
+                return Err(anyhow::anyhow!(
+                    "execution {} did not complete in {:?}",
+                    started.name.name(),
+                    timeout
+                ));
+            }
+            tokio::time::sleep(EXECUTION_POLL_INTERVAL).await;
+        }
+    }
+
     fn response_to_list_jobs(
         resp: (Response<Body>, GoogleCloudRunV2ListJobsResponse),
-    ) -> Result<Vec<job::Job>> {
-        Ok(resp
+    ) -> Result<(Vec<job::Job>, Option<String>)> {
+        let jobs = resp
             .1
             .jobs
             .as_ref()
@@ -326,18 +670,37 @@ impl CloudRun {
                     .map(|job| job::Job::from_job(job).unwrap())
                     .collect()
             })
-            .unwrap_or_else(|| vec![]))
+            .unwrap_or_else(|| vec![]);
+        Ok((jobs, resp.1.next_page_token.filter(|t| 0 < t.len())))
     }
 
-    pub async fn jobs_list(&self, job_name: &job::RunJobName) -> Result<Vec<job::Job>> {
-        let resp = self
+    /// List jobs in the location. All pages are fetched.
+    #[async_recursion]
+    pub async fn jobs_list(
+        &'async_recursion self,
+        job_name: &'async_recursion job::RunJobName,
+        p: &'async_recursion RunListParam,
+    ) -> Result<Vec<job::Job>> {
+        let mut list_api = self
             .api
             .projects()
-            .locations_jobs_list(&job_name.parent())
-            .doit()
-            .await;
-        println!("{:?}", resp);
-        CloudRun::handle_error(resp, &CloudRun::response_to_list_jobs)
+            .locations_jobs_list(&job_name.parent());
+        if let Some(page_size) = p.page_size {
+            list_api = list_api.page_size(page_size);
+        }
+        if let Some(token) = &p.page_token {
+            list_api = list_api.page_token(&token);
+        }
+        let resp = list_api.doit().await;
+        let (mut jobs, next_page_token) =
+            CloudRun::handle_error(resp, &CloudRun::response_to_list_jobs)?;
+        if let Some(token) = next_page_token {
+            let mut param = p.clone();
+            param.page_token(&token);
+            let additionals = self.jobs_list(job_name, &param).await?;
+            jobs.extend(additionals);
+        }
+        Ok(jobs)
     }
 
     fn response_to_execution(
@@ -355,7 +718,6 @@ impl CloudRun {
             .locations_jobs_executions_get(&execution_name.name())
             .doit()
             .await;
-        println!("{:?}", resp);
         CloudRun::handle_error(resp, &CloudRun::response_to_execution)
     }
     pub async fn executions_delete(
@@ -368,14 +730,42 @@ impl CloudRun {
             .locations_jobs_executions_delete(&execution_name.name())
             .doit()
             .await;
-        println!("{:?}", resp);
         CloudRun::handle_error(resp, &CloudRun::response_to_operation)
     }
 
+    fn response_operation_to_execution(
+        resp: (Response<Body>, GoogleLongrunningOperation),
+    ) -> Result<execution::Execution> {
+        let metadata = resp
+            .1
+            .metadata
+            .ok_or_else(|| anyhow::anyhow!("operation has no metadata"))?;
+        let exe: GoogleCloudRunV2Execution =
+            serde_json::from_value(serde_json::to_value(metadata)?)?;
+        execution::Execution::from_execution(&exe)
+    }
+
+    /// Cancel the running execution. The execution is kept in the history.
+    pub async fn executions_cancel(
+        &self,
+        execution_name: &execution::RunExecutionName,
+    ) -> Result<execution::Execution> {
+        let resp = self
+            .api
+            .projects()
+            .locations_jobs_executions_cancel(
+                GoogleCloudRunV2CancelExecutionRequest::default(),
+                &execution_name.name(),
+            )
+            .doit()
+            .await;
+        CloudRun::handle_error(resp, &CloudRun::response_operation_to_execution)
+    }
+
     fn response_to_list_executions(
         resp: (Response<Body>, GoogleCloudRunV2ListExecutionsResponse),
-    ) -> Result<Vec<execution::Execution>> {
-        Ok(resp
+    ) -> Result<(Vec<execution::Execution>, Option<String>)> {
+        let executions = resp
             .1
             .executions
             .as_ref()
@@ -385,20 +775,104 @@ impl CloudRun {
                     .map(|exe| execution::Execution::from_execution(exe).unwrap())
                     .collect()
             })
-            .unwrap_or_else(|| vec![]))
+            .unwrap_or_else(|| vec![]);
+        Ok((executions, resp.1.next_page_token.filter(|t| 0 < t.len())))
     }
 
+    /// List executions of the job. All pages are fetched.
+    #[async_recursion]
     pub async fn executions_list(
-        &self,
-        execution_name: &execution::RunExecutionName,
+        &'async_recursion self,
+        execution_name: &'async_recursion execution::RunExecutionName,
+        p: &'async_recursion RunListParam,
     ) -> Result<Vec<execution::Execution>> {
-        let resp = self
+        let mut list_api = self
             .api
             .projects()
-            .locations_jobs_executions_list(&execution_name.parent())
-            .doit()
-            .await;
-        println!("{:?}", resp);
-        CloudRun::handle_error(resp, &CloudRun::response_to_list_executions)
+            .locations_jobs_executions_list(&execution_name.parent());
+        if let Some(page_size) = p.page_size {
+            list_api = list_api.page_size(page_size);
+        }
+        if let Some(token) = &p.page_token {
+            list_api = list_api.page_token(&token);
+        }
+        let resp = list_api.doit().await;
+        let (mut executions, next_page_token) =
+            CloudRun::handle_error(resp, &CloudRun::response_to_list_executions)?;
+        if let Some(token) = next_page_token {
+            let mut param = p.clone();
+            param.page_token(&token);
+            let additionals = self.executions_list(execution_name, &param).await?;
+            executions.extend(additionals);
+        }
+        Ok(executions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn limits(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[rstest]
+    #[case("1")]
+    #[case("0.5")]
+    #[case("1000m")]
+    fn valid_cpu(#[case] cpu: &str) {
+        assert!(Resources::from_map(&limits(&[("cpu", cpu)])).is_ok());
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("1 core")]
+    #[case("0.5m")]
+    #[case("1Gi")]
+    fn invalid_cpu(#[case] cpu: &str) {
+        assert!(Resources::from_map(&limits(&[("cpu", cpu)])).is_err());
+    }
+
+    #[rstest]
+    #[case("512Mi")]
+    #[case("2Gi")]
+    #[case("0.5Gi")]
+    #[case("1Ti")]
+    #[case("1T")]
+    #[case("1000000")]
+    fn valid_memory(#[case] memory: &str) {
+        assert!(Resources::from_map(&limits(&[("memory", memory)])).is_ok());
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("2GB")]
+    #[case("Gi")]
+    #[case(".5Gi")]
+    #[case("1Pi")]
+    fn invalid_memory(#[case] memory: &str) {
+        assert!(Resources::from_map(&limits(&[("memory", memory)])).is_err());
+    }
+
+    #[test]
+    fn accepted_keys_are_passed_to_limits() {
+        let resources =
+            Resources::from_map(&limits(&[("cpu", "1"), ("nvidia.com/gpu", "1")])).unwrap();
+        assert_eq!(
+            resources.to_limits(),
+            limits(&[("cpu", "1"), ("nvidia.com/gpu", "1")])
+        );
+    }
+
+    #[test]
+    fn unknown_key_is_error() {
+        let err = Resources::from_map(&limits(&[("cpuu", "1")])).unwrap_err();
+        assert!(err.to_string().contains("cpuu"));
+        assert!(err.to_string().contains("nvidia.com/gpu"));
     }
 }
@@ -181,9 +181,39 @@ pub struct Execution {
     timeout: Option<i64>,
     service_account: String,
     conditions: Vec<Condition>,
+    /// Number of tasks of the execution
+    pub task_count: i32,
+    /// Number of tasks which reached the Succeeded state
+    pub succeeded_count: i32,
+    /// Number of tasks which reached the Failed state
+    pub failed_count: i32,
+    /// Number of running tasks
+    pub running_count: i32,
+    /// Number of tasks which reached the Cancelled state
+    pub cancelled_count: i32,
 }
 
 impl Execution {
+    fn completed_state(&self) -> Option<&ConditionState> {
+        self.conditions
+            .iter()
+            .find(|c| matches!(c.type_, ConditionType::Completed))
+            .map(|c| &c.state)
+    }
+
+    /// Returns true if the execution finished with success or failure.
+    pub fn is_completed(&self) -> bool {
+        matches!(
+            self.completed_state(),
+            Some(ConditionState::Succeeded) | Some(ConditionState::Failed)
+        )
+    }
+
+    /// Returns true if the execution finished successfully.
+    pub fn is_succeeded(&self) -> bool {
+        matches!(self.completed_state(), Some(ConditionState::Succeeded))
+    }
+
     pub fn from_execution(exe: &GoogleCloudRunV2Execution) -> Result<Self> {
         if let Some(template) = exe.template.as_ref() {
             let name = exe
@@ -223,6 +253,11 @@ impl Execution {
                 timeout,
                 service_account,
                 conditions,
+                task_count: exe.task_count.unwrap_or(0),
+                succeeded_count: exe.succeeded_count.unwrap_or(0),
+                failed_count: exe.failed_count.unwrap_or(0),
+                running_count: exe.running_count.unwrap_or(0),
+                cancelled_count: exe.cancelled_count.unwrap_or(0),
             })
         } else {
             Err(anyhow::anyhow!("template does not exist"))
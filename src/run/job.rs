@@ -1,7 +1,11 @@
 use chrono::Duration;
 use chrono::{DateTime, Utc};
 use cloud_run::{
-    api::{GoogleCloudRunV2ExecutionTemplate, GoogleCloudRunV2Job, GoogleCloudRunV2TaskTemplate},
+    api::{
+        GoogleCloudRunV2ContainerOverride, GoogleCloudRunV2EnvVar,
+        GoogleCloudRunV2ExecutionTemplate, GoogleCloudRunV2Job, GoogleCloudRunV2Overrides,
+        GoogleCloudRunV2TaskTemplate,
+    },
     Error, Result as GcpResult,
 };
 use google_run2 as cloud_run;
@@ -85,6 +89,47 @@ impl RunJobName {
     }
 }
 
+/// Parameters overridden for a single job execution.
+///
+/// `args` and `env` are applied to the container named `container_name`. If the job has only one
+/// container, `container_name` can be omitted.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RunJobOverrides {
+    pub args: Vec<String>,
+    pub env: HashMap<String, String>,
+    pub task_count: Option<i32>,
+    pub container_name: Option<String>,
+}
+
+impl RunJobOverrides {
+    pub fn to_overrides(&self) -> GoogleCloudRunV2Overrides {
+        let mut overrides = GoogleCloudRunV2Overrides::default();
+        // only task_count may be overridden, then the containers are left as they are
+        if 0 < self.args.len() || 0 < self.env.len() {
+            let mut container = GoogleCloudRunV2ContainerOverride::default();
+            container.name = self.container_name.clone();
+            if 0 < self.args.len() {
+                container.args = Some(self.args.clone());
+            }
+            if 0 < self.env.len() {
+                container.env = Some(
+                    self.env
+                        .iter()
+                        .map(|(k, v)| GoogleCloudRunV2EnvVar {
+                            name: Some(k.clone()),
+                            value: Some(v.clone()),
+                            value_source: None,
+                        })
+                        .collect(),
+                );
+            }
+            overrides.container_overrides = Some(vec![container]);
+        }
+        overrides.task_count = self.task_count;
+        overrides
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Job {
     pub name: RunJobName,
@@ -101,7 +146,7 @@ pub struct Job {
 }
 
 impl Job {
-    pub fn to_job(&self) -> GoogleCloudRunV2Job {
+    pub fn to_job(&self) -> Result<GoogleCloudRunV2Job> {
         let mut job = GoogleCloudRunV2Job::default();
         job.labels = Some(self.labels.clone());
         let mut template = GoogleCloudRunV2ExecutionTemplate::default();
@@ -109,13 +154,18 @@ impl Job {
         task_template.service_account = Some(self.service_account.clone());
         task_template.timeout = self.timeout.map(|t| Duration::seconds(t));
         task_template.max_retries = Some(self.max_retries);
-        task_template.containers = Some(self.containers.iter().map(|c| c.to_container()).collect());
+        task_template.containers = Some(
+            self.containers
+                .iter()
+                .map(|c| c.to_container())
+                .collect::<Result<Vec<_>>>()?,
+        );
         task_template.volumes = Some(self.volumes.iter().map(|v| v.to_volume()).collect());
         template.template = Some(task_template);
         template.parallelism = self.parallelism.clone();
         template.task_count = self.task_count.clone();
         job.template = Some(template);
-        job
+        Ok(job)
     }
     pub fn from_job(job: &GoogleCloudRunV2Job) -> Result<Self> {
         if let Some(template) = job.template.as_ref() {
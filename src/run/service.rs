@@ -2,7 +2,7 @@ use cloud_run::{
     api::{
         GoogleCloudRunV2CloudSqlInstance, GoogleCloudRunV2Container, GoogleCloudRunV2EnvVar,
         GoogleCloudRunV2RevisionScaling, GoogleCloudRunV2RevisionTemplate, GoogleCloudRunV2Service,
-        GoogleCloudRunV2Volume, GoogleCloudRunV2VolumeMount,
+        GoogleCloudRunV2TrafficTarget, GoogleCloudRunV2Volume, GoogleCloudRunV2VolumeMount,
     },
     Error, Result as GcpResult,
 };
@@ -30,6 +30,10 @@ impl RunServiceName {
         }
     }
 
+    pub fn service_name(&self) -> Option<&String> {
+        self.service_name.as_ref()
+    }
+
     pub fn from_name(name: &str) -> Result<Self> {
         let re: Regex = Regex::new(
             r"projects/(?P<project>.+)/locations/(?P<location>.+)/services/(?P<service_name>.+)",
@@ -75,6 +79,46 @@ impl RunServiceName {
     }
 }
 
+/// Traffic allocation to a revision
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrafficTarget {
+    /// Revision name. The latest ready revision is used if omitted.
+    pub revision: Option<String>,
+    /// Percentage of the traffic
+    pub percent: i32,
+    /// Tag to access the revision with the dedicated URL
+    pub tag: Option<String>,
+}
+
+impl TrafficTarget {
+    const TYPE_LATEST: &'static str = "TRAFFIC_TARGET_ALLOCATION_TYPE_LATEST";
+    const TYPE_REVISION: &'static str = "TRAFFIC_TARGET_ALLOCATION_TYPE_REVISION";
+
+    fn to_traffic_target(&self) -> GoogleCloudRunV2TrafficTarget {
+        let mut target = GoogleCloudRunV2TrafficTarget::default();
+        target.type_ = Some(
+            if self.revision.is_some() {
+                TrafficTarget::TYPE_REVISION
+            } else {
+                TrafficTarget::TYPE_LATEST
+            }
+            .to_string(),
+        );
+        target.revision = self.revision.clone();
+        target.percent = Some(self.percent);
+        target.tag = self.tag.clone();
+        target
+    }
+
+    fn from_traffic_target(target: &GoogleCloudRunV2TrafficTarget) -> Self {
+        TrafficTarget {
+            revision: target.revision.clone(),
+            percent: target.percent.unwrap_or_default(),
+            tag: target.tag.clone(),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Service {
     name: RunServiceName,
@@ -85,10 +129,13 @@ pub struct Service {
     containers: Vec<super::Container>,
     volumes: Vec<super::Volume>,
     uri: String,
+    /// Traffic split between the revisions. All traffic goes to the latest revision if empty.
+    #[serde(default)]
+    traffic: Vec<TrafficTarget>,
 }
 
 impl Service {
-    fn to_service(&self) -> GoogleCloudRunV2Service {
+    pub fn to_service(&self) -> Result<GoogleCloudRunV2Service> {
         let mut service = GoogleCloudRunV2Service::default();
         let mut template = GoogleCloudRunV2RevisionTemplate::default();
         let mut scaling = GoogleCloudRunV2RevisionScaling::default();
@@ -97,7 +144,25 @@ impl Service {
         template.scaling = Some(scaling);
         template.timeout = self.timeout.map(|t| Duration::seconds(t));
         template.service_account = Some(self.service_account.clone());
-        service
+        template.containers = Some(
+            self.containers
+                .iter()
+                .map(|c| c.to_container())
+                .collect::<Result<Vec<_>>>()?,
+        );
+        template.volumes = Some(self.volumes.iter().map(|v| v.to_volume()).collect());
+        service.template = Some(template);
+        if 0 < self.traffic.len() {
+            let total: i32 = self.traffic.iter().map(|t| t.percent).sum();
+            if total != 100 {
+                return Err(anyhow::anyhow!(
+                    "traffic percent must sum to 100 but {}",
+                    total
+                ));
+            }
+            service.traffic = Some(self.traffic.iter().map(|t| t.to_traffic_target()).collect());
+        }
+        Ok(service)
     }
     pub fn from_service(service: &GoogleCloudRunV2Service) -> Result<Self> {
         if let Some(template) = service.template.as_ref() {
@@ -126,7 +191,23 @@ impl Service {
                 .iter()
                 .map(|v| super::Volume::from_volume(&v))
                 .collect();
-            let uri = service.uri.as_ref().unwrap().to_string();
+            // uri is not assigned until the service is ready.
+            let uri = service
+                .uri
+                .as_ref()
+                .map(|u| u.to_string())
+                .unwrap_or_default();
+
+            let traffic = service
+                .traffic
+                .as_ref()
+                .map(|targets| {
+                    targets
+                        .iter()
+                        .map(|t| TrafficTarget::from_traffic_target(t))
+                        .collect()
+                })
+                .unwrap_or_default();
 
             Ok(Service {
                 name,
@@ -137,6 +218,7 @@ impl Service {
                 containers,
                 volumes,
                 uri,
+                traffic,
             })
         } else {
             Err(anyhow::anyhow!("template does not exist"))
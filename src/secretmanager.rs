@@ -38,7 +38,11 @@ pub struct SecretManager {
 
 impl SecretManager {
     pub fn new(auth: &auth::GcpAuth) -> Result<SecretManager> {
-        let client = auth::new_client();
+        SecretManager::with_client(auth, auth::new_client())
+    }
+
+    /// Create with the HTTP client built by `auth::new_client_with`
+    pub fn with_client(auth: &auth::GcpAuth, client: auth::HttpClient) -> Result<SecretManager> {
         let hub = GcpSecretManager::new(client, auth.authenticator());
         Ok(SecretManager { api: hub })
     }
@@ -58,7 +62,7 @@ impl SecretManager {
                 | Error::BadRequest(_)
                 | Error::FieldClash(_)
                 | Error::JsonDecodeError(_, _) => {
-                    println!("{}", e);
+                    tracing::error!("{}", e);
                     Err(anyhow::anyhow!("{}", e))
                 }
             },
@@ -73,11 +77,10 @@ impl SecretManager {
             .secrets_versions_access(&p.to_resource())
             .doit()
             .await;
-        println!("{:?}", res);
         match res {
             Err(e) => match e {
                 Error::BadRequest(_) => {
-                    eprintln!("{}", e);
+                    tracing::error!("{}", e);
                     Err(anyhow::anyhow!("{}", e))
                 }
                 Error::HttpError(_)
@@ -89,7 +92,7 @@ impl SecretManager {
                 | Error::Failure(_)
                 | Error::FieldClash(_)
                 | Error::JsonDecodeError(_, _) => {
-                    eprintln!("{}", e);
+                    tracing::error!("{}", e);
                     Err(anyhow::anyhow!("{}", e))
                 }
             },
@@ -1,6 +1,9 @@
 use crate::auth;
 use google_sheets4 as sheets4;
-use sheets4::api::ValueRange;
+use sheets4::api::{
+    AppendValuesResponse, BatchUpdateSpreadsheetRequest, BatchUpdateSpreadsheetResponse, Request,
+    UpdateValuesResponse, ValueRange,
+};
 use sheets4::Sheets;
 
 use anyhow;
@@ -55,9 +58,45 @@ impl ValueRenderOption {
     }
 }
 
+/// A cell value to be written to the sheet.
+#[derive(Clone, Debug)]
+pub enum SheetValue {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Empty,
+}
+
+impl SheetValue {
+    fn to_value(&self) -> serde_json::Value {
+        match self {
+            SheetValue::String(s) => serde_json::Value::String(s.clone()),
+            SheetValue::Number(n) => serde_json::json!(n),
+            SheetValue::Bool(b) => serde_json::Value::Bool(*b),
+            SheetValue::Empty => serde_json::Value::String(String::from("")),
+        }
+    }
+}
+
+fn to_value_range(range: &str, values: Vec<Vec<SheetValue>>) -> ValueRange {
+    let mut req = ValueRange::default();
+    req.range = Some(range.to_string());
+    req.values = Some(
+        values
+            .iter()
+            .map(|row| row.iter().map(|v| v.to_value()).collect())
+            .collect(),
+    );
+    req
+}
+
 impl SpreadSheet {
     pub fn new(auth: &auth::GcpAuth) -> Result<SpreadSheet> {
-        let client = auth::new_client();
+        SpreadSheet::with_client(auth, auth::new_client())
+    }
+
+    /// Create with the HTTP client built by `auth::new_client_with`
+    pub fn with_client(auth: &auth::GcpAuth, client: auth::HttpClient) -> Result<SpreadSheet> {
         let hub = Sheets::new(client, auth.authenticator());
         Ok(SpreadSheet { api: hub })
     }
@@ -77,4 +116,81 @@ impl SpreadSheet {
 
         Ok(result.1)
     }
+
+    /// Append rows after the last row of the table in the range.
+    ///
+    /// # Arguments
+    ///
+    /// * `spreadsheet_id` - target spreadsheet id
+    /// * `range` - A1 notation of the table to append, e.g. `Sheet1!A1`
+    /// * `values` - rows to be appended
+    pub async fn append_rows(
+        &self,
+        spreadsheet_id: &str,
+        range: &str,
+        values: Vec<Vec<SheetValue>>,
+    ) -> Result<AppendValuesResponse> {
+        // https://developers.google.com/sheets/api/reference/rest/v4/spreadsheets.values/append
+        let req = to_value_range(range, values);
+        let result = self
+            .api
+            .spreadsheets()
+            .values_append(req, spreadsheet_id, range)
+            .value_input_option("USER_ENTERED")
+            .insert_data_option("INSERT_ROWS")
+            .doit()
+            .await?;
+
+        Ok(result.1)
+    }
+
+    /// Overwrite values in the range.
+    ///
+    /// # Arguments
+    ///
+    /// * `spreadsheet_id` - target spreadsheet id
+    /// * `range` - A1 notation of the range to update
+    /// * `values` - rows to be written
+    pub async fn update_range(
+        &self,
+        spreadsheet_id: &str,
+        range: &str,
+        values: Vec<Vec<SheetValue>>,
+    ) -> Result<UpdateValuesResponse> {
+        // https://developers.google.com/sheets/api/reference/rest/v4/spreadsheets.values/update
+        let req = to_value_range(range, values);
+        let result = self
+            .api
+            .spreadsheets()
+            .values_update(req, spreadsheet_id, range)
+            .value_input_option("USER_ENTERED")
+            .doit()
+            .await?;
+
+        Ok(result.1)
+    }
+
+    /// Apply formatting or structural changes to the spreadsheet.
+    ///
+    /// # Arguments
+    ///
+    /// * `spreadsheet_id` - target spreadsheet id
+    /// * `requests` - update requests. They are applied in the given order.
+    pub async fn batch_update(
+        &self,
+        spreadsheet_id: &str,
+        requests: Vec<Request>,
+    ) -> Result<BatchUpdateSpreadsheetResponse> {
+        // https://developers.google.com/sheets/api/reference/rest/v4/spreadsheets/batchUpdate
+        let mut req = BatchUpdateSpreadsheetRequest::default();
+        req.requests = Some(requests);
+        let result = self
+            .api
+            .spreadsheets()
+            .batch_update(req, spreadsheet_id)
+            .doit()
+            .await?;
+
+        Ok(result.1)
+    }
 }